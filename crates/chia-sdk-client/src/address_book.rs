@@ -0,0 +1,351 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    net::SocketAddr,
+    num::ParseIntError,
+    path::Path,
+};
+
+use crate::ClientError;
+
+/// The maximum number of addresses kept in an address book.
+/// Once it's full, the least promising addresses are evicted to make room.
+pub const MAX_ADDRESSES: usize = 10_000;
+
+/// After this many consecutive failed connection attempts, an address is no longer suggested
+/// until [`FAILURE_RETRY_INTERVAL`] has passed.
+pub const MAX_CONNECTION_FAILURES: u32 = 3;
+
+/// How many seconds after its last failure an address that has failed too many times is suggested
+/// again, so that peers which were only briefly unreachable aren't forgotten for good.
+pub const FAILURE_RETRY_INTERVAL: u64 = 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressEntry {
+    /// The unix timestamp at which the address was last discovered or connected to.
+    pub last_seen: u64,
+    /// The unix timestamp of the last successful connection to the address.
+    pub last_success: Option<u64>,
+    /// The number of failed connection attempts since the last success.
+    pub failures: u32,
+    /// The unix timestamp of the last failed connection attempt.
+    pub last_failure: Option<u64>,
+}
+
+/// Remembers the addresses of peers across runs, along with how reliable they've been.
+///
+/// The file format has one entry per line, made up of the socket address, the last seen
+/// timestamp, the last success timestamp (or `-`), the number of failures, and the last failure
+/// timestamp (or `-`). The last column may be omitted, for files written by older versions:
+///
+/// ```text
+/// 203.0.113.5:8444 1700000000 1700000000 0 -
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct AddressBook {
+    entries: HashMap<SocketAddr, AddressEntry>,
+}
+
+impl AddressBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads an address book from a file.
+    /// If the file doesn't exist yet, an empty address book is returned.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ClientError> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::new()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Writes the address book to a file, replacing it atomically.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ClientError> {
+        let path = path.as_ref();
+        let temp_path = path.with_extension("tmp");
+
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(self.to_string().as_bytes())?;
+        file.sync_all()?;
+        fs::rename(temp_path, path)?;
+
+        Ok(())
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, ClientError> {
+        let mut address_book = Self::new();
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (addr, entry) =
+                parse_entry(line).ok_or(ClientError::InvalidAddressBook(index + 1))?;
+
+            address_book.entries.insert(addr, entry);
+        }
+
+        Ok(address_book)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, addr: &SocketAddr) -> Option<&AddressEntry> {
+        self.entries.get(addr)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&SocketAddr, &AddressEntry)> {
+        self.entries.iter()
+    }
+
+    /// Records that the address was discovered at the given time.
+    pub fn add(&mut self, addr: SocketAddr, timestamp: u64) {
+        if let Some(entry) = self.entries.get_mut(&addr) {
+            entry.last_seen = entry.last_seen.max(timestamp);
+            return;
+        }
+
+        if self.entries.len() >= MAX_ADDRESSES {
+            self.evict();
+        }
+
+        self.entries.insert(
+            addr,
+            AddressEntry {
+                last_seen: timestamp,
+                last_success: None,
+                failures: 0,
+                last_failure: None,
+            },
+        );
+    }
+
+    pub fn mark_success(&mut self, addr: SocketAddr, timestamp: u64) {
+        self.add(addr, timestamp);
+
+        if let Some(entry) = self.entries.get_mut(&addr) {
+            entry.last_success = Some(timestamp);
+            entry.failures = 0;
+        }
+    }
+
+    pub fn mark_failure(&mut self, addr: SocketAddr, timestamp: u64) {
+        if let Some(entry) = self.entries.get_mut(&addr) {
+            entry.failures = entry.failures.saturating_add(1);
+            entry.last_failure = Some(timestamp);
+        }
+    }
+
+    pub fn remove(&mut self, addr: &SocketAddr) -> Option<AddressEntry> {
+        self.entries.remove(addr)
+    }
+
+    /// The addresses worth connecting to at the given time, with the most recently successful ones
+    /// first. Addresses that have failed too many times in a row are skipped, unless they haven't
+    /// been tried for [`FAILURE_RETRY_INTERVAL`] seconds.
+    pub fn best(&self, timestamp: u64) -> Vec<SocketAddr> {
+        let mut addrs: Vec<(&SocketAddr, &AddressEntry)> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| {
+                entry.failures < MAX_CONNECTION_FAILURES
+                    || entry.last_failure.map_or(true, |last_failure| {
+                        timestamp >= last_failure.saturating_add(FAILURE_RETRY_INTERVAL)
+                    })
+            })
+            .collect();
+
+        addrs.sort_by_key(|(_, entry)| std::cmp::Reverse(rank(entry)));
+        addrs.into_iter().map(|(&addr, _)| addr).collect()
+    }
+
+    fn evict(&mut self) {
+        let worst = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| rank(entry))
+            .map(|(&addr, _)| addr);
+
+        if let Some(addr) = worst {
+            self.entries.remove(&addr);
+        }
+    }
+}
+
+impl std::fmt::Display for AddressBook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (addr, entry) in &self.entries {
+            writeln!(
+                f,
+                "{addr} {} {} {} {}",
+                entry.last_seen,
+                format_timestamp(entry.last_success),
+                entry.failures,
+                format_timestamp(entry.last_failure)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn rank(entry: &AddressEntry) -> (Option<u64>, std::cmp::Reverse<u32>, u64) {
+    (
+        entry.last_success,
+        std::cmp::Reverse(entry.failures),
+        entry.last_seen,
+    )
+}
+
+fn format_timestamp(timestamp: Option<u64>) -> String {
+    timestamp.map_or_else(|| "-".to_string(), |timestamp| timestamp.to_string())
+}
+
+fn parse_timestamp(part: &str) -> Result<Option<u64>, ParseIntError> {
+    match part {
+        "-" => Ok(None),
+        timestamp => timestamp.parse().map(Some),
+    }
+}
+
+fn parse_entry(line: &str) -> Option<(SocketAddr, AddressEntry)> {
+    let mut parts = line.split_whitespace();
+
+    let addr = parts.next()?.parse().ok()?;
+    let last_seen = parts.next()?.parse().ok()?;
+    let last_success = parse_timestamp(parts.next()?).ok()?;
+    let failures = parts.next()?.parse().ok()?;
+    let last_failure = match parts.next() {
+        Some(part) => parse_timestamp(part).ok()?,
+        None => None,
+    };
+
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some((
+        addr,
+        AddressEntry {
+            last_seen,
+            last_success,
+            failures,
+            last_failure,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn test_best_addresses() {
+        let mut address_book = AddressBook::new();
+
+        address_book.add(addr(1), 100);
+        address_book.mark_success(addr(2), 50);
+        address_book.mark_success(addr(3), 60);
+        address_book.add(addr(4), 200);
+
+        for _ in 0..MAX_CONNECTION_FAILURES {
+            address_book.mark_failure(addr(4), 200);
+        }
+
+        assert_eq!(address_book.best(200), vec![addr(3), addr(2), addr(1)]);
+
+        address_book.mark_success(addr(4), 300);
+        assert_eq!(address_book.best(300)[0], addr(4));
+    }
+
+    #[test]
+    fn test_failures_reset_and_decay() {
+        let mut address_book = AddressBook::new();
+        address_book.add(addr(1), 100);
+
+        for _ in 0..MAX_CONNECTION_FAILURES - 1 {
+            address_book.mark_failure(addr(1), 100);
+        }
+
+        // A success in between failures means they're no longer consecutive.
+        address_book.mark_success(addr(1), 200);
+        assert_eq!(address_book.get(&addr(1)).unwrap().failures, 0);
+
+        address_book.mark_failure(addr(1), 300);
+        assert_eq!(address_book.best(300), vec![addr(1)]);
+
+        for _ in 1..MAX_CONNECTION_FAILURES {
+            address_book.mark_failure(addr(1), 400);
+        }
+        assert!(address_book.best(400).is_empty());
+
+        // The address is tried again once enough time has passed since its last failure.
+        assert!(address_book
+            .best(400 + FAILURE_RETRY_INTERVAL - 1)
+            .is_empty());
+        assert_eq!(
+            address_book.best(400 + FAILURE_RETRY_INTERVAL),
+            vec![addr(1)]
+        );
+    }
+
+    #[test]
+    fn test_save_and_load() -> Result<(), ClientError> {
+        let mut address_book = AddressBook::new();
+        address_book.add(addr(1), 100);
+        address_book.mark_success(addr(2), 200);
+        address_book.mark_failure(addr(1), 150);
+
+        let path =
+            std::env::temp_dir().join(format!("chia-address-book-{}.txt", std::process::id()));
+        address_book.save(&path)?;
+
+        let loaded = AddressBook::load(&path)?;
+        fs::remove_file(&path)?;
+
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(
+            loaded.get(&addr(1)),
+            Some(&AddressEntry {
+                last_seen: 100,
+                last_success: None,
+                failures: 1,
+                last_failure: Some(150),
+            })
+        );
+        assert_eq!(
+            loaded.get(&addr(2)),
+            Some(&AddressEntry {
+                last_seen: 200,
+                last_success: Some(200),
+                failures: 0,
+                last_failure: None,
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_entry() {
+        assert!(matches!(
+            AddressBook::parse("# comment\n127.0.0.1:1 100 - 0\n127.0.0.1:2 100\n"),
+            Err(ClientError::InvalidAddressBook(3))
+        ));
+    }
+}
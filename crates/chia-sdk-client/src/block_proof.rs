@@ -0,0 +1,420 @@
+use std::collections::HashSet;
+
+use chia_consensus::merkle_tree::validate_merkle_proof;
+use chia_protocol::{
+    Bytes, Bytes32, Coin, FoliageTransactionBlock, HeaderBlock, RejectAdditionsRequest,
+    RejectRemovalsRequest, RespondAdditions, RespondRemovals,
+};
+use chia_sdk_types::{additions_root, hash_coin_ids, removals_root};
+
+use crate::{ClientError, Peer};
+
+type AdditionsProof = (Bytes32, Bytes, Option<Bytes>);
+type RemovalsProof = (Bytes32, Bytes);
+
+/// Checks that the coins in the response were created in the block, by verifying them against
+/// its additions root. Each of the requested puzzle hashes must be answered exactly once, and those
+/// with no coins must be proven to be excluded from the block. If no puzzle hashes were requested,
+/// the response must contain every coin created in the block.
+pub fn verify_additions(
+    header_block: &HeaderBlock,
+    puzzle_hashes: Option<&[Bytes32]>,
+    response: &RespondAdditions,
+) -> Result<(), ClientError> {
+    let height = header_block.height();
+    let transaction_block = transaction_block(header_block, response.header_hash)?;
+
+    if response.height != height
+        || !validate_additions(
+            transaction_block.additions_root,
+            puzzle_hashes,
+            &response.coins,
+            response.proofs.as_deref(),
+        )
+    {
+        return Err(ClientError::InvalidAdditions(height));
+    }
+
+    Ok(())
+}
+
+/// Checks that the coins in the response were spent in the block, by verifying them against
+/// its removals root. Each of the requested coin ids must be answered exactly once, and those with
+/// no coin must be proven to be excluded from the block. If no coin ids were requested, the
+/// response must contain every coin spent in the block.
+pub fn verify_removals(
+    header_block: &HeaderBlock,
+    coin_ids: Option<&[Bytes32]>,
+    response: &RespondRemovals,
+) -> Result<(), ClientError> {
+    let height = header_block.height();
+    let transaction_block = transaction_block(header_block, response.header_hash)?;
+
+    if response.height != height
+        || !validate_removals(
+            transaction_block.removals_root,
+            coin_ids,
+            &response.coins,
+            response.proofs.as_deref(),
+        )
+    {
+        return Err(ClientError::InvalidRemovals(height));
+    }
+
+    Ok(())
+}
+
+impl Peer {
+    /// Requests the coins created in the block, and verifies them against the header block.
+    /// The header block should come from a trusted source, or be validated separately.
+    pub async fn request_verified_additions(
+        &self,
+        header_block: &HeaderBlock,
+        puzzle_hashes: Option<Vec<Bytes32>>,
+    ) -> Result<Result<RespondAdditions, RejectAdditionsRequest>, ClientError> {
+        let response = self
+            .request_additions(
+                header_block.height(),
+                Some(header_block.header_hash()),
+                puzzle_hashes.clone(),
+            )
+            .await?;
+
+        if let Ok(additions) = &response {
+            verify_additions(header_block, puzzle_hashes.as_deref(), additions)?;
+        }
+
+        Ok(response)
+    }
+
+    /// Requests the coins spent in the block, and verifies them against the header block.
+    /// The header block should come from a trusted source, or be validated separately.
+    pub async fn request_verified_removals(
+        &self,
+        header_block: &HeaderBlock,
+        coin_ids: Option<Vec<Bytes32>>,
+    ) -> Result<Result<RespondRemovals, RejectRemovalsRequest>, ClientError> {
+        let response = self
+            .request_removals(
+                header_block.height(),
+                header_block.header_hash(),
+                coin_ids.clone(),
+            )
+            .await?;
+
+        if let Ok(removals) = &response {
+            verify_removals(header_block, coin_ids.as_deref(), removals)?;
+        }
+
+        Ok(response)
+    }
+}
+
+fn transaction_block(
+    header_block: &HeaderBlock,
+    header_hash: Bytes32,
+) -> Result<&FoliageTransactionBlock, ClientError> {
+    let expected = header_block.header_hash();
+
+    if header_hash != expected {
+        return Err(ClientError::WrongBlock(expected, header_hash));
+    }
+
+    header_block
+        .foliage_transaction_block
+        .as_ref()
+        .ok_or(ClientError::NotTransactionBlock(header_block.height()))
+}
+
+fn validate_additions(
+    root: Bytes32,
+    requested: Option<&[Bytes32]>,
+    coins: &[(Bytes32, Vec<Coin>)],
+    proofs: Option<&[AdditionsProof]>,
+) -> bool {
+    if coins
+        .iter()
+        .any(|(puzzle_hash, coins)| coins.iter().any(|coin| coin.puzzle_hash != *puzzle_hash))
+    {
+        return false;
+    }
+
+    if !answers_request(
+        requested,
+        coins.iter().map(|(puzzle_hash, _)| *puzzle_hash),
+        proofs.is_some(),
+    ) {
+        return false;
+    }
+
+    // Without proofs, the response must contain every coin created in the block.
+    let Some(proofs) = proofs else {
+        let additions: Vec<Coin> = coins
+            .iter()
+            .flat_map(|(_, coins)| coins.iter().copied())
+            .collect();
+        return additions_root(&additions) == root;
+    };
+
+    if proofs.len() != coins.len() {
+        return false;
+    }
+
+    coins.iter().zip(proofs).all(
+        |((puzzle_hash, coins), (proof_puzzle_hash, puzzle_hash_proof, coin_ids_proof))| {
+            if puzzle_hash != proof_puzzle_hash {
+                return false;
+            }
+
+            if coins.is_empty() {
+                return is_included(puzzle_hash_proof, *puzzle_hash, root) == Some(false);
+            }
+
+            let Some(coin_ids_proof) = coin_ids_proof else {
+                return false;
+            };
+
+            let coin_ids: Vec<Bytes32> = coins.iter().map(Coin::coin_id).collect();
+
+            is_included(puzzle_hash_proof, *puzzle_hash, root) == Some(true)
+                && is_included(coin_ids_proof, hash_coin_ids(&coin_ids), root) == Some(true)
+        },
+    )
+}
+
+fn validate_removals(
+    root: Bytes32,
+    requested: Option<&[Bytes32]>,
+    coins: &[(Bytes32, Option<Coin>)],
+    proofs: Option<&[RemovalsProof]>,
+) -> bool {
+    if coins
+        .iter()
+        .any(|(coin_id, coin)| coin.is_some_and(|coin| coin.coin_id() != *coin_id))
+    {
+        return false;
+    }
+
+    if !answers_request(
+        requested,
+        coins.iter().map(|(coin_id, _)| *coin_id),
+        proofs.is_some(),
+    ) {
+        return false;
+    }
+
+    // Without proofs, the response must contain every coin spent in the block.
+    let Some(proofs) = proofs else {
+        let mut coin_ids = Vec::new();
+
+        for (coin_id, coin) in coins {
+            if coin.is_none() {
+                return false;
+            }
+            coin_ids.push(*coin_id);
+        }
+
+        return removals_root(&coin_ids) == root;
+    };
+
+    if proofs.len() != coins.len() {
+        return false;
+    }
+
+    coins
+        .iter()
+        .zip(proofs)
+        .all(|((coin_id, coin), (proof_coin_id, proof))| {
+            coin_id == proof_coin_id && is_included(proof, *coin_id, root) == Some(coin.is_some())
+        })
+}
+
+/// Whether the response has an entry for each of the requested items exactly once, and nothing else.
+/// Responses to a request for specific items come with proofs, but ones for the whole block don't.
+fn answers_request(
+    requested: Option<&[Bytes32]>,
+    mut answered: impl Iterator<Item = Bytes32>,
+    has_proofs: bool,
+) -> bool {
+    let Some(requested) = requested else {
+        return !has_proofs;
+    };
+
+    let requested: HashSet<Bytes32> = requested.iter().copied().collect();
+    let mut seen = HashSet::new();
+
+    has_proofs
+        && answered.all(|item| requested.contains(&item) && seen.insert(item))
+        && seen.len() == requested.len()
+}
+
+/// Whether the proof shows that the item is or isn't in the Merkle set, or `None` if it's invalid.
+fn is_included(proof: &Bytes, item: Bytes32, root: Bytes32) -> Option<bool> {
+    validate_merkle_proof(proof.as_ref(), &item.to_bytes(), &root.to_bytes()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use chia_consensus::merkle_tree::MerkleSet;
+
+    use super::*;
+
+    fn proof(set: &MerkleSet, item: Bytes32) -> Bytes {
+        set.generate_proof(&item.to_bytes()).unwrap().1.into()
+    }
+
+    #[test]
+    fn test_validate_additions() {
+        let puzzle_hash = Bytes32::new([1; 32]);
+        let other_puzzle_hash = Bytes32::new([2; 32]);
+        let missing_puzzle_hash = Bytes32::new([3; 32]);
+
+        let a = Coin::new(Bytes32::new([4; 32]), puzzle_hash, 1);
+        let b = Coin::new(Bytes32::new([5; 32]), puzzle_hash, 2);
+        let c = Coin::new(Bytes32::new([6; 32]), other_puzzle_hash, 3);
+
+        let coin_ids_hash = hash_coin_ids(&[a.coin_id(), b.coin_id()]);
+
+        let mut leafs = [
+            puzzle_hash.to_bytes(),
+            coin_ids_hash.to_bytes(),
+            other_puzzle_hash.to_bytes(),
+            hash_coin_ids(&[c.coin_id()]).to_bytes(),
+        ];
+        let set = MerkleSet::from_leafs(&mut leafs);
+        let root = Bytes32::new(set.get_root());
+
+        assert_eq!(root, additions_root(&[a, b, c]));
+
+        // All of the additions, without proofs.
+        let coins = vec![(puzzle_hash, vec![a, b]), (other_puzzle_hash, vec![c])];
+        assert!(validate_additions(root, None, &coins, None));
+
+        let coins = vec![(puzzle_hash, vec![a, b])];
+        assert!(!validate_additions(root, None, &coins, None));
+
+        // Only some of the additions, with proofs of inclusion and exclusion.
+        let coins = vec![(puzzle_hash, vec![a, b]), (missing_puzzle_hash, vec![])];
+        let proofs = vec![
+            (
+                puzzle_hash,
+                proof(&set, puzzle_hash),
+                Some(proof(&set, coin_ids_hash)),
+            ),
+            (missing_puzzle_hash, proof(&set, missing_puzzle_hash), None),
+        ];
+        let requested = [puzzle_hash, missing_puzzle_hash];
+        assert!(validate_additions(
+            root,
+            Some(&requested),
+            &coins,
+            Some(&proofs)
+        ));
+
+        // Proofs are only valid for the puzzle hashes that were requested.
+        assert!(!validate_additions(root, None, &coins, Some(&proofs)));
+
+        // A coin with the puzzle hash was left out.
+        let coins = vec![(puzzle_hash, vec![a]), (missing_puzzle_hash, vec![])];
+        assert!(!validate_additions(
+            root,
+            Some(&requested),
+            &coins,
+            Some(&proofs)
+        ));
+
+        // A requested puzzle hash was left out of the response entirely.
+        let coins = vec![(puzzle_hash, vec![a, b])];
+        assert!(!validate_additions(
+            root,
+            Some(&requested),
+            &coins,
+            Some(&proofs[..1])
+        ));
+
+        // The same puzzle hash was answered twice, in place of another one.
+        let coins = vec![(puzzle_hash, vec![a, b]), (puzzle_hash, vec![a, b])];
+        let duplicate_proofs = vec![proofs[0].clone(), proofs[0].clone()];
+        assert!(!validate_additions(
+            root,
+            Some(&requested),
+            &coins,
+            Some(&duplicate_proofs)
+        ));
+
+        // The puzzle hash is claimed to have no coins.
+        let coins = vec![(other_puzzle_hash, vec![])];
+        let proofs = vec![(other_puzzle_hash, proof(&set, other_puzzle_hash), None)];
+        assert!(!validate_additions(
+            root,
+            Some(&[other_puzzle_hash]),
+            &coins,
+            Some(&proofs)
+        ));
+    }
+
+    #[test]
+    fn test_validate_removals() {
+        let a = Coin::new(Bytes32::new([1; 32]), Bytes32::new([2; 32]), 1);
+        let b = Coin::new(Bytes32::new([3; 32]), Bytes32::new([4; 32]), 2);
+        let missing = Coin::new(Bytes32::new([5; 32]), Bytes32::new([6; 32]), 3);
+
+        let mut leafs = [a.coin_id().to_bytes(), b.coin_id().to_bytes()];
+        let set = MerkleSet::from_leafs(&mut leafs);
+        let root = Bytes32::new(set.get_root());
+
+        assert_eq!(root, removals_root(&[a.coin_id(), b.coin_id()]));
+
+        // All of the removals, without proofs.
+        let coins = vec![(a.coin_id(), Some(a)), (b.coin_id(), Some(b))];
+        assert!(validate_removals(root, None, &coins, None));
+
+        let coins = vec![(a.coin_id(), Some(a))];
+        assert!(!validate_removals(root, None, &coins, None));
+
+        // Only some of the removals, with proofs of inclusion and exclusion.
+        let coins = vec![(a.coin_id(), Some(a)), (missing.coin_id(), None)];
+        let proofs = vec![
+            (a.coin_id(), proof(&set, a.coin_id())),
+            (missing.coin_id(), proof(&set, missing.coin_id())),
+        ];
+        let requested = [a.coin_id(), missing.coin_id()];
+        assert!(validate_removals(
+            root,
+            Some(&requested),
+            &coins,
+            Some(&proofs)
+        ));
+
+        // Proofs are only valid for the coin ids that were requested.
+        assert!(!validate_removals(root, None, &coins, Some(&proofs)));
+
+        // A requested coin id was left out of the response entirely.
+        let coins = vec![(a.coin_id(), Some(a))];
+        assert!(!validate_removals(
+            root,
+            Some(&requested),
+            &coins,
+            Some(&proofs[..1])
+        ));
+
+        // The coin is claimed to not have been spent.
+        let coins = vec![(b.coin_id(), None)];
+        let proofs = vec![(b.coin_id(), proof(&set, b.coin_id()))];
+        assert!(!validate_removals(
+            root,
+            Some(&[b.coin_id()]),
+            &coins,
+            Some(&proofs)
+        ));
+
+        // The coin doesn't match its id.
+        let coins = vec![(b.coin_id(), Some(a))];
+        assert!(!validate_removals(
+            root,
+            Some(&[b.coin_id()]),
+            &coins,
+            Some(&proofs)
+        ));
+    }
+}
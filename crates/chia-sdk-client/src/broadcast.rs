@@ -0,0 +1,756 @@
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use chia_consensus::consensus_constants::ConsensusConstants;
+use chia_protocol::{
+    Bytes32, Coin, CoinState, CoinStateUpdate, Message, ProtocolMessageTypes, SpendBundle,
+    TransactionAck,
+};
+use chia_traits::Streamable;
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use tokio::sync::oneshot;
+use tracing::{debug, warn};
+
+use crate::{spend_bundle_conditions, ClientError, Peer};
+
+/// The `status` of a [`TransactionAck`](chia_protocol::TransactionAck), as defined by the full node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MempoolInclusionStatus {
+    /// The transaction was added to the mempool.
+    Success = 1,
+    /// The transaction is valid, but couldn't be added to the mempool yet.
+    Pending = 2,
+    Failed = 3,
+}
+
+impl MempoolInclusionStatus {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            1 => Self::Success,
+            2 => Self::Pending,
+            3 => Self::Failed,
+            _ => return None,
+        })
+    }
+}
+
+/// How a peer responded to a transaction, according to its [`TransactionAck`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionAckStatus {
+    /// The transaction was added to the mempool.
+    Success,
+    /// The transaction is valid, but couldn't be added to the mempool yet, with the given error.
+    Pending(Option<String>),
+    /// The peer is already processing the transaction, or has already added it to the mempool.
+    AlreadyIncluded,
+    /// The transaction was rejected, with the given error.
+    Invalid(String),
+}
+
+impl TransactionAckStatus {
+    pub fn from_ack(ack: &TransactionAck) -> Self {
+        match MempoolInclusionStatus::from_u8(ack.status) {
+            Some(MempoolInclusionStatus::Success) => Self::Success,
+            Some(MempoolInclusionStatus::Pending) => Self::Pending(ack.error.clone()),
+            // The full node fails transactions that it has already seen with this error.
+            Some(MempoolInclusionStatus::Failed)
+                if ack.error.as_deref() == Some("ALREADY_INCLUDING_TRANSACTION") =>
+            {
+                Self::AlreadyIncluded
+            }
+            Some(MempoolInclusionStatus::Failed) => Self::Invalid(
+                ack.error
+                    .clone()
+                    .unwrap_or_else(|| "Unknown error".to_string()),
+            ),
+            None => Self::Invalid(format!("Unknown status {}", ack.status)),
+        }
+    }
+
+    /// Whether the peer will keep the transaction in its mempool, or try to add it later.
+    pub fn is_accepted(&self) -> bool {
+        !matches!(self, Self::Invalid(..))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionStatus {
+    /// The transaction was included in a block at the given height.
+    Confirmed(u32),
+    /// Every peer rejected the transaction, with the given error.
+    Failed(String),
+    /// Some of the coins were spent by a different transaction.
+    Conflicted,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BroadcastOptions {
+    /// How many peers to send the transaction to, when broadcasting through a `Client`.
+    pub peers: usize,
+    /// How long to wait before sending a pending transaction to the peers again.
+    pub rebroadcast_interval: Duration,
+}
+
+impl Default for BroadcastOptions {
+    fn default() -> Self {
+        Self {
+            peers: 3,
+            rebroadcast_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Sends transactions to peers, and tracks them until they are confirmed or can no longer be.
+///
+/// The coins spent and created by each transaction are subscribed to, so the messages received
+/// from the peers need to be passed into [`TransactionBroadcaster::handle_message`]. The coins are
+/// unsubscribed from once the transaction is resolved, unless another pending transaction uses them.
+#[derive(Debug, Clone)]
+pub struct TransactionBroadcaster {
+    constants: Arc<ConsensusConstants>,
+    options: BroadcastOptions,
+    pending: Arc<Mutex<HashMap<Bytes32, PendingState>>>,
+}
+
+#[derive(Debug)]
+struct PendingState {
+    spend_bundle: SpendBundle,
+    removals: HashSet<Bytes32>,
+    additions: HashSet<Bytes32>,
+    spent_removals: HashMap<Bytes32, u32>,
+    peers: Vec<Peer>,
+    last_broadcast: Instant,
+    sender: oneshot::Sender<TransactionStatus>,
+}
+
+/// A pending transaction which is due to be sent again.
+struct Rebroadcast {
+    transaction_id: Bytes32,
+    spend_bundle: SpendBundle,
+    coin_ids: Vec<Bytes32>,
+    peers: Vec<Peer>,
+    // Peers which haven't been sent the transaction or subscribed to its coins yet.
+    replacements: Vec<Peer>,
+}
+
+/// Resolves once the outcome of a broadcasted transaction is known.
+#[derive(Debug)]
+pub struct PendingTransaction {
+    transaction_id: Bytes32,
+    acks: Vec<(SocketAddr, TransactionAckStatus)>,
+    receiver: oneshot::Receiver<TransactionStatus>,
+}
+
+impl PendingTransaction {
+    pub fn transaction_id(&self) -> Bytes32 {
+        self.transaction_id
+    }
+
+    /// How each peer which responded to the broadcast handled the transaction.
+    pub fn acks(&self) -> &[(SocketAddr, TransactionAckStatus)] {
+        &self.acks
+    }
+}
+
+impl Future for PendingTransaction {
+    type Output = Result<TransactionStatus, ClientError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.receiver)
+            .poll(cx)
+            .map(|result| Ok(result?))
+    }
+}
+
+impl TransactionBroadcaster {
+    pub fn new(constants: ConsensusConstants, options: BroadcastOptions) -> Self {
+        Self {
+            constants: Arc::new(constants),
+            options,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn options(&self) -> &BroadcastOptions {
+        &self.options
+    }
+
+    /// The ids of the transactions that haven't been resolved yet.
+    pub fn pending_transactions(&self) -> Vec<Bytes32> {
+        self.lock().keys().copied().collect()
+    }
+
+    /// Sends the transaction to each of the peers, and subscribes to the coins it spends and creates.
+    ///
+    /// Returns an error if there are no peers, or none of them responded. Otherwise, the transaction is tracked
+    /// until it's confirmed, conflicted, or rejected by every peer that responded.
+    pub async fn broadcast(
+        &self,
+        peers: Vec<Peer>,
+        spend_bundle: SpendBundle,
+    ) -> Result<PendingTransaction, ClientError> {
+        if peers.is_empty() {
+            return Err(ClientError::NoPeers);
+        }
+
+        let transaction_id = spend_bundle.name();
+
+        let height = peers
+            .iter()
+            .filter_map(|peer| peer.peak().map(|peak| peak.height))
+            .max()
+            .unwrap_or(0);
+
+        let removals: HashSet<Bytes32> = spend_bundle
+            .coin_spends
+            .iter()
+            .map(|coin_spend| coin_spend.coin.coin_id())
+            .collect();
+
+        let additions: HashSet<Bytes32> = additions(&spend_bundle, &self.constants, height)?
+            .into_iter()
+            .map(|coin| coin.coin_id())
+            .collect();
+
+        let coin_ids: Vec<Bytes32> = removals.iter().chain(&additions).copied().collect();
+
+        let (sender, receiver) = oneshot::channel();
+
+        // The transaction is tracked before it's sent, so that no coin state updates are missed.
+        self.lock().insert(
+            transaction_id,
+            PendingState {
+                spend_bundle: spend_bundle.clone(),
+                removals,
+                additions,
+                spent_removals: HashMap::new(),
+                peers: peers.clone(),
+                last_broadcast: Instant::now(),
+                sender,
+            },
+        );
+
+        let mut futures = FuturesUnordered::new();
+
+        for peer in peers {
+            let spend_bundle = spend_bundle.clone();
+            let coin_ids = coin_ids.clone();
+
+            futures.push(async move {
+                let ack = peer.send_transaction(spend_bundle).await?;
+                let response = peer.register_for_coin_updates(coin_ids, 0).await?;
+                Ok::<_, ClientError>((
+                    peer.socket_addr(),
+                    TransactionAckStatus::from_ack(&ack),
+                    response.coin_states,
+                ))
+            });
+        }
+
+        let mut acks = Vec::new();
+        let mut last_error = None;
+
+        while let Some(result) = futures.next().await {
+            let (socket_addr, status, coin_states) = match result {
+                Ok(result) => result,
+                Err(error) => {
+                    warn!("Failed to broadcast transaction {transaction_id}: {error}");
+                    last_error = Some(error);
+                    continue;
+                }
+            };
+
+            if !status.is_accepted() {
+                debug!("Transaction {transaction_id} was rejected by {socket_addr}: {status:?}");
+            }
+
+            acks.push((socket_addr, status));
+            self.handle_coin_states(&coin_states).await;
+        }
+
+        if acks.is_empty() {
+            self.lock().remove(&transaction_id);
+            return Err(last_error.unwrap_or(ClientError::NoPeers));
+        }
+
+        // A transaction that spends coins which are already spent is either already confirmed,
+        // or conflicts with another transaction. This is resolved by the coin states instead.
+        if !acks.iter().any(|(_, status)| status.is_accepted()) {
+            let finished = {
+                let mut pending = self.lock();
+
+                match pending.remove(&transaction_id) {
+                    Some(state) if state.spent_removals.is_empty() => {
+                        let error = acks
+                            .iter()
+                            .find_map(|(_, status)| match status {
+                                TransactionAckStatus::Invalid(error) => Some(error.clone()),
+                                _ => None,
+                            })
+                            .unwrap_or_else(|| "Unknown error".to_string());
+                        vec![state.finish(Some(TransactionStatus::Failed(error)))]
+                    }
+                    Some(state) => {
+                        pending.insert(transaction_id, state);
+                        Vec::new()
+                    }
+                    None => Vec::new(),
+                }
+            };
+
+            self.unsubscribe(finished).await;
+        }
+
+        Ok(PendingTransaction {
+            transaction_id,
+            acks,
+            receiver,
+        })
+    }
+
+    /// Handles a message received from a peer. Coin state updates resolve pending transactions,
+    /// and new peaks cause pending transactions to be rebroadcasted to the peers they were sent to
+    /// if enough time has passed. Use [`TransactionBroadcaster::rebroadcast`] directly to replace
+    /// peers which have disconnected.
+    pub async fn handle_message(&self, message: &Message) -> Result<(), ClientError> {
+        match message.msg_type {
+            ProtocolMessageTypes::CoinStateUpdate => {
+                let update = CoinStateUpdate::from_bytes(&message.data)?;
+                self.handle_coin_states(&update.items).await;
+            }
+            ProtocolMessageTypes::NewPeakWallet => {
+                self.rebroadcast(&[]).await;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Updates pending transactions with the coin states, and resolves them if possible.
+    pub async fn handle_coin_states(&self, coin_states: &[CoinState]) {
+        let finished = {
+            let mut pending = self.lock();
+            let mut finished = Vec::new();
+
+            // Transactions whose future has been dropped no longer need to be tracked.
+            let dropped: Vec<Bytes32> = pending
+                .iter()
+                .filter(|(_, state)| state.sender.is_closed())
+                .map(|(&transaction_id, _)| transaction_id)
+                .collect();
+
+            for transaction_id in dropped {
+                if let Some(state) = pending.remove(&transaction_id) {
+                    finished.push(state.finish(None));
+                }
+            }
+
+            let resolved: Vec<(Bytes32, TransactionStatus)> = pending
+                .iter_mut()
+                .filter_map(|(&transaction_id, state)| {
+                    state
+                        .apply(coin_states)
+                        .map(|status| (transaction_id, status))
+                })
+                .collect();
+
+            for (transaction_id, status) in resolved {
+                if let Some(state) = pending.remove(&transaction_id) {
+                    debug!("Transaction {transaction_id} was resolved as {status:?}");
+                    finished.push(state.finish(Some(status)));
+                }
+            }
+
+            finished
+        };
+
+        self.unsubscribe(finished).await;
+    }
+
+    /// Removes the coin subscriptions of resolved transactions, except for the coins which are
+    /// still used by other pending transactions.
+    async fn unsubscribe(&self, finished: Vec<(Vec<Peer>, HashSet<Bytes32>)>) {
+        if finished.is_empty() {
+            return;
+        }
+
+        let tracked: HashSet<Bytes32> = self
+            .lock()
+            .values()
+            .flat_map(|state| state.removals.iter().chain(&state.additions).copied())
+            .collect();
+
+        for (peers, coin_ids) in finished {
+            let coin_ids: Vec<Bytes32> = coin_ids.difference(&tracked).copied().collect();
+
+            if coin_ids.is_empty() {
+                continue;
+            }
+
+            for peer in peers.iter().filter(|peer| peer.is_connected()) {
+                if let Err(error) = peer.remove_coin_subscriptions(Some(coin_ids.clone())).await {
+                    warn!(
+                        "Failed to unsubscribe from transaction coins on {}: {error}",
+                        peer.socket_addr()
+                    );
+                }
+            }
+        }
+    }
+
+    /// Sends the pending transactions which haven't been sent recently to their peers again.
+    ///
+    /// Peers which have disconnected are replaced with the given peers, up to
+    /// [`BroadcastOptions::peers`], and the replacements are subscribed to the transaction's coins.
+    pub async fn rebroadcast(&self, peers: &[Peer]) {
+        let due: Vec<Rebroadcast> = {
+            let mut pending = self.lock();
+            let now = Instant::now();
+
+            pending
+                .iter_mut()
+                .filter(|(_, state)| {
+                    now.duration_since(state.last_broadcast) >= self.options.rebroadcast_interval
+                })
+                .map(|(&transaction_id, state)| {
+                    state.last_broadcast = now;
+                    state.peers.retain(Peer::is_connected);
+
+                    let needed = self.options.peers.saturating_sub(state.peers.len());
+                    let replacements: Vec<Peer> = peers
+                        .iter()
+                        .filter(|peer| {
+                            peer.is_connected()
+                                && !state
+                                    .peers
+                                    .iter()
+                                    .any(|existing| existing.socket_addr() == peer.socket_addr())
+                        })
+                        .take(needed)
+                        .cloned()
+                        .collect();
+
+                    // The replacements are tracked right away, so that they're unsubscribed from
+                    // once the transaction is resolved.
+                    let existing = state.peers.clone();
+                    state.peers.extend(replacements.iter().cloned());
+
+                    Rebroadcast {
+                        transaction_id,
+                        spend_bundle: state.spend_bundle.clone(),
+                        coin_ids: state.removals.union(&state.additions).copied().collect(),
+                        peers: existing,
+                        replacements,
+                    }
+                })
+                .collect()
+        };
+
+        for Rebroadcast {
+            transaction_id,
+            spend_bundle,
+            coin_ids,
+            peers,
+            replacements,
+        } in due
+        {
+            for peer in peers {
+                if let Err(error) = peer.send_transaction(spend_bundle.clone()).await {
+                    warn!(
+                        "Failed to rebroadcast transaction {transaction_id} to {}: {error}",
+                        peer.socket_addr()
+                    );
+                }
+            }
+
+            for peer in replacements {
+                let result = async {
+                    peer.send_transaction(spend_bundle.clone()).await?;
+                    let response = peer.register_for_coin_updates(coin_ids.clone(), 0).await?;
+                    Ok::<_, ClientError>(response.coin_states)
+                }
+                .await;
+
+                match result {
+                    Ok(coin_states) => self.handle_coin_states(&coin_states).await,
+                    Err(error) => warn!(
+                        "Failed to rebroadcast transaction {transaction_id} to {}: {error}",
+                        peer.socket_addr()
+                    ),
+                }
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Bytes32, PendingState>> {
+        self.pending
+            .lock()
+            .expect("pending transactions lock poisoned")
+    }
+}
+
+impl PendingState {
+    /// Resolves the transaction, and returns the peers and coins that it was subscribed to.
+    fn finish(self, status: Option<TransactionStatus>) -> (Vec<Peer>, HashSet<Bytes32>) {
+        if let Some(status) = status {
+            self.sender.send(status).ok();
+        }
+
+        let coin_ids = self.removals.union(&self.additions).copied().collect();
+        (self.peers, coin_ids)
+    }
+
+    fn apply(&mut self, coin_states: &[CoinState]) -> Option<TransactionStatus> {
+        let mut created_height = None;
+
+        for coin_state in coin_states {
+            let coin_id = coin_state.coin.coin_id();
+
+            if let (true, Some(spent_height)) =
+                (self.removals.contains(&coin_id), coin_state.spent_height)
+            {
+                self.spent_removals.insert(coin_id, spent_height);
+            }
+
+            if let (true, Some(height)) =
+                (self.additions.contains(&coin_id), coin_state.created_height)
+            {
+                created_height = Some(height);
+            }
+        }
+
+        if let Some(height) = created_height {
+            return Some(TransactionStatus::Confirmed(height));
+        }
+
+        if self.spent_removals.is_empty() {
+            return None;
+        }
+
+        // Without any additions, the only sign of confirmation is that every coin was spent.
+        if self.additions.is_empty() {
+            if self.spent_removals.len() == self.removals.len() {
+                let height = self.spent_removals.values().copied().max().unwrap_or(0);
+                return Some(TransactionStatus::Confirmed(height));
+            }
+            return None;
+        }
+
+        // The additions would have been created in the same block that spent the removals.
+        Some(TransactionStatus::Conflicted)
+    }
+}
+
+fn additions(
+    spend_bundle: &SpendBundle,
+    constants: &ConsensusConstants,
+    height: u32,
+) -> Result<Vec<Coin>, ClientError> {
+    let conditions = spend_bundle_conditions(spend_bundle, constants, height)?;
+
+    Ok(conditions
+        .spends
+        .iter()
+        .flat_map(|spend| {
+            spend
+                .create_coin
+                .iter()
+                .map(|&(puzzle_hash, amount, _)| Coin::new(spend.coin_id, puzzle_hash, amount))
+        })
+        .collect())
+}
+
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+impl crate::Client {
+    /// Broadcasts the transaction to the connected peers with the highest reputation.
+    pub async fn broadcast_transaction(
+        &self,
+        broadcaster: &TransactionBroadcaster,
+        spend_bundle: SpendBundle,
+    ) -> Result<PendingTransaction, ClientError> {
+        let peers = self.lock().await.best_peers(broadcaster.options().peers);
+
+        if peers.is_empty() {
+            return Err(ClientError::NoPeers);
+        }
+
+        broadcaster.broadcast(peers, spend_bundle).await
+    }
+
+    /// Rebroadcasts the pending transactions, replacing peers which have disconnected with the
+    /// connected peers with the highest reputation.
+    pub async fn rebroadcast_transactions(&self, broadcaster: &TransactionBroadcaster) {
+        let peers = self.lock().await.best_peers(broadcaster.options().peers);
+        broadcaster.rebroadcast(&peers).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chia_bls::Signature;
+    use chia_protocol::{
+        CoinSpend, Program, RegisterForCoinUpdates, RespondToCoinUpdates, SendTransaction,
+    };
+    use chia_sdk_types::TESTNET11_CONSTANTS;
+    use chia_sha2::Sha256;
+
+    use crate::{test_server::TestServer, to_message, InboundPeer, RequestHandler};
+
+    use super::*;
+
+    fn ack(status: u8, error: Option<&str>) -> TransactionAck {
+        TransactionAck::new(Bytes32::default(), status, error.map(str::to_string))
+    }
+
+    /// Keeps every transaction pending, and records the coins that are subscribed to.
+    #[derive(Default)]
+    struct PendingHandler {
+        registrations: Mutex<Vec<Vec<Bytes32>>>,
+    }
+
+    impl RequestHandler for PendingHandler {
+        async fn handle(
+            &self,
+            _peer: &InboundPeer,
+            message: Message,
+        ) -> Result<Option<Message>, ClientError> {
+            match message.msg_type {
+                ProtocolMessageTypes::SendTransaction => {
+                    let request = SendTransaction::from_bytes(&message.data)?;
+                    Ok(Some(to_message(&TransactionAck::new(
+                        request.transaction.name(),
+                        MempoolInclusionStatus::Pending as u8,
+                        Some("MEMPOOL_CONFLICT".to_string()),
+                    ))?))
+                }
+                ProtocolMessageTypes::RegisterForCoinUpdates => {
+                    let request = RegisterForCoinUpdates::from_bytes(&message.data)?;
+                    self.registrations
+                        .lock()
+                        .expect("registrations lock poisoned")
+                        .push(request.coin_ids.clone());
+                    Ok(Some(to_message(&RespondToCoinUpdates::new(
+                        request.coin_ids,
+                        request.min_height,
+                        Vec::new(),
+                    ))?))
+                }
+                _ => Ok(None),
+            }
+        }
+    }
+
+    /// Spends a coin with the puzzle `1`, which returns its solution as the conditions, to create
+    /// a single child coin.
+    fn test_spend_bundle() -> (SpendBundle, Vec<Bytes32>) {
+        let mut hasher = Sha256::new();
+        hasher.update([1, 1]);
+        let puzzle_hash = Bytes32::new(hasher.finalize());
+
+        let coin = Coin::new(Bytes32::default(), puzzle_hash, 1);
+        let child = Coin::new(coin.coin_id(), Bytes32::default(), 1);
+
+        // The solution is `((51 child_puzzle_hash 1))`.
+        let mut solution = vec![0xff, 0xff, 0x33, 0xff, 0xa0];
+        solution.extend_from_slice(&child.puzzle_hash);
+        solution.extend_from_slice(&[0xff, 0x01, 0x80, 0x80]);
+
+        let spend_bundle = SpendBundle::new(
+            vec![CoinSpend::new(
+                coin,
+                Program::from(vec![1]),
+                Program::from(solution),
+            )],
+            Signature::default(),
+        );
+
+        let mut coin_ids = vec![coin.coin_id(), child.coin_id()];
+        coin_ids.sort();
+        (spend_bundle, coin_ids)
+    }
+
+    #[test]
+    fn test_ack_status() {
+        assert_eq!(
+            TransactionAckStatus::from_ack(&ack(1, None)),
+            TransactionAckStatus::Success
+        );
+        assert_eq!(
+            TransactionAckStatus::from_ack(&ack(2, Some("MEMPOOL_CONFLICT"))),
+            TransactionAckStatus::Pending(Some("MEMPOOL_CONFLICT".to_string()))
+        );
+        assert_eq!(
+            TransactionAckStatus::from_ack(&ack(3, Some("ALREADY_INCLUDING_TRANSACTION"))),
+            TransactionAckStatus::AlreadyIncluded
+        );
+        assert_eq!(
+            TransactionAckStatus::from_ack(&ack(3, Some("DOUBLE_SPEND"))),
+            TransactionAckStatus::Invalid("DOUBLE_SPEND".to_string())
+        );
+        assert_eq!(
+            TransactionAckStatus::from_ack(&ack(7, None)),
+            TransactionAckStatus::Invalid("Unknown status 7".to_string())
+        );
+        assert!(TransactionAckStatus::AlreadyIncluded.is_accepted());
+    }
+
+    #[tokio::test]
+    async fn test_rebroadcast_replaces_disconnected_peers() -> Result<(), ClientError> {
+        let first = TestServer::start_plain(PendingHandler::default()).await?;
+        let second = TestServer::start_plain(PendingHandler::default()).await?;
+        let (first_peer, _first_receiver) = first.connect_plain().await?;
+        let (second_peer, _second_receiver) = second.connect_plain().await?;
+
+        let broadcaster = TransactionBroadcaster::new(
+            TESTNET11_CONSTANTS.clone(),
+            BroadcastOptions {
+                peers: 1,
+                rebroadcast_interval: Duration::ZERO,
+            },
+        );
+
+        let (spend_bundle, coin_ids) = test_spend_bundle();
+        let pending = broadcaster
+            .broadcast(vec![first_peer.clone()], spend_bundle)
+            .await?;
+        assert!(matches!(
+            pending.acks(),
+            [(_, TransactionAckStatus::Pending(_))]
+        ));
+
+        first.stop();
+        while first_peer.is_connected() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        broadcaster.rebroadcast(&[second_peer]).await;
+
+        // The replacement peer is subscribed to the transaction's coins, and it stays pending.
+        let mut registrations = second
+            .server
+            .handler()
+            .registrations
+            .lock()
+            .expect("registrations lock poisoned")
+            .clone();
+        registrations
+            .iter_mut()
+            .for_each(|coin_ids| coin_ids.sort());
+        assert_eq!(registrations, vec![coin_ids]);
+        assert_eq!(
+            broadcaster.pending_transactions(),
+            vec![pending.transaction_id()]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+use std::{collections::HashSet, fmt, str::FromStr};
+
+use chia_protocol::Handshake;
+
+/// The protocol version sent in the handshake, which matches `protocol_version` in
+/// `chia/protocols/shared_protocol.py` of the reference full node.
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion::new(0, 0, 37);
+
+/// The protocol version in which the wallet sync requests, such as `RequestPuzzleState`, were added.
+///
+/// Full nodes have advertised this version since chia-blockchain 2.4.0, which added those requests.
+/// Requests are refused for peers below this version, so it must never be raised above the version
+/// that mainnet full nodes advertise.
+pub const WALLET_PROTOCOL_V2: ProtocolVersion = ProtocolVersion::new(0, 0, 37);
+
+/// The capabilities that are advertised in the handshake.
+pub const WALLET_CAPABILITIES: [Capability; 3] = [
+    Capability::Base,
+    Capability::BlockHeaders,
+    Capability::RateLimitsV2,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u16)]
+pub enum Capability {
+    Base = 1,
+    /// Supports `RequestBlockHeaders`, which is faster than requesting header blocks one at a time.
+    BlockHeaders = 2,
+    RateLimitsV2 = 3,
+    NoneResponse = 4,
+    MempoolUpdates = 5,
+}
+
+impl Capability {
+    pub fn from_u16(value: u16) -> Option<Self> {
+        Some(match value {
+            1 => Self::Base,
+            2 => Self::BlockHeaders,
+            3 => Self::RateLimitsV2,
+            4 => Self::NoneResponse,
+            5 => Self::MempoolUpdates,
+            _ => return None,
+        })
+    }
+
+    /// The capabilities advertised by both sides of the connection.
+    pub fn negotiate(ours: &[Capability], handshake: &Handshake) -> HashSet<Capability> {
+        handshake
+            .capabilities
+            .iter()
+            .filter(|(_, value)| value == "1")
+            .filter_map(|&(capability, _)| Self::from_u16(capability))
+            .filter(|capability| ours.contains(capability))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProtocolVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl ProtocolVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl FromStr for ProtocolVersion {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split('.').map(str::parse::<u32>);
+
+        let (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(());
+        };
+
+        Ok(Self::new(major, minor, patch))
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chia_protocol::NodeType;
+
+    use super::*;
+
+    #[test]
+    fn test_protocol_version() {
+        assert_eq!("0.0.37".parse(), Ok(ProtocolVersion::new(0, 0, 37)));
+        assert_eq!("0.0".parse::<ProtocolVersion>(), Err(()));
+        assert_eq!("0.0.x".parse::<ProtocolVersion>(), Err(()));
+        assert!(ProtocolVersion::new(0, 0, 36) < WALLET_PROTOCOL_V2);
+        assert!(ProtocolVersion::new(0, 1, 0) > WALLET_PROTOCOL_V2);
+    }
+
+    #[test]
+    fn test_negotiate_capabilities() {
+        let handshake = Handshake {
+            network_id: "mainnet".to_string(),
+            protocol_version: "0.0.37".to_string(),
+            software_version: "2.4.0".to_string(),
+            server_port: 8444,
+            node_type: NodeType::FullNode,
+            capabilities: vec![
+                (1, "1".to_string()),
+                (2, "0".to_string()),
+                (3, "1".to_string()),
+                (5, "1".to_string()),
+                (100, "1".to_string()),
+            ],
+        };
+
+        assert_eq!(
+            Capability::negotiate(&WALLET_CAPABILITIES, &handshake),
+            HashSet::from([Capability::Base, Capability::RateLimitsV2])
+        );
+    }
+}
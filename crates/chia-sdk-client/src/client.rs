@@ -1,23 +1,28 @@
 use std::{
     collections::{HashMap, HashSet},
     fmt,
+    future::Future,
     net::{IpAddr, SocketAddr},
     ops::Deref,
     sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use chia_protocol::Message;
-use tokio::sync::{mpsc, Mutex};
+use chia_protocol::{Message, NodeType};
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use tokio::sync::{mpsc, Mutex, Notify};
 use tokio_tungstenite::Connector;
+use tracing::{debug, info, warn};
 
-use crate::{connect_peer, ClientError, Network, Peer, PeerOptions};
+use crate::{connect::connect_node, AddressBook, ClientError, Network, Peer, PeerOptions, Proxy};
 
 #[derive(Clone)]
 pub struct Client {
     network_id: String,
     network: Network,
     connector: Connector,
+    options: ClientOptions,
+    proxy: Option<Arc<Proxy>>,
     state: Arc<Mutex<ClientState>>,
 }
 
@@ -27,6 +32,8 @@ impl fmt::Debug for Client {
         f.debug_struct("Client")
             .field("network_id", &self.network_id)
             .field("network", &self.network)
+            .field("options", &self.options)
+            .field("proxy", &self.proxy)
             .finish()
     }
 }
@@ -39,23 +46,132 @@ impl Deref for Client {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone, Copy)]
+pub struct ClientOptions {
+    /// The number of peers that the client will try to stay connected to.
+    pub target_peers: usize,
+    /// How many peers to attempt to connect to at the same time.
+    pub connection_batch_size: usize,
+    pub connection_timeout: Duration,
+    pub dns_batch_size: usize,
+    pub dns_timeout: Duration,
+    /// How long to wait between checks of the peer pool, if no peer disconnects in the meantime.
+    pub discovery_interval: Duration,
+    /// How long peers are banned for, unless a duration is specified explicitly.
+    pub ban_duration: Duration,
+    /// Peers are banned once their reputation drops to this value or below.
+    pub ban_threshold: u32,
+    pub peer_options: PeerOptions,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            target_peers: 5,
+            connection_batch_size: 10,
+            connection_timeout: Duration::from_secs(3),
+            dns_batch_size: 2,
+            dns_timeout: Duration::from_secs(3),
+            discovery_interval: Duration::from_secs(10),
+            ban_duration: DEFAULT_BAN_DURATION,
+            ban_threshold: 0,
+            peer_options: PeerOptions::default(),
+        }
+    }
+}
+
+/// The reputation that a peer starts out with. It's banned once its reputation drops to the
+/// [`ClientOptions::ban_threshold`].
+pub const INITIAL_REPUTATION: u32 = 100;
+
+/// How much a peer's reputation recovers for each successful request, up to [`INITIAL_REPUTATION`].
+/// This keeps occasional failures from long-lived peers from adding up to a ban.
+pub const REPUTATION_RECOVERY: u32 = 1;
+
+pub const DEFAULT_BAN_DURATION: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerPenalty {
+    /// The peer responded with an unexpected or malformed message.
+    InvalidResponse,
+    /// The peer rejected a request which it should have been able to fulfill.
+    RejectedRequest,
+    /// The peer didn't respond to a request in time.
+    Timeout,
+    Custom(u32),
+}
+
+impl PeerPenalty {
+    pub fn amount(self) -> u32 {
+        match self {
+            Self::InvalidResponse => 50,
+            Self::RejectedRequest => 10,
+            Self::Timeout => 20,
+            Self::Custom(amount) => amount,
+        }
+    }
+
+    /// The penalty for a peer that caused the error, if it indicates misbehavior.
+    pub fn from_error(error: &ClientError) -> Option<Self> {
+        match error {
+            ClientError::InvalidResponse(..)
+            | ClientError::UnexpectedMessage(..)
+            | ClientError::Streamable(..) => Some(Self::InvalidResponse),
+            ClientError::Timeout(..) => Some(Self::Timeout),
+            ClientError::PuzzleStateRejected(..)
+            | ClientError::CoinStateRejected(..)
+            | ClientError::Rejected(..) => Some(Self::RejectedRequest),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ClientState {
     peers: HashMap<IpAddr, Peer>,
+    // The unix timestamp at which the ban expires.
     banned_peers: HashMap<IpAddr, u64>,
     trusted_peers: HashSet<IpAddr>,
+    reputations: HashMap<IpAddr, u32>,
+    ban_duration: Duration,
+    ban_threshold: u32,
+    address_book: AddressBook,
 }
 
 impl Client {
     pub fn new(network_id: String, network: Network, connector: Connector) -> Self {
+        Self::with_options(network_id, network, connector, ClientOptions::default())
+    }
+
+    pub fn with_options(
+        network_id: String,
+        network: Network,
+        connector: Connector,
+        options: ClientOptions,
+    ) -> Self {
+        let state = ClientState {
+            ban_duration: options.ban_duration,
+            ban_threshold: options.ban_threshold,
+            ..Default::default()
+        };
+
         Self {
             network_id,
             network,
             connector,
-            state: Arc::new(Mutex::new(ClientState::default())),
+            options,
+            proxy: None,
+            state: Arc::new(Mutex::new(state)),
         }
     }
 
+    /// Tunnels every connection to peers and introducers through the proxy.
+    #[must_use]
+    pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(Arc::new(proxy));
+        self
+    }
+
     pub fn network_id(&self) -> &str {
         &self.network_id
     }
@@ -64,16 +180,24 @@ impl Client {
         &self.network
     }
 
+    pub fn options(&self) -> &ClientOptions {
+        &self.options
+    }
+
     pub async fn connect(
         &self,
         socket_addr: SocketAddr,
-        options: PeerOptions,
+        mut options: PeerOptions,
     ) -> Result<mpsc::Receiver<Message>, ClientError> {
-        let (peer, receiver) = connect_peer(
+        options.trusted |= self.state.lock().await.is_trusted(&socket_addr.ip());
+
+        let (peer, receiver) = connect_node(
             self.network_id.clone(),
             self.connector.clone(),
             socket_addr,
             options,
+            NodeType::FullNode,
+            self.proxy.as_deref(),
         )
         .await?;
 
@@ -84,10 +208,316 @@ impl Client {
             return Err(ClientError::BannedPeer);
         }
 
-        state.peers.insert(peer.socket_addr().ip(), peer);
+        state.address_book.mark_success(socket_addr, now());
+        state.peers.insert(peer.socket_addr().ip(), peer.clone());
+
+        // However the messages are consumed, the peer is removed from the pool once it disconnects.
+        let client_state = self.state.clone();
+
+        tokio::spawn(async move {
+            peer.wait_for_disconnect().await;
+            client_state.lock().await.remove_disconnected(&peer);
+        });
 
         Ok(receiver)
     }
+
+    /// Sends a request to a peer, and penalizes the peer if the request fails because it misbehaved.
+    /// Otherwise, the peer's reputation recovers slightly.
+    /// This is how requests made on behalf of the pool should be sent.
+    pub async fn request<T, Fut>(&self, peer: &Peer, request: Fut) -> Result<T, ClientError>
+    where
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let result = request.await;
+        let ip_addr = peer.socket_addr().ip();
+
+        match &result {
+            Ok(..) => self.state.lock().await.recover(ip_addr),
+            Err(error) => {
+                if self.state.lock().await.penalize_error(ip_addr, error) {
+                    warn!("Banned peer {ip_addr} after it failed a request: {error}");
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Spawns a task which keeps the peer pool filled up to the target number of peers.
+    ///
+    /// Peers that were connected to successfully before are tried first, from the address book.
+    /// After that, more peers are discovered by asking connected peers for addresses, and then
+    /// through the network's introducers and DNS introducers. Discovered peers are added to the
+    /// address book. Peers which disconnect are removed from the pool and replaced.
+    /// Messages received from any peer in the pool are forwarded to the returned receiver,
+    /// and the task stops once the receiver is dropped.
+    pub fn spawn_discovery(&self) -> mpsc::Receiver<(SocketAddr, Message)> {
+        let (sender, receiver) = mpsc::channel(32);
+        let client = self.clone();
+
+        tokio::spawn(async move {
+            client.discovery_loop(sender).await;
+        });
+
+        receiver
+    }
+
+    async fn discovery_loop(&self, sender: mpsc::Sender<(SocketAddr, Message)>) {
+        let notify = Arc::new(Notify::new());
+        let mut candidates = HashSet::new();
+
+        while !sender.is_closed() {
+            let peer_count = self.prune_disconnected().await;
+
+            if peer_count < self.options.target_peers {
+                if candidates.is_empty() {
+                    candidates.extend(self.state.lock().await.address_book.best(now()));
+                }
+
+                if candidates.is_empty() {
+                    candidates.extend(self.request_peer_addresses().await);
+                }
+
+                if candidates.is_empty() {
+                    candidates.extend(self.request_introducer_addresses().await);
+                }
+
+                if candidates.is_empty() {
+                    let addrs = self
+                        .network
+                        .lookup_all(self.options.dns_timeout, self.options.dns_batch_size)
+                        .await;
+
+                    let mut state = self.state.lock().await;
+                    for &addr in &addrs {
+                        state.address_book.add(addr, now());
+                    }
+
+                    candidates.extend(addrs);
+                }
+
+                self.connect_candidates(&mut candidates, &sender, &notify)
+                    .await;
+            }
+
+            tokio::time::timeout(self.options.discovery_interval, notify.notified())
+                .await
+                .ok();
+        }
+
+        debug!("Stopping peer discovery, since the receiver has been dropped");
+    }
+
+    async fn prune_disconnected(&self) -> usize {
+        let mut state = self.state.lock().await;
+
+        let disconnected: Vec<Peer> = state
+            .peers
+            .values()
+            .filter(|peer| !peer.is_connected())
+            .cloned()
+            .collect();
+
+        for peer in disconnected {
+            state.remove_disconnected(&peer);
+        }
+
+        state.peers.len()
+    }
+
+    async fn request_peer_addresses(&self) -> HashSet<SocketAddr> {
+        let peers: Vec<Peer> = self.state.lock().await.peers().cloned().collect();
+        let mut addrs = HashSet::new();
+
+        for peer in peers {
+            let peer = peer.with_request_timeout(self.options.connection_timeout);
+
+            let response = match self.request(&peer, peer.request_peers()).await {
+                Ok(response) => response,
+                Err(error) => {
+                    warn!(
+                        "Failed to request peers from {}: {error}",
+                        peer.socket_addr()
+                    );
+                    continue;
+                }
+            };
+
+            let mut state = self.state.lock().await;
+
+            for item in response.peer_list {
+                let Ok(ip_addr) = item.host.parse::<IpAddr>() else {
+                    continue;
+                };
+                let addr = SocketAddr::new(ip_addr, item.port);
+                state.address_book.add(addr, item.timestamp);
+                addrs.insert(addr);
+            }
+        }
+
+        addrs
+    }
+
+    async fn request_introducer_addresses(&self) -> HashSet<SocketAddr> {
+        let mut addrs = HashSet::new();
+
+        for introducer in &self.network.introducers {
+            let lookup = tokio::net::lookup_host((introducer.as_str(), self.network.default_port));
+
+            let introducer_addrs =
+                match tokio::time::timeout(self.options.dns_timeout, lookup).await {
+                    Ok(Ok(introducer_addrs)) => introducer_addrs,
+                    Ok(Err(error)) => {
+                        warn!("Failed to lookup introducer {introducer}: {error}");
+                        continue;
+                    }
+                    Err(_timeout) => {
+                        warn!("Timeout looking up introducer {introducer}");
+                        continue;
+                    }
+                };
+
+            for socket_addr in introducer_addrs {
+                let result = tokio::time::timeout(self.options.connection_timeout, async {
+                    let (peer, _receiver) = connect_node(
+                        self.network_id.clone(),
+                        self.connector.clone(),
+                        socket_addr,
+                        self.options.peer_options,
+                        NodeType::Introducer,
+                        self.proxy.as_deref(),
+                    )
+                    .await?;
+                    let response = peer.request_peers_introducer().await;
+                    peer.close().await.ok();
+                    response
+                })
+                .await;
+
+                let response = match result {
+                    Ok(Ok(response)) => response,
+                    Ok(Err(error)) => {
+                        warn!("Failed to request peers from introducer {socket_addr}: {error}");
+                        continue;
+                    }
+                    Err(_timeout) => {
+                        warn!("Timeout requesting peers from introducer {socket_addr}");
+                        continue;
+                    }
+                };
+
+                let mut state = self.state.lock().await;
+
+                for item in response.peer_list {
+                    let Ok(ip_addr) = item.host.parse::<IpAddr>() else {
+                        continue;
+                    };
+                    let addr = SocketAddr::new(ip_addr, item.port);
+                    state.address_book.add(addr, item.timestamp);
+                    addrs.insert(addr);
+                }
+
+                break;
+            }
+        }
+
+        addrs
+    }
+
+    async fn connect_candidates(
+        &self,
+        candidates: &mut HashSet<SocketAddr>,
+        sender: &mpsc::Sender<(SocketAddr, Message)>,
+        notify: &Arc<Notify>,
+    ) {
+        let needed = {
+            let state = self.state.lock().await;
+            candidates.retain(|addr| {
+                !state.peers.contains_key(&addr.ip()) && !state.is_banned(&addr.ip())
+            });
+            self.options.target_peers.saturating_sub(state.peers.len())
+        };
+
+        let batch_size = needed.min(self.options.connection_batch_size);
+        let batch: Vec<SocketAddr> = candidates.iter().copied().take(batch_size).collect();
+
+        let mut futures = FuturesUnordered::new();
+
+        for socket_addr in batch {
+            candidates.remove(&socket_addr);
+
+            futures.push(async move {
+                let result = tokio::time::timeout(
+                    self.options.connection_timeout,
+                    self.connect(socket_addr, self.options.peer_options),
+                )
+                .await;
+                (socket_addr, result)
+            });
+        }
+
+        while let Some((socket_addr, result)) = futures.next().await {
+            let receiver = match result {
+                Ok(Ok(receiver)) => receiver,
+                Ok(Err(error)) => {
+                    debug!("Failed to connect to peer {socket_addr}: {error}");
+                    self.state
+                        .lock()
+                        .await
+                        .address_book
+                        .mark_failure(socket_addr, now());
+                    continue;
+                }
+                Err(_timeout) => {
+                    debug!("Timeout connecting to peer {socket_addr}");
+                    self.state
+                        .lock()
+                        .await
+                        .address_book
+                        .mark_failure(socket_addr, now());
+                    continue;
+                }
+            };
+
+            info!("Connected to peer {socket_addr}");
+
+            self.forward_messages(socket_addr, receiver, sender.clone(), notify.clone());
+        }
+    }
+
+    fn forward_messages(
+        &self,
+        socket_addr: SocketAddr,
+        mut receiver: mpsc::Receiver<Message>,
+        sender: mpsc::Sender<(SocketAddr, Message)>,
+        notify: Arc<Notify>,
+    ) {
+        let state = self.state.clone();
+
+        tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                if sender.send((socket_addr, message)).await.is_err() {
+                    return;
+                }
+            }
+
+            info!("Peer {socket_addr} disconnected");
+
+            // The receiver only closes once the peer's inbound task is done with it, so the peer is
+            // removed even if the task hasn't been marked as finished yet.
+            {
+                let mut state = state.lock().await;
+                let peer = state.peers.get(&socket_addr.ip()).cloned();
+
+                if let Some(peer) = peer.filter(|peer| peer.socket_addr() == socket_addr) {
+                    state.remove_disconnected(&peer);
+                }
+            }
+
+            notify.notify_one();
+        });
+    }
 }
 
 impl ClientState {
@@ -95,43 +525,464 @@ impl ClientState {
         self.peers.values()
     }
 
+    /// The connected peers with the highest reputation, up to the given count.
+    pub fn best_peers(&self, count: usize) -> Vec<Peer> {
+        let mut peers: Vec<Peer> = self.peers.values().cloned().collect();
+        peers.sort_by_key(|peer| std::cmp::Reverse(self.reputation(&peer.socket_addr().ip())));
+        peers.truncate(count);
+        peers
+    }
+
     pub fn disconnect(&mut self, ip_addr: &IpAddr) -> bool {
         self.peers.remove(ip_addr).is_some()
     }
 
+    /// Removes a peer which has disconnected from the pool, and bans it if it was disconnected for
+    /// exceeding the inbound rate limits. A newer connection to the same IP address is left alone.
+    fn remove_disconnected(&mut self, peer: &Peer) {
+        let socket_addr = peer.socket_addr();
+        let ip_addr = socket_addr.ip();
+
+        if self
+            .peers
+            .get(&ip_addr)
+            .map_or(true, |existing| existing.socket_addr() != socket_addr)
+        {
+            return;
+        }
+
+        self.peers.remove(&ip_addr);
+
+        if let Some(msg_type) = peer.rate_limit_violation() {
+            warn!("Banning peer {socket_addr} for exceeding the rate limit of {msg_type:?}");
+            self.ban(ip_addr);
+        }
+    }
+
+    /// The addresses of peers that have been discovered, which can be saved between runs.
+    pub fn address_book(&self) -> &AddressBook {
+        &self.address_book
+    }
+
+    pub fn address_book_mut(&mut self) -> &mut AddressBook {
+        &mut self.address_book
+    }
+
     pub fn is_banned(&self, ip_addr: &IpAddr) -> bool {
-        self.banned_peers.contains_key(ip_addr)
+        self.banned_peers
+            .get(ip_addr)
+            .is_some_and(|&banned_until| banned_until > now())
+    }
+
+    /// The unix timestamp at which the ban on the peer expires, if it's currently banned.
+    pub fn banned_until(&self, ip_addr: &IpAddr) -> Option<u64> {
+        self.banned_peers
+            .get(ip_addr)
+            .copied()
+            .filter(|&banned_until| banned_until > now())
     }
 
     pub fn is_trusted(&self, ip_addr: &IpAddr) -> bool {
         self.trusted_peers.contains(ip_addr)
     }
 
+    /// Bans the peer for the default ban duration.
     pub fn ban(&mut self, ip_addr: IpAddr) -> bool {
+        self.ban_for(ip_addr, self.ban_duration)
+    }
+
+    pub fn ban_for(&mut self, ip_addr: IpAddr, duration: Duration) -> bool {
         if self.is_trusted(&ip_addr) {
             return false;
         }
 
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs();
+        let was_banned = self.is_banned(&ip_addr);
+        let banned_until = now() + duration.as_secs();
 
+        self.banned_peers
+            .retain(|_, &mut banned_until| banned_until > now());
         self.disconnect(&ip_addr);
-        self.banned_peers.insert(ip_addr, timestamp).is_none()
+        self.reputations.remove(&ip_addr);
+
+        let banned_until = self
+            .banned_peers
+            .get(&ip_addr)
+            .map_or(banned_until, |&existing| existing.max(banned_until));
+        self.banned_peers.insert(ip_addr, banned_until);
+
+        !was_banned
     }
 
     pub fn unban(&mut self, ip_addr: IpAddr) -> bool {
-        self.banned_peers.remove(&ip_addr).is_some()
+        let was_banned = self.is_banned(&ip_addr);
+        self.banned_peers.remove(&ip_addr);
+        was_banned
     }
 
     pub fn trust(&mut self, ip_addr: IpAddr) -> bool {
         let result = self.trusted_peers.insert(ip_addr);
         self.banned_peers.remove(&ip_addr);
+        self.reputations.remove(&ip_addr);
         result
     }
 
     pub fn untrust(&mut self, ip_addr: IpAddr) -> bool {
         self.trusted_peers.remove(&ip_addr)
     }
+
+    /// The current reputation of the peer, which starts out at [`INITIAL_REPUTATION`].
+    pub fn reputation(&self, ip_addr: &IpAddr) -> u32 {
+        self.reputations
+            .get(ip_addr)
+            .copied()
+            .unwrap_or(INITIAL_REPUTATION)
+    }
+
+    /// Lowers the reputation of the peer, and bans it if the reputation drops to the ban threshold.
+    /// Returns `true` if the peer was banned as a result.
+    pub fn penalize(&mut self, ip_addr: IpAddr, penalty: PeerPenalty) -> bool {
+        if self.is_trusted(&ip_addr) {
+            return false;
+        }
+
+        let reputation = self.reputation(&ip_addr).saturating_sub(penalty.amount());
+
+        if reputation > self.ban_threshold {
+            self.reputations.insert(ip_addr, reputation);
+            return false;
+        }
+
+        self.ban(ip_addr)
+    }
+
+    /// Raises the reputation of the peer by [`REPUTATION_RECOVERY`], up to [`INITIAL_REPUTATION`].
+    pub fn recover(&mut self, ip_addr: IpAddr) {
+        let Some(reputation) = self.reputations.get_mut(&ip_addr) else {
+            return;
+        };
+
+        *reputation = reputation.saturating_add(REPUTATION_RECOVERY);
+
+        if *reputation >= INITIAL_REPUTATION {
+            self.reputations.remove(&ip_addr);
+        }
+    }
+
+    /// Penalizes the peer if the error indicates that it misbehaved.
+    /// Returns `true` if the peer was banned as a result.
+    pub fn penalize_error(&mut self, ip_addr: IpAddr, error: &ClientError) -> bool {
+        let Some(penalty) = PeerPenalty::from_error(error) else {
+            return false;
+        };
+        self.penalize(ip_addr, penalty)
+    }
+}
+
+impl Default for ClientState {
+    fn default() -> Self {
+        Self {
+            peers: HashMap::new(),
+            banned_peers: HashMap::new(),
+            trusted_peers: HashSet::new(),
+            reputations: HashMap::new(),
+            ban_duration: DEFAULT_BAN_DURATION,
+            ban_threshold: 0,
+            address_book: AddressBook::new(),
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const IP_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    #[test]
+    fn test_ban_expiry() {
+        let mut state = ClientState::default();
+
+        assert!(state.ban_for(IP_ADDR, Duration::ZERO));
+        assert!(!state.is_banned(&IP_ADDR));
+
+        assert!(state.ban(IP_ADDR));
+        assert!(state.is_banned(&IP_ADDR));
+        assert!(!state.ban(IP_ADDR));
+
+        assert!(state.unban(IP_ADDR));
+        assert!(!state.is_banned(&IP_ADDR));
+    }
+
+    #[test]
+    fn test_reputation() {
+        let mut state = ClientState::default();
+
+        assert!(!state.penalize(IP_ADDR, PeerPenalty::InvalidResponse));
+        assert_eq!(state.reputation(&IP_ADDR), 50);
+
+        assert!(!state.penalize_error(
+            IP_ADDR,
+            &ClientError::Timeout(chia_protocol::ProtocolMessageTypes::RequestPeers)
+        ));
+        assert_eq!(state.reputation(&IP_ADDR), 30);
+
+        assert!(state.penalize(IP_ADDR, PeerPenalty::InvalidResponse));
+        assert!(state.is_banned(&IP_ADDR));
+        assert_eq!(state.reputation(&IP_ADDR), INITIAL_REPUTATION);
+    }
+
+    #[test]
+    fn test_reputation_recovery() {
+        let mut state = ClientState::default();
+
+        assert!(!state.penalize(IP_ADDR, PeerPenalty::Timeout));
+        assert_eq!(state.reputation(&IP_ADDR), 80);
+
+        for _ in 0..10 {
+            state.recover(IP_ADDR);
+        }
+        assert_eq!(state.reputation(&IP_ADDR), 90);
+
+        // Reputation never recovers past where it started.
+        for _ in 0..20 {
+            state.recover(IP_ADDR);
+        }
+        assert_eq!(state.reputation(&IP_ADDR), INITIAL_REPUTATION);
+
+        // A peer that keeps timing out is still banned, as long as it fails more than it succeeds.
+        for _ in 0..10 {
+            state.penalize(IP_ADDR, PeerPenalty::Timeout);
+            state.recover(IP_ADDR);
+        }
+        assert!(state.is_banned(&IP_ADDR));
+    }
+
+    #[test]
+    fn test_rejection_penalty() {
+        let mut state = ClientState {
+            ban_threshold: 80,
+            ..Default::default()
+        };
+
+        let rejection = ClientError::from(chia_protocol::RejectCoinState::new(
+            chia_protocol::RejectStateReason::ExceededSubscriptionLimit,
+        ));
+        assert_eq!(
+            PeerPenalty::from_error(&rejection),
+            Some(PeerPenalty::RejectedRequest)
+        );
+        assert!(!state.penalize_error(IP_ADDR, &rejection));
+        assert_eq!(state.reputation(&IP_ADDR), 90);
+
+        let rejection = ClientError::from(chia_protocol::RejectHeaderRequest::new(10));
+        assert!(state.penalize_error(IP_ADDR, &rejection));
+        assert!(state.is_banned(&IP_ADDR));
+    }
+
+    #[test]
+    fn test_trusted_exempt() {
+        let mut state = ClientState::default();
+        state.trust(IP_ADDR);
+
+        assert!(!state.ban(IP_ADDR));
+        assert!(!state.penalize(IP_ADDR, PeerPenalty::Custom(INITIAL_REPUTATION)));
+        assert!(!state.is_banned(&IP_ADDR));
+        assert_eq!(state.reputation(&IP_ADDR), INITIAL_REPUTATION);
+    }
+
+    #[cfg(feature = "native-tls")]
+    mod discovery {
+        use chia_protocol::{Bytes32, NewPeakWallet};
+        use chia_traits::Streamable;
+
+        use crate::{
+            test_server::{test_connector, EmptyHandler, TestServer, NETWORK_ID},
+            ServerOptions, MAX_CONNECTION_FAILURES,
+        };
+
+        use super::*;
+
+        fn test_client(target_peers: usize) -> Result<Client, ClientError> {
+            let network = Network {
+                dns_introducers: Vec::new(),
+                introducers: Vec::new(),
+                ..Network::default_testnet11()
+            };
+
+            Ok(Client::with_options(
+                NETWORK_ID.to_string(),
+                network,
+                test_connector()?,
+                ClientOptions {
+                    target_peers,
+                    discovery_interval: Duration::from_millis(100),
+                    ..Default::default()
+                },
+            ))
+        }
+
+        async fn wait_for_peers(client: &Client, expected: &[SocketAddr]) {
+            tokio::time::timeout(Duration::from_secs(10), async {
+                loop {
+                    let mut addrs: Vec<SocketAddr> =
+                        client.lock().await.peers().map(Peer::socket_addr).collect();
+                    addrs.sort();
+
+                    if addrs == expected {
+                        return;
+                    }
+
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("timed out waiting for the peer pool");
+        }
+
+        #[tokio::test]
+        async fn test_discovery_fills_pool() -> Result<(), ClientError> {
+            let first = TestServer::start("127.0.0.1:0", EmptyHandler).await?;
+
+            // The pool only keeps one peer per IP address, so the second peer needs its own.
+            // Only 127.0.0.1 is available on some systems, such as macOS.
+            let Ok(second) = TestServer::start("127.0.0.2:0", EmptyHandler).await else {
+                eprintln!("Skipping test, since 127.0.0.2 isn't available");
+                return Ok(());
+            };
+
+            let client = test_client(2)?;
+            {
+                let mut state = client.lock().await;
+                state.address_book_mut().add(first.addr, 0);
+                state.address_book_mut().add(second.addr, 0);
+            }
+
+            let _receiver = client.spawn_discovery();
+            wait_for_peers(&client, &[first.addr, second.addr]).await;
+
+            let state = client.lock().await;
+            assert!(state
+                .address_book()
+                .get(&first.addr)
+                .is_some_and(|entry| entry.last_success.is_some()));
+            assert!(state
+                .address_book()
+                .get(&second.addr)
+                .is_some_and(|entry| entry.last_success.is_some()));
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_discovery_replaces_disconnected_peers() -> Result<(), ClientError> {
+            let first = TestServer::start("127.0.0.1:0", EmptyHandler).await?;
+            let second = TestServer::start("127.0.0.1:0", EmptyHandler).await?;
+
+            let client = test_client(1)?;
+            client.lock().await.address_book_mut().add(first.addr, 0);
+
+            let _receiver = client.spawn_discovery();
+            wait_for_peers(&client, &[first.addr]).await;
+
+            client.lock().await.address_book_mut().add(second.addr, 0);
+            first.wait_for_peer().await;
+            first.stop();
+
+            wait_for_peers(&client, &[second.addr]).await;
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_connection_resets_failures() -> Result<(), ClientError> {
+            let server = TestServer::start("127.0.0.1:0", EmptyHandler).await?;
+
+            let client = test_client(1)?;
+            {
+                let mut state = client.lock().await;
+                state.address_book_mut().add(server.addr, 0);
+                for _ in 1..MAX_CONNECTION_FAILURES {
+                    state.address_book_mut().mark_failure(server.addr, now());
+                }
+            }
+
+            let _receiver = client.spawn_discovery();
+            wait_for_peers(&client, &[server.addr]).await;
+
+            let state = client.lock().await;
+            let entry = state.address_book().get(&server.addr).copied();
+            assert_eq!(entry.map(|entry| entry.failures), Some(0));
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_discovery_forwards_messages() -> Result<(), ClientError> {
+            let server = TestServer::start("127.0.0.1:0", EmptyHandler).await?;
+
+            let client = test_client(1)?;
+            client.lock().await.address_book_mut().add(server.addr, 0);
+
+            let mut receiver = client.spawn_discovery();
+            wait_for_peers(&client, &[server.addr]).await;
+
+            let new_peak = NewPeakWallet::new(Bytes32::default(), 10, 100, 10);
+            server.wait_for_peer().await.send(new_peak.clone()).await?;
+
+            let (socket_addr, message) = receiver.recv().await.expect("discovery stopped");
+            assert_eq!(socket_addr, server.addr);
+            assert_eq!(
+                message.msg_type,
+                chia_protocol::ProtocolMessageTypes::NewPeakWallet
+            );
+            assert_eq!(NewPeakWallet::from_bytes(&message.data)?, new_peak);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_rate_limit_ban() -> Result<(), ClientError> {
+            // The server sends faster than the client accepts messages.
+            let mut options = ServerOptions::new(NETWORK_ID.to_string(), NodeType::FullNode);
+            options.rate_limit_factor = 10.0;
+            let server =
+                TestServer::start_with_options("127.0.0.1:0", EmptyHandler, options).await?;
+
+            // The peer is connected directly rather than through discovery, and the messages are
+            // consumed without being forwarded anywhere.
+            let client = test_client(1)?;
+            let mut receiver = client.connect(server.addr, PeerOptions::default()).await?;
+            tokio::spawn(async move { while receiver.recv().await.is_some() {} });
+
+            let peer = server.wait_for_peer().await;
+
+            for height in 0..300 {
+                let new_peak = NewPeakWallet::new(Bytes32::default(), height, 100, height);
+                if peer.send(new_peak).await.is_err() {
+                    break;
+                }
+            }
+
+            tokio::time::timeout(Duration::from_secs(10), async {
+                while !client.lock().await.is_banned(&server.addr.ip()) {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("peer wasn't banned");
+
+            assert_eq!(client.lock().await.peers().count(), 0);
+
+            Ok(())
+        }
+    }
 }
@@ -1,12 +1,11 @@
 use std::net::SocketAddr;
 
-use chia_protocol::{Handshake, Message, NodeType, ProtocolMessageTypes};
-use chia_traits::Streamable;
+use chia_protocol::{Message, NodeType};
 use tokio::sync::mpsc;
 use tokio_tungstenite::Connector;
 use tracing::instrument;
 
-use crate::{ClientError, Peer, PeerOptions};
+use crate::{ClientError, Peer, PeerOptions, Proxy};
 
 #[instrument(skip(connector))]
 pub async fn connect_peer(
@@ -15,48 +14,69 @@ pub async fn connect_peer(
     socket_addr: SocketAddr,
     options: PeerOptions,
 ) -> Result<(Peer, mpsc::Receiver<Message>), ClientError> {
-    let (peer, mut receiver) = Peer::connect(socket_addr, connector, options).await?;
-
-    peer.send(Handshake {
-        network_id: network_id.clone(),
-        protocol_version: "0.0.37".to_string(),
-        software_version: "0.0.0".to_string(),
-        server_port: 0,
-        node_type: NodeType::Wallet,
-        capabilities: vec![
-            (1, "1".to_string()),
-            (2, "1".to_string()),
-            (3, "1".to_string()),
-        ],
-    })
-    .await?;
-
-    let Some(message) = receiver.recv().await else {
-        return Err(ClientError::MissingHandshake);
-    };
-
-    if message.msg_type != ProtocolMessageTypes::Handshake {
-        return Err(ClientError::InvalidResponse(
-            vec![ProtocolMessageTypes::Handshake],
-            message.msg_type,
-        ));
-    }
-
-    let handshake = Handshake::from_bytes(&message.data)?;
+    connect_node(
+        network_id,
+        connector,
+        socket_addr,
+        options,
+        NodeType::FullNode,
+        None,
+    )
+    .await
+}
 
-    if handshake.node_type != NodeType::FullNode {
-        return Err(ClientError::WrongNodeType(
-            NodeType::FullNode,
-            handshake.node_type,
-        ));
-    }
+/// Connects to a full node through a proxy, and performs the handshake.
+#[instrument(skip(connector))]
+pub async fn connect_peer_with_proxy(
+    network_id: String,
+    connector: Connector,
+    socket_addr: SocketAddr,
+    options: PeerOptions,
+    proxy: &Proxy,
+) -> Result<(Peer, mpsc::Receiver<Message>), ClientError> {
+    connect_node(
+        network_id,
+        connector,
+        socket_addr,
+        options,
+        NodeType::FullNode,
+        Some(proxy),
+    )
+    .await
+}
 
-    if handshake.network_id != network_id {
-        return Err(ClientError::WrongNetwork(
-            network_id.to_string(),
-            handshake.network_id,
-        ));
-    }
+/// Connects to an introducer, which can be asked for the addresses of full nodes.
+#[instrument(skip(connector))]
+pub async fn connect_introducer(
+    network_id: String,
+    connector: Connector,
+    socket_addr: SocketAddr,
+    options: PeerOptions,
+) -> Result<(Peer, mpsc::Receiver<Message>), ClientError> {
+    connect_node(
+        network_id,
+        connector,
+        socket_addr,
+        options,
+        NodeType::Introducer,
+        None,
+    )
+    .await
+}
 
+pub(crate) async fn connect_node(
+    network_id: String,
+    connector: Connector,
+    socket_addr: SocketAddr,
+    options: PeerOptions,
+    node_type: NodeType,
+    proxy: Option<&Proxy>,
+) -> Result<(Peer, mpsc::Receiver<Message>), ClientError> {
+    let (peer, mut receiver) = match proxy {
+        Some(proxy) => Peer::connect_with_proxy(socket_addr, connector, options, proxy).await?,
+        None => Peer::connect(socket_addr, connector, options).await?,
+    };
+    peer.perform_handshake(&mut receiver, &network_id, node_type)
+        .await?;
     Ok((peer, receiver))
 }
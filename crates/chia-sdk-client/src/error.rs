@@ -1,7 +1,14 @@
-use chia_protocol::{NodeType, ProtocolMessageTypes};
+use chia_consensus::gen::validation_error::ErrorCode;
+use chia_protocol::{
+    Bytes32, ChiaProtocolMessage, NodeType, ProtocolMessageTypes, RejectAdditionsRequest,
+    RejectBlockHeaders, RejectCoinState, RejectHeaderRequest, RejectPuzzleSolution,
+    RejectPuzzleState, RejectRemovalsRequest, RejectStateReason,
+};
 use thiserror::Error;
 use tokio::sync::oneshot::error::RecvError;
 
+use crate::Capability;
+
 #[derive(Debug, Error)]
 pub enum ClientError {
     #[error("SSL error: {0}")]
@@ -44,15 +51,108 @@ pub enum ClientError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
+    #[error("YAML error: {0}")]
+    Yaml(#[from] serde_yaml_ng::Error),
+
+    #[error("Proxy error: {0}")]
+    Proxy(String),
+
     #[error("Missing response during handshake")]
     MissingHandshake,
 
     #[error("Expected node type {0:?}, but found {1:?}")]
     WrongNodeType(NodeType, NodeType),
 
+    #[error("Peers with node type {0:?} are not accepted")]
+    UnexpectedNodeType(NodeType),
+
     #[error("Expected network {0}, but found {1}")]
     WrongNetwork(String, String),
 
+    #[error("Peer with protocol version {1} does not support {0:?} requests")]
+    UnsupportedRequest(ProtocolMessageTypes, String),
+
+    #[error("Peer does not support {0:?} requests, which require the {1:?} capability")]
+    MissingCapability(ProtocolMessageTypes, Capability),
+
+    #[error("Invalid network config: {0}")]
+    InvalidNetworkConfig(String),
+
     #[error("The peer is banned")]
     BannedPeer,
+
+    #[error("Request of type {0:?} timed out")]
+    Timeout(ProtocolMessageTypes),
+
+    #[error("Received {0:?} message which exceeded the inbound rate limit")]
+    RateLimitExceeded(ProtocolMessageTypes),
+
+    #[error("Puzzle state request was rejected: {0:?}")]
+    PuzzleStateRejected(RejectStateReason),
+
+    #[error("Failed to run the spend bundle: {0:?}")]
+    InvalidSpendBundle(ErrorCode),
+
+    #[error("Fee estimate failed: {0}")]
+    FeeEstimate(String),
+
+    #[error("Invalid address book entry on line {0}")]
+    InvalidAddressBook(usize),
+
+    #[error("Coin state request was rejected: {0:?}")]
+    CoinStateRejected(RejectStateReason),
+
+    #[error("Only {0} peers agreed on the response, but {1} are required")]
+    NoQuorum(usize, usize),
+
+    #[error("No peers are connected")]
+    NoPeers,
+
+    #[error("Block at height {0} is not a transaction block")]
+    NotTransactionBlock(u32),
+
+    #[error("Expected data for block {0}, but found block {1}")]
+    WrongBlock(Bytes32, Bytes32),
+
+    #[error("Additions for block at height {0} don't match the additions root")]
+    InvalidAdditions(u32),
+
+    #[error("Removals for block at height {0} don't match the removals root")]
+    InvalidRemovals(u32),
+
+    #[error("Invalid session recording")]
+    InvalidRecording,
+
+    #[error("Request was rejected with {0:?}")]
+    Rejected(ProtocolMessageTypes),
 }
+
+impl From<RejectPuzzleState> for ClientError {
+    fn from(rejection: RejectPuzzleState) -> Self {
+        Self::PuzzleStateRejected(rejection.reason)
+    }
+}
+
+impl From<RejectCoinState> for ClientError {
+    fn from(rejection: RejectCoinState) -> Self {
+        Self::CoinStateRejected(rejection.reason)
+    }
+}
+
+macro_rules! impl_rejected {
+    ( $( $rejection:ident ),* $(,)? ) => {
+        $( impl From<$rejection> for ClientError {
+            fn from(_rejection: $rejection) -> Self {
+                Self::Rejected($rejection::msg_type())
+            }
+        } )*
+    };
+}
+
+impl_rejected!(
+    RejectPuzzleSolution,
+    RejectHeaderRequest,
+    RejectBlockHeaders,
+    RejectAdditionsRequest,
+    RejectRemovalsRequest,
+);
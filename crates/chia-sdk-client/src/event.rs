@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+
+use chia_protocol::{CoinStateUpdate, Message, NewPeakWallet, ProtocolMessageTypes};
+use chia_traits::Streamable;
+use tokio::sync::{broadcast, mpsc};
+use tracing::warn;
+
+use crate::ClientError;
+
+/// An unsolicited message sent by a peer, decoded into its typed form.
+///
+/// There are no mempool events, since the version of `chia-protocol` used here doesn't define
+/// the wallet protocol's mempool messages (such as `MempoolItemsAdded`), so they can't be
+/// decoded or requested.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerEvent {
+    CoinStateUpdate(CoinStateUpdate),
+    NewPeakWallet(NewPeakWallet),
+    /// A message that doesn't have a typed representation.
+    Other(Message),
+}
+
+impl PeerEvent {
+    pub fn from_message(message: Message) -> Result<Self, ClientError> {
+        Ok(match message.msg_type {
+            ProtocolMessageTypes::CoinStateUpdate => {
+                Self::CoinStateUpdate(CoinStateUpdate::from_bytes(&message.data)?)
+            }
+            ProtocolMessageTypes::NewPeakWallet => {
+                Self::NewPeakWallet(NewPeakWallet::from_bytes(&message.data)?)
+            }
+            _ => Self::Other(message),
+        })
+    }
+
+    pub fn msg_type(&self) -> ProtocolMessageTypes {
+        match self {
+            Self::CoinStateUpdate(..) => ProtocolMessageTypes::CoinStateUpdate,
+            Self::NewPeakWallet(..) => ProtocolMessageTypes::NewPeakWallet,
+            Self::Other(message) => message.msg_type,
+        }
+    }
+}
+
+/// Decodes the messages received from a peer and broadcasts them to any number of subscribers.
+#[derive(Debug, Clone)]
+pub struct PeerEventRouter {
+    sender: broadcast::Sender<PeerEvent>,
+}
+
+impl PeerEventRouter {
+    /// Spawns a task which routes messages from the receiver until the peer disconnects.
+    /// Each subscriber can buffer up to `capacity` events before it starts missing them,
+    /// and the capacity is at least 1.
+    pub fn spawn(mut receiver: mpsc::Receiver<Message>, capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        let router = Self {
+            sender: sender.clone(),
+        };
+
+        tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                let msg_type = message.msg_type;
+
+                match PeerEvent::from_message(message) {
+                    Ok(event) => {
+                        sender.send(event).ok();
+                    }
+                    Err(error) => {
+                        warn!("Failed to decode {msg_type:?} message: {error}");
+                    }
+                }
+            }
+        });
+
+        router
+    }
+
+    /// Subscribes to every event.
+    pub fn subscribe(&self) -> PeerEventSubscription {
+        PeerEventSubscription {
+            receiver: self.sender.subscribe(),
+            msg_types: None,
+        }
+    }
+
+    /// Subscribes to only the events with the given message types.
+    pub fn subscribe_to(
+        &self,
+        msg_types: impl IntoIterator<Item = ProtocolMessageTypes>,
+    ) -> PeerEventSubscription {
+        PeerEventSubscription {
+            receiver: self.sender.subscribe(),
+            msg_types: Some(msg_types.into_iter().collect()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PeerEventSubscription {
+    receiver: broadcast::Receiver<PeerEvent>,
+    msg_types: Option<HashSet<ProtocolMessageTypes>>,
+}
+
+impl PeerEventSubscription {
+    /// Waits for the next matching event, or returns `None` once the peer has disconnected.
+    /// If the subscriber falls too far behind, the oldest events are skipped.
+    pub async fn recv(&mut self) -> Option<PeerEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) if self.matches(&event) => return Some(event),
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!("Peer event subscriber lagged behind by {count} events");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Returns the next matching event if one is immediately available.
+    pub fn try_recv(&mut self) -> Option<PeerEvent> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) if self.matches(&event) => return Some(event),
+                Ok(_) => {}
+                Err(broadcast::error::TryRecvError::Lagged(count)) => {
+                    warn!("Peer event subscriber lagged behind by {count} events");
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+
+    fn matches(&self, event: &PeerEvent) -> bool {
+        self.msg_types
+            .as_ref()
+            .map_or(true, |msg_types| msg_types.contains(&event.msg_type()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chia_protocol::Bytes32;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_zero_capacity() -> Result<(), ClientError> {
+        let (sender, receiver) = mpsc::channel(1);
+        let router = PeerEventRouter::spawn(receiver, 0);
+        let mut subscription = router.subscribe();
+
+        let new_peak = NewPeakWallet::new(Bytes32::default(), 1, 1, 0);
+        sender
+            .send(Message {
+                msg_type: ProtocolMessageTypes::NewPeakWallet,
+                id: None,
+                data: new_peak.to_bytes()?.into(),
+            })
+            .await
+            .ok();
+
+        assert_eq!(
+            subscription.recv().await,
+            Some(PeerEvent::NewPeakWallet(new_peak))
+        );
+
+        Ok(())
+    }
+}
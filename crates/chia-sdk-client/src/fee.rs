@@ -0,0 +1,112 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chia_consensus::{
+    consensus_constants::ConsensusConstants, gen::owned_conditions::OwnedSpendBundleConditions,
+    spendbundle_conditions::get_conditions_from_spendbundle,
+};
+use chia_protocol::{FeeEstimateGroup, SpendBundle};
+use clvmr::Allocator;
+
+use crate::{ClientError, Peer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeSuggestion {
+    /// How long after the request the transaction is expected to be confirmed.
+    pub time_target: Duration,
+    pub mojos_per_clvm_cost: u64,
+    /// The total fee in mojos, for the cost that the suggestion was made for.
+    pub fee: u64,
+}
+
+/// Calculates the CLVM cost of a spend bundle, without validating its signature.
+pub fn spend_bundle_cost(
+    spend_bundle: &SpendBundle,
+    constants: &ConsensusConstants,
+    height: u32,
+) -> Result<u64, ClientError> {
+    Ok(spend_bundle_conditions(spend_bundle, constants, height)?.cost)
+}
+
+/// Runs the spend bundle to get its conditions, without validating its signature.
+pub(crate) fn spend_bundle_conditions(
+    spend_bundle: &SpendBundle,
+    constants: &ConsensusConstants,
+    height: u32,
+) -> Result<OwnedSpendBundleConditions, ClientError> {
+    let mut allocator = Allocator::new();
+
+    let conditions = get_conditions_from_spendbundle(
+        &mut allocator,
+        spend_bundle,
+        constants.max_block_cost_clvm,
+        height,
+        constants,
+    )
+    .map_err(|error| ClientError::InvalidSpendBundle(error.1))?;
+
+    Ok(OwnedSpendBundleConditions::from(&allocator, conditions))
+}
+
+/// Combines fee estimates with a cost, to suggest the fee for each time target.
+/// The time targets must be the same ones that the estimates were requested for.
+pub fn suggest_fees(
+    cost: u64,
+    time_targets: &[Duration],
+    estimates: &FeeEstimateGroup,
+) -> Result<Vec<FeeSuggestion>, ClientError> {
+    if let Some(error) = &estimates.error {
+        return Err(ClientError::FeeEstimate(error.clone()));
+    }
+
+    if estimates.estimates.len() != time_targets.len() {
+        return Err(ClientError::FeeEstimate(format!(
+            "expected {} estimates, but received {}",
+            time_targets.len(),
+            estimates.estimates.len()
+        )));
+    }
+
+    time_targets
+        .iter()
+        .zip(&estimates.estimates)
+        .map(|(&time_target, estimate)| {
+            if let Some(error) = &estimate.error {
+                return Err(ClientError::FeeEstimate(error.clone()));
+            }
+
+            let mojos_per_clvm_cost = estimate.estimated_fee_rate.mojos_per_clvm_cost;
+
+            Ok(FeeSuggestion {
+                time_target,
+                mojos_per_clvm_cost,
+                fee: cost.saturating_mul(mojos_per_clvm_cost),
+            })
+        })
+        .collect()
+}
+
+impl Peer {
+    /// Asks the peer for fee estimates, and suggests the fee for a transaction with the given cost
+    /// to be confirmed within each of the time targets.
+    pub async fn suggest_fees(
+        &self,
+        cost: u64,
+        time_targets: &[Duration],
+    ) -> Result<Vec<FeeSuggestion>, ClientError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time is before the unix epoch")
+            .as_secs();
+
+        let response = self
+            .request_fee_estimates(
+                time_targets
+                    .iter()
+                    .map(|time_target| now + time_target.as_secs())
+                    .collect(),
+            )
+            .await?;
+
+        suggest_fees(cost, time_targets, &response.estimates)
+    }
+}
@@ -0,0 +1,13 @@
+use chia_protocol::TimestampedPeerInfo;
+use chia_streamable_macro::streamable;
+
+// The introducer protocol messages aren't defined in `chia-protocol`.
+
+#[streamable(message)]
+#[derive(Copy)]
+pub struct RequestPeersIntroducer {}
+
+#[streamable(message)]
+pub struct RespondPeersIntroducer {
+    peer_list: Vec<TimestampedPeerInfo>,
+}
@@ -1,27 +1,62 @@
+mod address_book;
+mod block_proof;
+mod broadcast;
+mod capability;
 mod error;
+mod event;
+mod fee;
+mod introducer;
+mod metrics;
 mod network;
 mod peer;
+mod proxy;
 mod rate_limiter;
 mod rate_limits;
+mod recording;
 mod request_map;
+mod server;
+mod sync;
 mod tls;
 
+pub use address_book::*;
+pub use block_proof::*;
+pub use broadcast::*;
+pub use capability::*;
 pub use error::*;
+pub use event::*;
+pub use fee::*;
+pub use introducer::*;
+pub use metrics::*;
 pub use network::*;
 pub use peer::*;
+pub use proxy::*;
 pub use rate_limiter::*;
 pub use rate_limits::*;
+pub use recording::*;
+pub use server::*;
+pub use sync::*;
 pub use tls::*;
 
 #[cfg(any(feature = "native-tls", feature = "rustls"))]
 mod client;
 #[cfg(any(feature = "native-tls", feature = "rustls"))]
 mod connect;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+mod quorum;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+mod resilient_peer;
 
 #[cfg(any(feature = "native-tls", feature = "rustls"))]
 pub use client::*;
 #[cfg(any(feature = "native-tls", feature = "rustls"))]
 pub use connect::*;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+pub use quorum::*;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+pub use resilient_peer::*;
 
 #[cfg(any(feature = "native-tls", feature = "rustls"))]
 pub use tokio_tungstenite::Connector;
+
+#[cfg(test)]
+mod test_server;
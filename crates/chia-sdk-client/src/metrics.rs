@@ -0,0 +1,221 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use chia_protocol::ProtocolMessageTypes;
+
+/// The upper bounds of the request latency histogram buckets, in milliseconds.
+/// Latencies above the last bound are counted in an additional overflow bucket.
+pub const LATENCY_BUCKETS_MS: [u64; 10] = [10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MessageCounts {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+impl MessageCounts {
+    fn record(&mut self, bytes: usize) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    buckets: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let index = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| latency <= Duration::from_millis(bound))
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+
+        self.buckets[index] += 1;
+        self.count += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(self.total / u32::try_from(self.count).unwrap_or(u32::MAX))
+    }
+
+    /// The number of latencies in each bucket, by its upper bound.
+    /// The overflow bucket has no upper bound.
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        LATENCY_BUCKETS_MS
+            .iter()
+            .map(|&bound| Some(Duration::from_millis(bound)))
+            .chain([None])
+            .zip(self.buckets.iter().copied())
+    }
+
+    /// The upper bound of the bucket which contains the given quantile, between 0 and 1.
+    /// If the quantile falls in the overflow bucket, the maximum latency is returned instead.
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let target = ((self.count as f64 * quantile.clamp(0.0, 1.0)).ceil() as u64).max(1);
+        let mut seen = 0;
+
+        for (bound, count) in self.buckets() {
+            seen += count;
+            if seen >= target {
+                return Some(bound.unwrap_or(self.max));
+            }
+        }
+
+        Some(self.max)
+    }
+}
+
+/// A snapshot of the traffic and request statistics of a single peer connection.
+#[derive(Debug, Default, Clone)]
+pub struct PeerMetrics {
+    pub inbound: HashMap<ProtocolMessageTypes, MessageCounts>,
+    pub outbound: HashMap<ProtocolMessageTypes, MessageCounts>,
+    /// The time between sending each request and receiving its response, by request type.
+    pub latency: HashMap<ProtocolMessageTypes, LatencyHistogram>,
+    pub timeouts: HashMap<ProtocolMessageTypes, u64>,
+    /// How many outbound messages had to wait for the outbound rate limit.
+    pub outbound_rate_limited: u64,
+    /// How many inbound messages exceeded the inbound rate limit.
+    pub inbound_rate_limited: u64,
+    /// Requests which are waiting for a response.
+    pub pending_requests: usize,
+    /// Requests which were abandoned, and whose late responses are still expected.
+    pub expired_requests: usize,
+}
+
+impl PeerMetrics {
+    pub fn total_inbound(&self) -> MessageCounts {
+        total(&self.inbound)
+    }
+
+    pub fn total_outbound(&self) -> MessageCounts {
+        total(&self.outbound)
+    }
+
+    /// The latencies of all requests combined.
+    pub fn total_latency(&self) -> LatencyHistogram {
+        let mut total = LatencyHistogram::default();
+
+        for histogram in self.latency.values() {
+            for (index, count) in histogram.buckets.iter().enumerate() {
+                total.buckets[index] += count;
+            }
+            total.count += histogram.count;
+            total.total += histogram.total;
+            total.max = total.max.max(histogram.max);
+        }
+
+        total
+    }
+}
+
+fn total(counts: &HashMap<ProtocolMessageTypes, MessageCounts>) -> MessageCounts {
+    counts
+        .values()
+        .fold(MessageCounts::default(), |total, counts| MessageCounts {
+            messages: total.messages + counts.messages,
+            bytes: total.bytes + counts.bytes,
+        })
+}
+
+/// Collects metrics from both the peer handle and its inbound message task.
+#[derive(Debug, Default)]
+pub(crate) struct MetricsRecorder(Mutex<PeerMetrics>);
+
+impl MetricsRecorder {
+    pub(crate) fn snapshot(&self) -> PeerMetrics {
+        self.lock().clone()
+    }
+
+    pub(crate) fn inbound(&self, msg_type: ProtocolMessageTypes, bytes: usize) {
+        self.lock()
+            .inbound
+            .entry(msg_type)
+            .or_default()
+            .record(bytes);
+    }
+
+    pub(crate) fn outbound(&self, msg_type: ProtocolMessageTypes, bytes: usize) {
+        self.lock()
+            .outbound
+            .entry(msg_type)
+            .or_default()
+            .record(bytes);
+    }
+
+    pub(crate) fn latency(&self, msg_type: ProtocolMessageTypes, latency: Duration) {
+        self.lock()
+            .latency
+            .entry(msg_type)
+            .or_default()
+            .record(latency);
+    }
+
+    pub(crate) fn timeout(&self, msg_type: ProtocolMessageTypes) {
+        *self.lock().timeouts.entry(msg_type).or_default() += 1;
+    }
+
+    pub(crate) fn outbound_rate_limited(&self) {
+        self.lock().outbound_rate_limited += 1;
+    }
+
+    pub(crate) fn inbound_rate_limited(&self) {
+        self.lock().inbound_rate_limited += 1;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, PeerMetrics> {
+        self.0.lock().expect("metrics lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_histogram() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.mean(), None);
+        assert_eq!(histogram.quantile(0.5), None);
+
+        for millis in [5, 20, 20, 400, 30_000] {
+            histogram.record(Duration::from_millis(millis));
+        }
+
+        assert_eq!(histogram.count(), 5);
+        assert_eq!(histogram.max(), Duration::from_secs(30));
+        assert_eq!(histogram.mean(), Some(Duration::from_millis(6089)));
+        assert_eq!(histogram.quantile(0.5), Some(Duration::from_millis(25)));
+        assert_eq!(histogram.quantile(0.8), Some(Duration::from_millis(500)));
+        assert_eq!(histogram.quantile(1.0), Some(Duration::from_secs(30)));
+
+        let buckets: Vec<u64> = histogram.buckets().map(|(_, count)| count).collect();
+        assert_eq!(buckets, [1, 2, 0, 0, 0, 1, 0, 0, 0, 0, 1]);
+    }
+}
@@ -1,17 +1,80 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{collections::HashMap, fs, net::SocketAddr, path::Path, time::Duration};
 
+use chia_consensus::consensus_constants::ConsensusConstants;
 use chia_protocol::Bytes32;
-use chia_sdk_types::{MAINNET_CONSTANTS, TESTNET11_CONSTANTS};
+use chia_sdk_types::{default_constants, MAINNET_CONSTANTS, TESTNET11_CONSTANTS};
 use futures_util::{stream::FuturesUnordered, StreamExt};
+use serde::Deserialize;
 use tracing::{info, instrument, warn};
 
 use crate::ClientError;
 
+/// Everything needed to connect to a network, sign for it, and encode addresses on it.
+///
+/// The signer's constants can be derived from the consensus constants with
+/// `AggSigConstants::from(&network.constants)`.
 #[derive(Debug, Clone)]
 pub struct Network {
     pub default_port: u16,
     pub genesis_challenge: Bytes32,
     pub dns_introducers: Vec<String>,
+    /// The hostnames of introducer nodes, which listen on the default port.
+    pub introducers: Vec<String>,
+    pub address_prefix: String,
+    pub constants: ConsensusConstants,
+}
+
+/// The `network_overrides` section of a Chia `config.yaml` file, keyed by network name.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct NetworkOverrides {
+    #[serde(default)]
+    pub constants: HashMap<String, ConstantsOverrides>,
+    #[serde(default)]
+    pub config: HashMap<String, NetworkConfigOverrides>,
+}
+
+impl NetworkOverrides {
+    /// Reads the top level `network_overrides` section from a Chia `config.yaml` file.
+    pub fn from_config_yaml(path: impl AsRef<Path>) -> Result<Self, ClientError> {
+        #[derive(Deserialize)]
+        struct Config {
+            #[serde(default)]
+            network_overrides: NetworkOverrides,
+        }
+
+        let config: Config = serde_yaml_ng::from_str(&fs::read_to_string(path)?)?;
+        Ok(config.network_overrides)
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct NetworkConfigOverrides {
+    pub address_prefix: Option<String>,
+    pub default_full_node_port: Option<u16>,
+}
+
+/// Overrides for the consensus constants which are relevant to wallets.
+/// Hashes are hex encoded, and any other constants in the config are ignored.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub struct ConstantsOverrides {
+    pub genesis_challenge: Option<String>,
+    pub agg_sig_me_additional_data: Option<String>,
+    pub genesis_pre_farm_pool_puzzle_hash: Option<String>,
+    pub genesis_pre_farm_farmer_puzzle_hash: Option<String>,
+    pub min_plot_size: Option<u8>,
+    pub difficulty_constant_factor: Option<u128>,
+    pub difficulty_starting: Option<u64>,
+    pub epoch_blocks: Option<u32>,
+    pub sub_slot_iters_starting: Option<u64>,
+    pub mempool_block_buffer: Option<u8>,
+    pub max_block_cost_clvm: Option<u64>,
+    pub cost_per_byte: Option<u64>,
+    pub soft_fork5_height: Option<u32>,
+    pub hard_fork_height: Option<u32>,
+    pub plot_filter_128_height: Option<u32>,
+    pub plot_filter_64_height: Option<u32>,
+    pub plot_filter_32_height: Option<u32>,
 }
 
 impl Network {
@@ -25,6 +88,9 @@ impl Network {
                 "seeder.dexie.space".to_string(),
                 "chia.hoffmang.com".to_string(),
             ],
+            introducers: vec!["introducer.chia.net".to_string()],
+            address_prefix: "xch".to_string(),
+            constants: MAINNET_CONSTANTS.clone(),
         }
     }
 
@@ -33,7 +99,121 @@ impl Network {
             default_port: 58444,
             genesis_challenge: TESTNET11_CONSTANTS.genesis_challenge,
             dns_introducers: vec!["dns-introducer-testnet11.chia.net".to_string()],
+            introducers: vec!["introducer-testnet11.chia.net".to_string()],
+            address_prefix: "txch".to_string(),
+            constants: TESTNET11_CONSTANTS.clone(),
+        }
+    }
+
+    /// Creates a network from the overrides in a Chia config, such as the `selected_network`.
+    ///
+    /// Mainnet and testnet11 start out with their default values. Any other network starts out
+    /// with the mainnet constants and no introducers, and must override the genesis challenge.
+    pub fn from_overrides(name: &str, overrides: &NetworkOverrides) -> Result<Self, ClientError> {
+        let mut network = match name {
+            "mainnet" => Self::default_mainnet(),
+            "testnet11" => Self::default_testnet11(),
+            _ => {
+                let has_genesis_challenge = overrides
+                    .constants
+                    .get(name)
+                    .is_some_and(|constants| constants.genesis_challenge.is_some());
+
+                if !has_genesis_challenge {
+                    return Err(ClientError::InvalidNetworkConfig(format!(
+                        "missing GENESIS_CHALLENGE for network {name}"
+                    )));
+                }
+
+                Self {
+                    default_port: 8444,
+                    genesis_challenge: MAINNET_CONSTANTS.genesis_challenge,
+                    dns_introducers: Vec::new(),
+                    introducers: Vec::new(),
+                    address_prefix: "txch".to_string(),
+                    constants: MAINNET_CONSTANTS.clone(),
+                }
+            }
+        };
+
+        if let Some(constants) = overrides.constants.get(name) {
+            network.apply_constants(constants)?;
+        }
+
+        if let Some(config) = overrides.config.get(name) {
+            if let Some(address_prefix) = &config.address_prefix {
+                network.address_prefix.clone_from(address_prefix);
+            }
+
+            if let Some(port) = config.default_full_node_port {
+                network.default_port = port;
+            }
+        }
+
+        Ok(network)
+    }
+
+    fn apply_constants(&mut self, overrides: &ConstantsOverrides) -> Result<(), ClientError> {
+        let constants = &mut self.constants;
+
+        if let Some(genesis_challenge) = &overrides.genesis_challenge {
+            constants.genesis_challenge = parse_hash("GENESIS_CHALLENGE", genesis_challenge)?;
+        }
+
+        // The additional data for each kind of `AGG_SIG_*` condition is derived from this value.
+        if let Some(agg_sig_me) = &overrides.agg_sig_me_additional_data {
+            let agg_sig_me = parse_hash("AGG_SIG_ME_ADDITIONAL_DATA", agg_sig_me)?;
+            let derived = default_constants(constants.genesis_challenge, agg_sig_me);
+
+            constants.agg_sig_me_additional_data = derived.agg_sig_me_additional_data;
+            constants.agg_sig_parent_additional_data = derived.agg_sig_parent_additional_data;
+            constants.agg_sig_puzzle_additional_data = derived.agg_sig_puzzle_additional_data;
+            constants.agg_sig_amount_additional_data = derived.agg_sig_amount_additional_data;
+            constants.agg_sig_puzzle_amount_additional_data =
+                derived.agg_sig_puzzle_amount_additional_data;
+            constants.agg_sig_parent_amount_additional_data =
+                derived.agg_sig_parent_amount_additional_data;
+            constants.agg_sig_parent_puzzle_additional_data =
+                derived.agg_sig_parent_puzzle_additional_data;
+        }
+
+        if let Some(puzzle_hash) = &overrides.genesis_pre_farm_pool_puzzle_hash {
+            constants.genesis_pre_farm_pool_puzzle_hash =
+                parse_hash("GENESIS_PRE_FARM_POOL_PUZZLE_HASH", puzzle_hash)?;
+        }
+
+        if let Some(puzzle_hash) = &overrides.genesis_pre_farm_farmer_puzzle_hash {
+            constants.genesis_pre_farm_farmer_puzzle_hash =
+                parse_hash("GENESIS_PRE_FARM_FARMER_PUZZLE_HASH", puzzle_hash)?;
+        }
+
+        macro_rules! apply {
+            ( $( $field:ident ),* $(,)? ) => {
+                $( if let Some(value) = overrides.$field {
+                    constants.$field = value;
+                } )*
+            };
         }
+
+        apply!(
+            min_plot_size,
+            difficulty_constant_factor,
+            difficulty_starting,
+            epoch_blocks,
+            sub_slot_iters_starting,
+            mempool_block_buffer,
+            max_block_cost_clvm,
+            cost_per_byte,
+            soft_fork5_height,
+            hard_fork_height,
+            plot_filter_128_height,
+            plot_filter_64_height,
+            plot_filter_32_height,
+        );
+
+        self.genesis_challenge = constants.genesis_challenge;
+
+        Ok(())
     }
 
     #[instrument]
@@ -77,3 +257,136 @@ impl Network {
         Ok(result)
     }
 }
+
+fn parse_hash(name: &str, value: &str) -> Result<Bytes32, ClientError> {
+    let value = value.strip_prefix("0x").unwrap_or(value);
+
+    hex::decode(value)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .map(Bytes32::new)
+        .ok_or_else(|| ClientError::InvalidNetworkConfig(format!("invalid hash for {name}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_testnet11_overrides() -> Result<(), ClientError> {
+        let network = Network::from_overrides("testnet11", &NetworkOverrides::default())?;
+        assert_eq!(network.address_prefix, "txch");
+        assert_eq!(network.default_port, 58444);
+        assert_eq!(network.constants, *TESTNET11_CONSTANTS);
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_network_overrides() -> Result<(), ClientError> {
+        let genesis_challenge = Bytes32::new([1; 32]);
+
+        let overrides = NetworkOverrides {
+            constants: HashMap::from([(
+                "simulator0".to_string(),
+                ConstantsOverrides {
+                    genesis_challenge: Some(hex::encode(genesis_challenge)),
+                    agg_sig_me_additional_data: Some(hex::encode(genesis_challenge)),
+                    min_plot_size: Some(18),
+                    ..Default::default()
+                },
+            )]),
+            config: HashMap::from([(
+                "simulator0".to_string(),
+                NetworkConfigOverrides {
+                    address_prefix: Some("txch".to_string()),
+                    default_full_node_port: Some(38444),
+                },
+            )]),
+        };
+
+        let network = Network::from_overrides("simulator0", &overrides)?;
+        let expected = ConsensusConstants {
+            min_plot_size: 18,
+            ..default_constants(genesis_challenge, genesis_challenge)
+        };
+
+        assert_eq!(network.genesis_challenge, genesis_challenge);
+        assert_eq!(network.address_prefix, "txch");
+        assert_eq!(network.default_port, 38444);
+        assert_eq!(network.constants, expected);
+
+        Ok(())
+    }
+
+    // An excerpt of the initial config which ships with the reference full node.
+    const CONFIG_YAML: &str = r#"
+min_mainnet_k_size: 32
+
+network_overrides: &network_overrides
+  constants:
+    mainnet:
+      GENESIS_CHALLENGE: ccd5bb71183532bff220ba46c268991a3ff07eb358e8255a65c30a2dce0e5fbb
+      GENESIS_PRE_FARM_POOL_PUZZLE_HASH: "d23da14695a188ae5708dd152263c4db883eb27edeb936178d4d988b8f3ce5fc"
+      GENESIS_PRE_FARM_FARMER_PUZZLE_HASH: "3d8765d3a597ec1d99663f6c9816d915b9f68613ac94009884c4addaefcce6af"
+    testnet11:
+      MIN_PLOT_SIZE: 18
+      GENESIS_CHALLENGE: 37a90eb5185a9c4439a91ddc98bbadce7b4feba060d50116a067de66bf236615
+      GENESIS_PRE_FARM_POOL_PUZZLE_HASH: "3ef7c233fc0785f3c0cae5992c1d35e7c955ca37a423571c1607ba392a9d12f7"
+      GENESIS_PRE_FARM_FARMER_PUZZLE_HASH: "08296fc227decd043aee855741444538e4cc9a31772c4d1a9e6242d1e777e42a"
+      SUB_SLOT_ITERS_STARTING: 67108864
+      # Forks activated from the beginning on this network
+      HARD_FORK_HEIGHT: 0
+      SOFT_FORK4_HEIGHT: 641500
+      SOFT_FORK5_HEIGHT: 1340000
+      PLOT_FILTER_128_HEIGHT: 6029568
+      PLOT_FILTER_64_HEIGHT: 11075328
+      PLOT_FILTER_32_HEIGHT: 16121088
+      DIFFICULTY_CONSTANT_FACTOR: 10052721566054
+      DIFFICULTY_STARTING: 30
+      EPOCH_BLOCKS: 768
+  config:
+    mainnet:
+      address_prefix: "xch"
+      default_full_node_port: 8444
+    testnet11:
+      address_prefix: "txch"
+      default_full_node_port: 58444
+
+selected_network: &selected_network "mainnet"
+
+full_node:
+  network_overrides: *network_overrides
+  selected_network: *selected_network
+"#;
+
+    #[test]
+    fn test_config_yaml() -> Result<(), ClientError> {
+        let path = std::env::temp_dir().join(format!(
+            "chia-sdk-client-config-{}.yaml",
+            std::process::id()
+        ));
+        fs::write(&path, CONFIG_YAML)?;
+        let overrides = NetworkOverrides::from_config_yaml(&path)?;
+        fs::remove_file(path)?;
+
+        let network = Network::from_overrides("testnet11", &overrides)?;
+        assert_eq!(network.address_prefix, "txch");
+        assert_eq!(network.default_port, 58444);
+        assert_eq!(network.constants, *TESTNET11_CONSTANTS);
+
+        let network = Network::from_overrides("mainnet", &overrides)?;
+        assert_eq!(network.address_prefix, "xch");
+        assert_eq!(network.default_port, 8444);
+        assert_eq!(network.constants, *MAINNET_CONSTANTS);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_genesis_challenge() {
+        assert!(matches!(
+            Network::from_overrides("simulator0", &NetworkOverrides::default()),
+            Err(ClientError::InvalidNetworkConfig(..))
+        ));
+    }
+}
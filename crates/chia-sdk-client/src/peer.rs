@@ -1,14 +1,22 @@
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
 
 use chia_protocol::{
-    Bytes32, ChiaProtocolMessage, CoinStateFilters, Message, PuzzleSolutionResponse,
-    RegisterForCoinUpdates, RegisterForPhUpdates, RejectCoinState, RejectPuzzleSolution,
-    RejectPuzzleState, RequestChildren, RequestCoinState, RequestPeers, RequestPuzzleSolution,
-    RequestPuzzleState, RequestRemoveCoinSubscriptions, RequestRemovePuzzleSubscriptions,
-    RequestTransaction, RespondChildren, RespondCoinState, RespondPeers, RespondPuzzleSolution,
-    RespondPuzzleState, RespondRemoveCoinSubscriptions, RespondRemovePuzzleSubscriptions,
-    RespondToCoinUpdates, RespondToPhUpdates, RespondTransaction, SendTransaction, SpendBundle,
-    TransactionAck,
+    Bytes, Bytes32, ChiaProtocolMessage, CoinStateFilters, Handshake, Message, NewPeakWallet,
+    NodeType, ProtocolMessageTypes, PuzzleSolutionResponse, RegisterForCoinUpdates,
+    RegisterForPhUpdates, RejectAdditionsRequest, RejectBlockHeaders, RejectCoinState,
+    RejectHeaderRequest, RejectPuzzleSolution, RejectPuzzleState, RejectRemovalsRequest,
+    RequestAdditions, RequestBlockHeader, RequestBlockHeaders, RequestChildren, RequestCoinState,
+    RequestFeeEstimates, RequestPeers, RequestPuzzleSolution, RequestPuzzleState, RequestRemovals,
+    RequestRemoveCoinSubscriptions, RequestRemovePuzzleSubscriptions, RequestTransaction,
+    RespondAdditions, RespondBlockHeader, RespondBlockHeaders, RespondChildren, RespondCoinState,
+    RespondFeeEstimates, RespondPeers, RespondPuzzleSolution, RespondPuzzleState, RespondRemovals,
+    RespondRemoveCoinSubscriptions, RespondRemovePuzzleSubscriptions, RespondToCoinUpdates,
+    RespondToPhUpdates, RespondTransaction, SendTransaction, SpendBundle, TransactionAck,
 };
 use chia_traits::Streamable;
 use futures_util::{
@@ -17,16 +25,29 @@ use futures_util::{
 };
 use tokio::{
     net::TcpStream,
-    sync::{mpsc, oneshot, Mutex},
+    sync::{mpsc, oneshot, watch, Mutex},
     task::JoinHandle,
 };
 use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
-use tracing::{debug, warn};
+use tracing::{debug, debug_span, info_span, warn, Instrument, Span};
+
+use crate::{
+    metrics::MetricsRecorder,
+    recording::SessionRecorder,
+    request_map::{PendingRequest, RequestMap},
+    Capability, ClientError, MessageDirection, PeerMetrics, ProtocolVersion, RateLimiter,
+    RequestPeersIntroducer, RespondPeersIntroducer, PROTOCOL_VERSION, V2_RATE_LIMITS,
+    WALLET_CAPABILITIES, WALLET_PROTOCOL_V2,
+};
+
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use std::net::{IpAddr, Ipv4Addr};
 
-use crate::{request_map::RequestMap, ClientError, RateLimiter, V2_RATE_LIMITS};
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use crate::Proxy;
 
 #[cfg(any(feature = "native-tls", feature = "rustls"))]
-use tokio_tungstenite::Connector;
+use tokio_tungstenite::{tungstenite::client::IntoClientRequest, Connector};
 
 type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
 type Sink = SplitSink<WebSocket, tungstenite::Message>;
@@ -36,18 +57,29 @@ type Response<T, E> = std::result::Result<T, E>;
 #[derive(Debug, Clone, Copy)]
 pub struct PeerOptions {
     pub rate_limit_factor: f64,
+    pub inbound_rate_limit_factor: f64,
+    /// Trusted peers are exempt from inbound rate limits.
+    pub trusted: bool,
+    /// How long to wait for a response to a request before giving up.
+    pub request_timeout: Duration,
 }
 
 impl Default for PeerOptions {
     fn default() -> Self {
         Self {
             rate_limit_factor: 0.6,
+            inbound_rate_limit_factor: 1.0,
+            trusted: false,
+            request_timeout: Duration::from_secs(60),
         }
     }
 }
 
 #[derive(Debug, Clone)]
-pub struct Peer(Arc<PeerInner>);
+pub struct Peer {
+    inner: Arc<PeerInner>,
+    request_timeout: Duration,
+}
 
 #[derive(Debug)]
 struct PeerInner {
@@ -56,6 +88,13 @@ struct PeerInner {
     requests: Arc<RequestMap>,
     socket_addr: SocketAddr,
     outbound_rate_limiter: Mutex<RateLimiter>,
+    rate_limit_violation: Arc<std::sync::Mutex<Option<ProtocolMessageTypes>>>,
+    disconnected: watch::Receiver<bool>,
+    peak: Arc<std::sync::Mutex<Option<NewPeakWallet>>>,
+    handshake: OnceLock<Handshake>,
+    metrics: Arc<MetricsRecorder>,
+    recorder: Arc<SessionRecorder>,
+    span: Span,
 }
 
 impl Peer {
@@ -69,6 +108,23 @@ impl Peer {
         Self::connect_full_uri(&format!("wss://{socket_addr}/ws"), connector, options).await
     }
 
+    /// Connects to a peer through a proxy, using its IP address and port.
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    pub async fn connect_with_proxy(
+        socket_addr: SocketAddr,
+        connector: Connector,
+        options: PeerOptions,
+        proxy: &Proxy,
+    ) -> Result<(Self, mpsc::Receiver<Message>), ClientError> {
+        Self::connect_full_uri_with_proxy(
+            &format!("wss://{socket_addr}/ws"),
+            connector,
+            options,
+            proxy,
+        )
+        .await
+    }
+
     /// Connects to a peer using its full websocket URI.
     /// For example, `wss://127.0.0.1:8444/ws`.
     #[cfg(any(feature = "native-tls", feature = "rustls"))]
@@ -83,41 +139,119 @@ impl Peer {
         Self::from_websocket(ws, options)
     }
 
+    /// Connects to a peer through a proxy, using its full websocket URI.
+    ///
+    /// A hostname in the URI is passed to the proxy rather than being resolved locally, so that
+    /// lookups don't leak outside of the proxy and onion addresses can be reached through Tor.
+    /// Since the peer's IP address isn't known in that case, its [`Peer::socket_addr`] is the
+    /// unspecified address with the port from the URI.
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    pub async fn connect_full_uri_with_proxy(
+        uri: &str,
+        connector: Connector,
+        options: PeerOptions,
+        proxy: &Proxy,
+    ) -> Result<(Self, mpsc::Receiver<Message>), ClientError> {
+        let request = uri.into_client_request()?;
+        let host = request
+            .uri()
+            .host()
+            .unwrap_or_default()
+            .trim_matches(&['[', ']'][..])
+            .to_string();
+        let port = request.uri().port_u16().unwrap_or(443);
+
+        // The TCP connection is to the proxy, so the peer's address comes from the URI instead.
+        let ip = host
+            .parse::<IpAddr>()
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        let socket_addr = SocketAddr::new(ip, port);
+
+        let stream = proxy.connect(&host, port).await?;
+        let (ws, _) =
+            tokio_tungstenite::client_async_tls_with_config(request, stream, None, Some(connector))
+                .await?;
+
+        Ok(Self::from_websocket_with_addr(ws, socket_addr, options))
+    }
+
     /// Creates a peer from an existing websocket connection.
     /// The connection must be secured with TLS, so that the certificate can be hashed in a peer id.
     pub fn from_websocket(
         ws: WebSocket,
         options: PeerOptions,
     ) -> Result<(Self, mpsc::Receiver<Message>), ClientError> {
-        let socket_addr = match ws.get_ref() {
-            #[cfg(feature = "native-tls")]
-            MaybeTlsStream::NativeTls(tls) => {
-                let tls_stream = tls.get_ref();
-                let tcp_stream = tls_stream.get_ref().get_ref();
-                tcp_stream.peer_addr()?
-            }
-            #[cfg(feature = "rustls")]
-            MaybeTlsStream::Rustls(tls) => {
-                let (tcp_stream, _) = tls.get_ref();
-                tcp_stream.peer_addr()?
-            }
-            MaybeTlsStream::Plain(plain) => plain.peer_addr()?,
-            _ => return Err(ClientError::UnsupportedTls),
-        };
+        let socket_addr = websocket_peer_addr(&ws)?;
+        Ok(Self::from_websocket_with_addr(ws, socket_addr, options))
+    }
 
+    fn from_websocket_with_addr(
+        ws: WebSocket,
+        socket_addr: SocketAddr,
+        options: PeerOptions,
+    ) -> (Self, mpsc::Receiver<Message>) {
         let (sink, stream) = ws.split();
         let (sender, receiver) = mpsc::channel(32);
 
         let requests = Arc::new(RequestMap::new());
         let requests_clone = requests.clone();
 
-        let inbound_handle = tokio::spawn(async move {
-            if let Err(error) = handle_inbound_messages(stream, sender, requests_clone).await {
-                debug!("Error handling message: {error}");
-            }
+        let inbound_rate_limiter = (!options.trusted).then(|| {
+            RateLimiter::new(
+                true,
+                60,
+                options.inbound_rate_limit_factor,
+                V2_RATE_LIMITS.clone(),
+            )
         });
 
-        let peer = Self(Arc::new(PeerInner {
+        let rate_limit_violation = Arc::new(std::sync::Mutex::new(None));
+        let rate_limit_violation_clone = rate_limit_violation.clone();
+
+        let (disconnected_sender, disconnected) = watch::channel(false);
+
+        let peak = Arc::new(std::sync::Mutex::new(None));
+        let peak_clone = peak.clone();
+
+        let metrics = Arc::new(MetricsRecorder::default());
+        let metrics_clone = metrics.clone();
+
+        let recorder = Arc::new(SessionRecorder::default());
+        let recorder_clone = recorder.clone();
+
+        let span = info_span!("peer", addr = %socket_addr);
+
+        let inbound_handle = tokio::spawn(
+            async move {
+                // The sender is only dropped once this task is done, so that the receiver closing
+                // means that the peer has disconnected and any rate limit violation was recorded.
+                if let Err(error) = handle_inbound_messages(
+                    stream,
+                    &sender,
+                    requests_clone,
+                    inbound_rate_limiter,
+                    peak_clone,
+                    metrics_clone,
+                    recorder_clone,
+                )
+                .await
+                {
+                    debug!("Error handling message: {error}");
+
+                    if let ClientError::RateLimitExceeded(msg_type) = error {
+                        *rate_limit_violation_clone
+                            .lock()
+                            .expect("rate limit violation lock poisoned") = Some(msg_type);
+                    }
+                }
+
+                drop(sender);
+                disconnected_sender.send(true).ok();
+            }
+            .instrument(span.clone()),
+        );
+
+        let inner = Arc::new(PeerInner {
             sink: Mutex::new(sink),
             inbound_handle,
             requests,
@@ -128,14 +262,205 @@ impl Peer {
                 options.rate_limit_factor,
                 V2_RATE_LIMITS.clone(),
             )),
-        }));
+            rate_limit_violation,
+            disconnected,
+            peak,
+            handshake: OnceLock::new(),
+            metrics,
+            recorder,
+            span,
+        });
 
-        Ok((peer, receiver))
+        let peer = Self {
+            inner,
+            request_timeout: options.request_timeout,
+        };
+
+        (peer, receiver)
+    }
+
+    /// Returns a handle to the same connection, which uses a different timeout for requests.
+    #[must_use]
+    pub fn with_request_timeout(&self, request_timeout: Duration) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            request_timeout,
+        }
+    }
+
+    pub fn request_timeout(&self) -> Duration {
+        self.request_timeout
     }
 
     /// The IP address and port of the peer connection.
     pub fn socket_addr(&self) -> SocketAddr {
-        self.0.socket_addr
+        self.inner.socket_addr
+    }
+
+    /// Whether the task handling inbound messages from the peer is still running.
+    pub fn is_connected(&self) -> bool {
+        !self.inner.inbound_handle.is_finished()
+    }
+
+    /// Waits until the peer has disconnected, and any rate limit violation has been recorded.
+    pub async fn wait_for_disconnect(&self) {
+        let mut disconnected = self.inner.disconnected.clone();
+
+        // If the task was aborted, the sender is dropped without sending.
+        disconnected
+            .wait_for(|&disconnected| disconnected)
+            .await
+            .ok();
+    }
+
+    /// The type of the message which exceeded the inbound rate limits, if the peer was
+    /// disconnected for doing so.
+    pub fn rate_limit_violation(&self) -> Option<ProtocolMessageTypes> {
+        *self
+            .inner
+            .rate_limit_violation
+            .lock()
+            .expect("rate limit violation lock poisoned")
+    }
+
+    /// The handshake that the peer sent when connecting, if it was performed with `connect_peer`
+    /// or restored from a [`Recording`](crate::Recording).
+    pub fn handshake(&self) -> Option<&Handshake> {
+        self.inner.handshake.get()
+    }
+
+    pub(crate) fn set_handshake(&self, handshake: Handshake) {
+        self.inner.handshake.set(handshake).ok();
+    }
+
+    /// Sends the wallet's handshake, and waits for the peer to respond with its own.
+    /// The peer must be the expected kind of node, on the same network.
+    #[cfg_attr(not(any(feature = "native-tls", feature = "rustls")), allow(dead_code))]
+    pub(crate) async fn perform_handshake(
+        &self,
+        receiver: &mut mpsc::Receiver<Message>,
+        network_id: &str,
+        node_type: NodeType,
+    ) -> Result<(), ClientError> {
+        self.send(Handshake {
+            network_id: network_id.to_string(),
+            protocol_version: PROTOCOL_VERSION.to_string(),
+            software_version: "0.0.0".to_string(),
+            server_port: 0,
+            node_type: NodeType::Wallet,
+            capabilities: WALLET_CAPABILITIES
+                .iter()
+                .map(|&capability| (capability as u16, "1".to_string()))
+                .collect(),
+        })
+        .await?;
+
+        let Some(message) = receiver.recv().await else {
+            return Err(ClientError::MissingHandshake);
+        };
+
+        if message.msg_type != ProtocolMessageTypes::Handshake {
+            return Err(ClientError::InvalidResponse(
+                vec![ProtocolMessageTypes::Handshake],
+                message.msg_type,
+            ));
+        }
+
+        let handshake = Handshake::from_bytes(&message.data)?;
+
+        if handshake.node_type != node_type {
+            return Err(ClientError::WrongNodeType(node_type, handshake.node_type));
+        }
+
+        if handshake.network_id != network_id {
+            return Err(ClientError::WrongNetwork(
+                network_id.to_string(),
+                handshake.network_id,
+            ));
+        }
+
+        self.set_handshake(handshake);
+
+        Ok(())
+    }
+
+    /// The protocol version of the peer, if it sent a handshake with a valid version.
+    pub fn protocol_version(&self) -> Option<ProtocolVersion> {
+        self.handshake()?.protocol_version.parse().ok()
+    }
+
+    /// Whether both the peer and the wallet advertised the capability in their handshakes.
+    pub fn has_capability(&self, capability: Capability) -> bool {
+        self.handshake().is_some_and(|handshake| {
+            Capability::negotiate(&WALLET_CAPABILITIES, handshake).contains(&capability)
+        })
+    }
+
+    /// Fails if the peer sent a handshake with a protocol version that's too old for the request.
+    /// Peers that don't support a request may never respond to it, rather than rejecting it.
+    fn require_protocol_version(
+        &self,
+        msg_type: ProtocolMessageTypes,
+        required: ProtocolVersion,
+    ) -> Result<(), ClientError> {
+        let Some(handshake) = self.handshake() else {
+            return Ok(());
+        };
+
+        if self
+            .protocol_version()
+            .is_some_and(|version| version >= required)
+        {
+            return Ok(());
+        }
+
+        Err(ClientError::UnsupportedRequest(
+            msg_type,
+            handshake.protocol_version.clone(),
+        ))
+    }
+
+    /// Fails if the peer sent a handshake without the capability that the request relies on.
+    fn require_capability(
+        &self,
+        msg_type: ProtocolMessageTypes,
+        capability: Capability,
+    ) -> Result<(), ClientError> {
+        if self.handshake().is_none() || self.has_capability(capability) {
+            return Ok(());
+        }
+
+        Err(ClientError::MissingCapability(msg_type, capability))
+    }
+
+    /// A snapshot of the traffic and request statistics of the connection.
+    pub fn metrics(&self) -> PeerMetrics {
+        let mut metrics = self.inner.metrics.snapshot();
+        (metrics.pending_requests, metrics.expired_requests) = self.inner.requests.len();
+        metrics
+    }
+
+    /// The span that messages and requests for this peer are recorded in.
+    /// It includes the peer's address, so that logs from many peers can be told apart.
+    pub fn span(&self) -> &Span {
+        &self.inner.span
+    }
+
+    /// Starts writing every message sent to and received from the peer to a file, with timing,
+    /// replacing any recording in progress. The file can be loaded as a [`Recording`](crate::Recording)
+    /// and replayed.
+    pub fn start_recording(&self, path: impl AsRef<Path>) -> Result<(), ClientError> {
+        self.inner.recorder.start(path.as_ref(), self.handshake())
+    }
+
+    /// Stops the recording, once the messages recorded so far have been written to the file.
+    pub fn stop_recording(&self) -> Result<(), ClientError> {
+        self.inner.recorder.stop()
+    }
+
+    /// The most recent peak that the peer has announced with a [`NewPeakWallet`] message.
+    pub fn peak(&self) -> Option<NewPeakWallet> {
+        self.inner.peak.lock().expect("peak lock poisoned").clone()
     }
 
     pub async fn send_transaction(
@@ -154,6 +479,11 @@ impl Peer {
         filters: CoinStateFilters,
         subscribe_when_finished: bool,
     ) -> Result<Response<RespondPuzzleState, RejectPuzzleState>, ClientError> {
+        self.require_protocol_version(
+            ProtocolMessageTypes::RequestPuzzleState,
+            WALLET_PROTOCOL_V2,
+        )?;
+
         self.request_fallible(RequestPuzzleState::new(
             puzzle_hashes,
             previous_height,
@@ -171,6 +501,8 @@ impl Peer {
         header_hash: Bytes32,
         subscribe: bool,
     ) -> Result<Response<RespondCoinState, RejectCoinState>, ClientError> {
+        self.require_protocol_version(ProtocolMessageTypes::RequestCoinState, WALLET_PROTOCOL_V2)?;
+
         self.request_fallible(RequestCoinState::new(
             coin_ids,
             previous_height,
@@ -202,6 +534,11 @@ impl Peer {
         &self,
         puzzle_hashes: Option<Vec<Bytes32>>,
     ) -> Result<RespondRemovePuzzleSubscriptions, ClientError> {
+        self.require_protocol_version(
+            ProtocolMessageTypes::RequestRemovePuzzleSubscriptions,
+            WALLET_PROTOCOL_V2,
+        )?;
+
         self.request_infallible(RequestRemovePuzzleSubscriptions::new(puzzle_hashes))
             .await
     }
@@ -210,6 +547,11 @@ impl Peer {
         &self,
         coin_ids: Option<Vec<Bytes32>>,
     ) -> Result<RespondRemoveCoinSubscriptions, ClientError> {
+        self.require_protocol_version(
+            ProtocolMessageTypes::RequestRemoveCoinSubscriptions,
+            WALLET_PROTOCOL_V2,
+        )?;
+
         self.request_infallible(RequestRemoveCoinSubscriptions::new(coin_ids))
             .await
     }
@@ -242,10 +584,80 @@ impl Peer {
         self.request_infallible(RequestChildren::new(coin_id)).await
     }
 
+    pub async fn request_block_header(
+        &self,
+        height: u32,
+    ) -> Result<Response<RespondBlockHeader, RejectHeaderRequest>, ClientError> {
+        self.request_fallible(RequestBlockHeader::new(height)).await
+    }
+
+    /// Requests a range of header blocks, which is faster than requesting them one at a time.
+    /// The transactions filter is left empty unless `return_filter` is set.
+    pub async fn request_block_headers(
+        &self,
+        start_height: u32,
+        end_height: u32,
+        return_filter: bool,
+    ) -> Result<Response<RespondBlockHeaders, RejectBlockHeaders>, ClientError> {
+        self.require_capability(
+            ProtocolMessageTypes::RequestBlockHeaders,
+            Capability::BlockHeaders,
+        )?;
+
+        self.request_fallible(RequestBlockHeaders::new(
+            start_height,
+            end_height,
+            return_filter,
+        ))
+        .await
+    }
+
+    /// Requests the coins created in a block. If puzzle hashes are given, only coins with those
+    /// puzzle hashes are returned, along with proofs of inclusion or exclusion.
+    ///
+    /// The response is not verified, see [`verify_additions`](crate::verify_additions).
+    pub async fn request_additions(
+        &self,
+        height: u32,
+        header_hash: Option<Bytes32>,
+        puzzle_hashes: Option<Vec<Bytes32>>,
+    ) -> Result<Response<RespondAdditions, RejectAdditionsRequest>, ClientError> {
+        self.request_fallible(RequestAdditions::new(height, header_hash, puzzle_hashes))
+            .await
+    }
+
+    /// Requests the coins spent in a block. If coin ids are given, only those coins are returned,
+    /// along with proofs of inclusion or exclusion.
+    ///
+    /// The response is not verified, see [`verify_removals`](crate::verify_removals).
+    pub async fn request_removals(
+        &self,
+        height: u32,
+        header_hash: Bytes32,
+        coin_ids: Option<Vec<Bytes32>>,
+    ) -> Result<Response<RespondRemovals, RejectRemovalsRequest>, ClientError> {
+        self.request_fallible(RequestRemovals::new(height, header_hash, coin_ids))
+            .await
+    }
+
     pub async fn request_peers(&self) -> Result<RespondPeers, ClientError> {
         self.request_infallible(RequestPeers::new()).await
     }
 
+    /// Requests the estimated fee rates for each target, which are unix timestamps in seconds.
+    pub async fn request_fee_estimates(
+        &self,
+        time_targets: Vec<u64>,
+    ) -> Result<RespondFeeEstimates, ClientError> {
+        self.request_infallible(RequestFeeEstimates::new(time_targets))
+            .await
+    }
+
+    /// Requests the addresses of full nodes from an introducer.
+    pub async fn request_peers_introducer(&self) -> Result<RespondPeersIntroducer, ClientError> {
+        self.request_infallible(RequestPeersIntroducer::new()).await
+    }
+
     /// Sends a message to the peer, but does not expect any response.
     pub async fn send<T>(&self, body: T) -> Result<(), ClientError>
     where
@@ -303,48 +715,119 @@ impl Peer {
     where
         T: Streamable + ChiaProtocolMessage,
     {
-        let (sender, receiver) = oneshot::channel();
+        let msg_type = T::msg_type();
+        let data = body.to_bytes()?.into();
+        let span = debug_span!(parent: &self.inner.span, "request", ?msg_type);
 
-        self.send_raw(Message {
-            msg_type: T::msg_type(),
-            id: Some(self.0.requests.insert(sender).await),
-            data: body.to_bytes()?.into(),
-        })
-        .await?;
+        self.request_message(msg_type, data).instrument(span).await
+    }
+
+    async fn request_message(
+        &self,
+        msg_type: ProtocolMessageTypes,
+        data: Bytes,
+    ) -> Result<Message, ClientError> {
+        // The timeout also covers waiting for the outbound rate limit, not just for the response.
+        let request = async {
+            let (sender, receiver) = oneshot::channel();
+
+            let id = self.inner.requests.insert(sender).await;
+            let pending = PendingRequest::new(&self.inner.requests, id);
+
+            self.send_raw(Message {
+                msg_type,
+                id: Some(id),
+                data,
+            })
+            .await?;
+
+            let start = Instant::now();
+            let response = receiver.await;
+
+            pending.complete();
 
-        Ok(receiver.await?)
+            let latency = start.elapsed();
+            self.inner.metrics.latency(msg_type, latency);
+            debug!(id, latency_ms = latency.as_millis(), "Received response");
+
+            Ok(response?)
+        };
+
+        let Ok(response) = tokio::time::timeout(self.request_timeout, request).await else {
+            warn!(
+                "Request of type {msg_type:?} to {} timed out",
+                self.inner.socket_addr
+            );
+            self.inner.metrics.timeout(msg_type);
+            return Err(ClientError::Timeout(msg_type));
+        };
+
+        response
     }
 
     async fn send_raw(&self, message: Message) -> Result<(), ClientError> {
+        let mut rate_limited = false;
+
         loop {
             if !self
-                .0
+                .inner
                 .outbound_rate_limiter
                 .lock()
                 .await
                 .handle_message(&message)
             {
+                if !rate_limited {
+                    rate_limited = true;
+                    self.inner.metrics.outbound_rate_limited();
+                    debug!(
+                        "Waiting for the outbound rate limit to send {:?} message",
+                        message.msg_type
+                    );
+                }
+
                 tokio::time::sleep(Duration::from_secs(1)).await;
                 continue;
             }
 
-            self.0
-                .sink
-                .lock()
-                .await
-                .send(message.to_bytes()?.into())
-                .await?;
+            let bytes = message.to_bytes()?;
+            let len = bytes.len();
+
+            // This is recorded before sending, so that the response can't be recorded first.
+            self.inner
+                .recorder
+                .record(MessageDirection::Outbound, &bytes);
+
+            self.inner.sink.lock().await.send(bytes.into()).await?;
+            self.inner.metrics.outbound(message.msg_type, len);
 
             return Ok(());
         }
     }
 
     pub async fn close(&self) -> Result<(), ClientError> {
-        self.0.sink.lock().await.close().await?;
+        self.inner.sink.lock().await.close().await?;
         Ok(())
     }
 }
 
+fn websocket_peer_addr(ws: &WebSocket) -> Result<SocketAddr, ClientError> {
+    Ok(match ws.get_ref() {
+        #[cfg(feature = "native-tls")]
+        MaybeTlsStream::NativeTls(tls) => {
+            let tls_stream = tls.get_ref();
+            let tcp_stream = tls_stream.get_ref().get_ref();
+            tcp_stream.peer_addr()?
+        }
+        #[cfg(feature = "rustls")]
+        MaybeTlsStream::Rustls(tls) => {
+            let (tcp_stream, _) = tls.get_ref();
+            tcp_stream.peer_addr()?
+        }
+        MaybeTlsStream::Plain(plain) => plain.peer_addr()?,
+        _ => return Err(ClientError::UnsupportedTls),
+    })
+}
+
 impl Drop for PeerInner {
     fn drop(&mut self) {
         self.inbound_handle.abort();
@@ -353,8 +836,12 @@ impl Drop for PeerInner {
 
 async fn handle_inbound_messages(
     mut stream: Stream,
-    sender: mpsc::Sender<Message>,
+    sender: &mpsc::Sender<Message>,
     requests: Arc<RequestMap>,
+    mut rate_limiter: Option<RateLimiter>,
+    peak: Arc<std::sync::Mutex<Option<NewPeakWallet>>>,
+    metrics: Arc<MetricsRecorder>,
+    recorder: Arc<SessionRecorder>,
 ) -> Result<(), ClientError> {
     use tungstenite::Message::{Binary, Close, Frame, Ping, Pong, Text};
 
@@ -371,12 +858,40 @@ async fn handle_inbound_messages(
             Binary(binary) => {
                 let message = Message::from_bytes(&binary)?;
 
+                metrics.inbound(message.msg_type, binary.len());
+                recorder.record(MessageDirection::Inbound, &binary);
+
+                if let Some(rate_limiter) = &mut rate_limiter {
+                    if !rate_limiter.handle_message(&message) {
+                        metrics.inbound_rate_limited();
+                        warn!(
+                            "Received {:?} message which exceeded the inbound rate limit",
+                            message.msg_type
+                        );
+                        return Err(ClientError::RateLimitExceeded(message.msg_type));
+                    }
+                }
+
+                if message.msg_type == ProtocolMessageTypes::NewPeakWallet {
+                    if let Ok(new_peak) = NewPeakWallet::from_bytes(&message.data) {
+                        *peak.lock().expect("peak lock poisoned") = Some(new_peak);
+                    }
+                }
+
                 let Some(id) = message.id else {
                     sender.send(message).await.ok();
                     continue;
                 };
 
-                let Some(request) = requests.remove(id).await else {
+                let Some(request) = requests.remove(id) else {
+                    if requests.remove_expired(id) {
+                        debug!(
+                            "Received {:?} message for expired request with id {id}",
+                            message.msg_type
+                        );
+                        continue;
+                    }
+
                     warn!(
                         "Received {:?} message with untracked id {id}",
                         message.msg_type
@@ -390,3 +905,179 @@ async fn handle_inbound_messages(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite;
+
+    use crate::{test_server::NETWORK_ID, to_message};
+
+    #[cfg(feature = "native-tls")]
+    use crate::test_server::{socks5_proxy, test_connector, EmptyHandler, TestServer};
+
+    use super::*;
+
+    /// Connects to a full node which responds to the wallet's handshake with the given version and
+    /// capabilities, and then ignores every message.
+    async fn connect_with_handshake(
+        protocol_version: &str,
+        capabilities: &[Capability],
+    ) -> Result<(Peer, mpsc::Receiver<Message>), ClientError> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+
+        let handshake = Handshake {
+            network_id: NETWORK_ID.to_string(),
+            protocol_version: protocol_version.to_string(),
+            software_version: "2.4.0".to_string(),
+            server_port: addr.port(),
+            node_type: NodeType::FullNode,
+            capabilities: capabilities
+                .iter()
+                .map(|&capability| (capability as u16, "1".to_string()))
+                .collect(),
+        };
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut ws = tokio_tungstenite::accept_async(MaybeTlsStream::Plain(stream)).await?;
+
+            ws.next().await;
+            ws.send(tungstenite::Message::Binary(
+                to_message(&handshake)?.to_bytes()?,
+            ))
+            .await?;
+
+            while ws.next().await.is_some() {}
+
+            Ok::<(), ClientError>(())
+        });
+
+        let stream = TcpStream::connect(addr).await?;
+        let (ws, _) =
+            tokio_tungstenite::client_async(format!("ws://{addr}"), MaybeTlsStream::Plain(stream))
+                .await?;
+
+        let (peer, mut receiver) = Peer::from_websocket(ws, PeerOptions::default())?;
+        peer.perform_handshake(&mut receiver, NETWORK_ID, NodeType::FullNode)
+            .await?;
+
+        Ok((peer, receiver))
+    }
+
+    #[tokio::test]
+    async fn test_old_protocol_version() -> Result<(), ClientError> {
+        let (peer, _receiver) = connect_with_handshake("0.0.36", &WALLET_CAPABILITIES).await?;
+
+        assert_eq!(
+            peer.protocol_version(),
+            Some(ProtocolVersion::new(0, 0, 36))
+        );
+
+        assert!(matches!(
+            peer.request_puzzle_state(
+                vec![Bytes32::default()],
+                None,
+                Bytes32::default(),
+                CoinStateFilters::new(true, true, true, 0),
+                false,
+            )
+            .await,
+            Err(ClientError::UnsupportedRequest(
+                ProtocolMessageTypes::RequestPuzzleState,
+                version
+            )) if version == "0.0.36"
+        ));
+
+        assert!(matches!(
+            peer.request_coin_state(vec![Bytes32::default()], None, Bytes32::default(), false)
+                .await,
+            Err(ClientError::UnsupportedRequest(
+                ProtocolMessageTypes::RequestCoinState,
+                ..
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_missing_capability() -> Result<(), ClientError> {
+        let (peer, _receiver) = connect_with_handshake(
+            &PROTOCOL_VERSION.to_string(),
+            &[Capability::Base, Capability::RateLimitsV2],
+        )
+        .await?;
+
+        assert!(!peer.has_capability(Capability::BlockHeaders));
+
+        assert!(matches!(
+            peer.request_block_headers(0, 10, false).await,
+            Err(ClientError::MissingCapability(
+                ProtocolMessageTypes::RequestBlockHeaders,
+                Capability::BlockHeaders
+            ))
+        ));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "native-tls")]
+    #[tokio::test]
+    async fn test_tls_through_proxy() -> Result<(), ClientError> {
+        let server = TestServer::start("127.0.0.1:0", EmptyHandler).await?;
+        let (proxy_addr, proxy_handle) = socks5_proxy().await?;
+
+        let (peer, mut receiver) = Peer::connect_with_proxy(
+            server.addr,
+            test_connector()?,
+            PeerOptions::default(),
+            &Proxy::socks5(proxy_addr.to_string()),
+        )
+        .await?;
+        peer.perform_handshake(&mut receiver, NETWORK_ID, NodeType::FullNode)
+            .await?;
+
+        // The peer is identified by its own address, rather than the proxy's.
+        assert_eq!(peer.socket_addr(), server.addr);
+        assert_eq!(
+            proxy_handle.await.expect("proxy panicked")?,
+            server.addr.to_string()
+        );
+        assert!(peer.request_peers().await?.peer_list.is_empty());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "native-tls")]
+    #[tokio::test]
+    async fn test_hostname_through_proxy() -> Result<(), ClientError> {
+        let addr = tokio::net::lookup_host("localhost:0")
+            .await?
+            .next()
+            .expect("localhost doesn't resolve");
+        let server = TestServer::start(&addr.to_string(), EmptyHandler).await?;
+        let (proxy_addr, proxy_handle) = socks5_proxy().await?;
+
+        let (peer, mut receiver) = Peer::connect_full_uri_with_proxy(
+            &format!("wss://localhost:{}/ws", server.addr.port()),
+            test_connector()?,
+            PeerOptions::default(),
+            &Proxy::socks5(proxy_addr.to_string()),
+        )
+        .await?;
+        peer.perform_handshake(&mut receiver, NETWORK_ID, NodeType::FullNode)
+            .await?;
+
+        // The proxy is asked to connect to the hostname, which it resolves itself.
+        assert_eq!(
+            proxy_handle.await.expect("proxy panicked")?,
+            format!("localhost:{}", server.addr.port())
+        );
+        assert!(peer.socket_addr().ip().is_unspecified());
+        assert_eq!(peer.socket_addr().port(), server.addr.port());
+
+        Ok(())
+    }
+}
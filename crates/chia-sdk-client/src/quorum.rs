@@ -0,0 +1,279 @@
+use std::{future::Future, net::IpAddr};
+
+use chia_protocol::{Bytes32, CoinState};
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use tracing::warn;
+
+use crate::{Client, ClientError, Peer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuorumStrategy {
+    /// The response returned by the most peers is chosen.
+    Majority,
+    /// The response returned by the most peers at the highest peak is chosen.
+    /// Peers which are still catching up to that peak won't count against it.
+    HighestPeak,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuorumOptions {
+    /// How many peers to send the request to. Peers with the highest reputation are chosen first.
+    pub peers: usize,
+    /// The minimum number of peers which must agree on the chosen response.
+    pub min_agreement: usize,
+    pub strategy: QuorumStrategy,
+}
+
+impl Default for QuorumOptions {
+    fn default() -> Self {
+        Self {
+            peers: 3,
+            min_agreement: 2,
+            strategy: QuorumStrategy::Majority,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct QuorumResponse<T> {
+    pub value: T,
+    /// The peers which responded with the chosen value.
+    pub agreed: Vec<IpAddr>,
+    /// The peers which responded with a different value.
+    /// These can be penalized or banned with the [`ClientState`](crate::ClientState).
+    pub disagreed: Vec<IpAddr>,
+    /// The peers which responded from a lower peak than the others, with the [`QuorumStrategy::HighestPeak`]
+    /// strategy. Their responses aren't counted, since they may just be catching up.
+    pub lagging: Vec<IpAddr>,
+    /// The peers which failed to respond at all.
+    /// Peers which failed because they misbehaved have already been penalized.
+    pub failed: Vec<(IpAddr, ClientError)>,
+}
+
+impl Client {
+    /// Sends the same request to multiple peers, and reconciles their responses.
+    /// Responses are compared for equality, so they should be normalized by the request.
+    pub async fn request_quorum<T, F, Fut>(
+        &self,
+        options: QuorumOptions,
+        request: F,
+    ) -> Result<QuorumResponse<T>, ClientError>
+    where
+        T: PartialEq,
+        F: Fn(Peer) -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let peers = self.lock().await.best_peers(options.peers);
+
+        let mut futures = FuturesUnordered::new();
+
+        for peer in peers {
+            let future = request(peer.clone());
+            futures.push(async move {
+                let result = self.request(&peer, future).await;
+                let peak_height = peer.peak().map(|peak| peak.height);
+                (peer.socket_addr().ip(), peak_height, result)
+            });
+        }
+
+        let mut responses = Vec::new();
+        let mut failed = Vec::new();
+
+        while let Some((ip_addr, peak_height, result)) = futures.next().await {
+            match result {
+                Ok(value) => responses.push((ip_addr, peak_height, value)),
+                Err(error) => {
+                    warn!("Quorum request to {ip_addr} failed: {error}");
+                    failed.push((ip_addr, error));
+                }
+            }
+        }
+
+        let mut response = reconcile(responses, options)?;
+        response.failed = failed;
+        Ok(response)
+    }
+
+    /// Requests the state of the coins from multiple peers.
+    pub async fn request_coin_state_quorum(
+        &self,
+        coin_ids: Vec<Bytes32>,
+        options: QuorumOptions,
+    ) -> Result<QuorumResponse<Vec<CoinState>>, ClientError> {
+        let genesis_challenge = self.network().genesis_challenge;
+
+        self.request_quorum(options, |peer| {
+            let coin_ids = coin_ids.clone();
+
+            async move {
+                let response = peer
+                    .request_coin_state(coin_ids, None, genesis_challenge, false)
+                    .await??;
+                Ok(normalize_coin_states(response.coin_states))
+            }
+        })
+        .await
+    }
+
+    /// Requests the children of the coin from multiple peers.
+    pub async fn request_children_quorum(
+        &self,
+        coin_id: Bytes32,
+        options: QuorumOptions,
+    ) -> Result<QuorumResponse<Vec<CoinState>>, ClientError> {
+        self.request_quorum(options, |peer| async move {
+            let response = peer.request_children(coin_id).await?;
+            Ok(normalize_coin_states(response.coin_states))
+        })
+        .await
+    }
+}
+
+// Peers aren't required to return coin states in any particular order.
+fn normalize_coin_states(mut coin_states: Vec<CoinState>) -> Vec<CoinState> {
+    coin_states.sort_by_key(|coin_state| {
+        (
+            coin_state.coin.coin_id(),
+            coin_state.created_height,
+            coin_state.spent_height,
+        )
+    });
+    coin_states.dedup();
+    coin_states
+}
+
+struct Group<T> {
+    value: T,
+    peers: Vec<IpAddr>,
+}
+
+fn reconcile<T>(
+    responses: Vec<(IpAddr, Option<u32>, T)>,
+    options: QuorumOptions,
+) -> Result<QuorumResponse<T>, ClientError>
+where
+    T: PartialEq,
+{
+    let highest_peak = match options.strategy {
+        QuorumStrategy::Majority => None,
+        QuorumStrategy::HighestPeak => responses.iter().map(|(_, peak, _)| *peak).max().flatten(),
+    };
+
+    let mut groups: Vec<Group<T>> = Vec::new();
+    let mut lagging = Vec::new();
+
+    for (ip_addr, peak_height, value) in responses {
+        if peak_height < highest_peak {
+            lagging.push(ip_addr);
+        } else if let Some(group) = groups.iter_mut().find(|group| group.value == value) {
+            group.peers.push(ip_addr);
+        } else {
+            groups.push(Group {
+                value,
+                peers: vec![ip_addr],
+            });
+        }
+    }
+
+    groups.sort_by_key(|group| std::cmp::Reverse(group.peers.len()));
+
+    if groups.is_empty() {
+        return Err(ClientError::NoQuorum(0, options.min_agreement.max(1)));
+    }
+
+    // If multiple responses are equally common, none of them can be trusted.
+    if groups.len() > 1 && groups[0].peers.len() == groups[1].peers.len() {
+        return Err(ClientError::NoQuorum(
+            groups[0].peers.len(),
+            options.min_agreement.max(1),
+        ));
+    }
+
+    let chosen = groups.remove(0);
+
+    if chosen.peers.len() < options.min_agreement {
+        return Err(ClientError::NoQuorum(
+            chosen.peers.len(),
+            options.min_agreement,
+        ));
+    }
+
+    Ok(QuorumResponse {
+        value: chosen.value,
+        agreed: chosen.peers,
+        disagreed: groups.into_iter().flat_map(|group| group.peers).collect(),
+        lagging,
+        failed: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn ip(n: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(127, 0, 0, n))
+    }
+
+    fn options(min_agreement: usize, strategy: QuorumStrategy) -> QuorumOptions {
+        QuorumOptions {
+            peers: 5,
+            min_agreement,
+            strategy,
+        }
+    }
+
+    #[test]
+    fn test_majority() {
+        let responses = vec![
+            (ip(1), Some(10), "a"),
+            (ip(2), Some(10), "b"),
+            (ip(3), Some(10), "a"),
+        ];
+
+        let response = reconcile(responses, options(2, QuorumStrategy::Majority)).unwrap();
+        assert_eq!(response.value, "a");
+        assert_eq!(response.agreed, vec![ip(1), ip(3)]);
+        assert_eq!(response.disagreed, vec![ip(2)]);
+        assert!(response.lagging.is_empty());
+    }
+
+    #[test]
+    fn test_majority_tie() {
+        let responses = vec![(ip(1), Some(10), "a"), (ip(2), Some(10), "b")];
+
+        assert!(matches!(
+            reconcile(responses, options(1, QuorumStrategy::Majority)),
+            Err(ClientError::NoQuorum(1, 1))
+        ));
+    }
+
+    #[test]
+    fn test_min_agreement() {
+        let responses = vec![(ip(1), Some(10), "a"), (ip(2), Some(10), "a")];
+
+        assert!(matches!(
+            reconcile(responses, options(3, QuorumStrategy::Majority)),
+            Err(ClientError::NoQuorum(2, 3))
+        ));
+    }
+
+    #[test]
+    fn test_highest_peak() {
+        let responses = vec![
+            (ip(1), Some(9), "a"),
+            (ip(2), Some(9), "a"),
+            (ip(3), Some(10), "b"),
+            (ip(4), Some(10), "b"),
+            (ip(5), Some(10), "c"),
+        ];
+
+        let response = reconcile(responses, options(2, QuorumStrategy::HighestPeak)).unwrap();
+        assert_eq!(response.value, "b");
+        assert_eq!(response.agreed, vec![ip(3), ip(4)]);
+        assert_eq!(response.disagreed, vec![ip(5)]);
+        assert_eq!(response.lagging, vec![ip(1), ip(2)]);
+    }
+}
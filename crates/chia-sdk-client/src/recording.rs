@@ -0,0 +1,487 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{mpsc as std_mpsc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use chia_protocol::{Handshake, Message, ProtocolMessageTypes};
+use chia_traits::Streamable;
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{debug, warn};
+
+use crate::{ClientError, Peer, PeerOptions};
+
+const RECORDING_MAGIC: &[u8; 8] = b"CHIAREC1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageDirection {
+    /// Sent by us to the peer.
+    Outbound,
+    /// Received by us from the peer.
+    Inbound,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedMessage {
+    pub direction: MessageDirection,
+    /// The time between when the recording was started and when the message was sent or received.
+    pub elapsed: Duration,
+    pub message: Message,
+}
+
+/// The messages of a peer session captured with [`Peer::start_recording`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Recording {
+    pub messages: Vec<RecordedMessage>,
+}
+
+impl Recording {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ClientError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ClientError> {
+        std::fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, ClientError> {
+        if take::<8>(&mut bytes)? != *RECORDING_MAGIC {
+            return Err(ClientError::InvalidRecording);
+        }
+
+        let mut messages = Vec::new();
+
+        while !bytes.is_empty() {
+            let direction = match take::<1>(&mut bytes)? {
+                [0] => MessageDirection::Outbound,
+                [1] => MessageDirection::Inbound,
+                _ => return Err(ClientError::InvalidRecording),
+            };
+            let elapsed = Duration::from_micros(u64::from_be_bytes(take(&mut bytes)?));
+            let len = u32::from_be_bytes(take(&mut bytes)?) as usize;
+
+            let (message, rest) = bytes
+                .split_at_checked(len)
+                .ok_or(ClientError::InvalidRecording)?;
+            bytes = rest;
+
+            messages.push(RecordedMessage {
+                direction,
+                elapsed,
+                message: Message::from_bytes(message)?,
+            });
+        }
+
+        Ok(Self { messages })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ClientError> {
+        let mut bytes = RECORDING_MAGIC.to_vec();
+
+        for recorded in &self.messages {
+            bytes.extend(encode_entry(
+                recorded.direction,
+                recorded.elapsed,
+                &recorded.message.to_bytes()?,
+            ));
+        }
+
+        Ok(bytes)
+    }
+
+    /// The handshake that the peer sent, if the recording was started after it was received.
+    pub fn handshake(&self) -> Result<Option<Handshake>, ClientError> {
+        let Some(recorded) = self.messages.iter().find(|recorded| {
+            recorded.direction == MessageDirection::Inbound
+                && recorded.message.msg_type == ProtocolMessageTypes::Handshake
+        }) else {
+            return Ok(None);
+        };
+
+        Ok(Some(Handshake::from_bytes(&recorded.message.data)?))
+    }
+
+    /// Creates a peer which answers requests with the responses from the recording, rather than
+    /// connecting to a real node. Requests are matched by their type and contents, and messages
+    /// which the peer sent on its own are delivered once the requests recorded before them were made.
+    ///
+    /// Messages are replayed as fast as possible, so the peer is always trusted to exempt it from
+    /// inbound rate limits. Making a request which isn't in the recording closes the connection.
+    pub async fn replay(
+        &self,
+        options: PeerOptions,
+    ) -> Result<(Peer, mpsc::Receiver<Message>), ClientError> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let messages = self.messages.clone();
+
+        tokio::spawn(async move {
+            let result = async {
+                let (stream, _) = listener.accept().await?;
+                let ws = tokio_tungstenite::accept_async(stream).await?;
+                replay_messages(ws, &messages).await
+            }
+            .await;
+
+            if let Err(error) = result {
+                debug!("Replay failed: {error}");
+            }
+        });
+
+        let stream = TcpStream::connect(addr).await?;
+        let (ws, _) =
+            tokio_tungstenite::client_async(format!("ws://{addr}"), MaybeTlsStream::Plain(stream))
+                .await?;
+
+        let (peer, receiver) = Peer::from_websocket(
+            ws,
+            PeerOptions {
+                trusted: true,
+                ..options
+            },
+        )?;
+
+        if let Some(handshake) = self.handshake()? {
+            peer.set_handshake(handshake);
+        }
+
+        Ok((peer, receiver))
+    }
+}
+
+async fn replay_messages(
+    ws: WebSocketStream<TcpStream>,
+    messages: &[RecordedMessage],
+) -> Result<(), ClientError> {
+    use tungstenite::Message::{Binary, Close};
+
+    let (mut sink, mut stream) = ws.split();
+
+    // The handshake is restored on the peer directly, rather than being sent again.
+    let mut replayed: Vec<bool> = messages
+        .iter()
+        .map(|recorded| recorded.message.msg_type == ProtocolMessageTypes::Handshake)
+        .collect();
+
+    let mut next = 0;
+
+    loop {
+        // Deliver the messages that the peer sent on its own, up to the first recorded message
+        // from us which hasn't been replayed yet. Responses are only sent when requested.
+        while let Some(recorded) = messages.get(next) {
+            if !replayed[next] {
+                match recorded.direction {
+                    MessageDirection::Outbound => break,
+                    MessageDirection::Inbound if recorded.message.id.is_none() => {
+                        sink.send(recorded.message.to_bytes()?.into()).await?;
+                        replayed[next] = true;
+                    }
+                    MessageDirection::Inbound => {}
+                }
+            }
+            next += 1;
+        }
+
+        let Some(message) = stream.next().await else {
+            break;
+        };
+
+        let binary = match message? {
+            Binary(binary) => binary,
+            Close(..) => break,
+            _ => continue,
+        };
+
+        let message = Message::from_bytes(&binary)?;
+
+        let Some(index) = find_request(messages, &replayed, &message) else {
+            warn!(
+                "Received {:?} message which isn't in the recording",
+                message.msg_type
+            );
+            break;
+        };
+
+        replayed[index] = true;
+
+        let Some(id) = messages[index].message.id else {
+            continue;
+        };
+
+        // The response is the next message from the peer with the same id as the recorded request.
+        let response = messages
+            .iter()
+            .enumerate()
+            .skip(index + 1)
+            .find(|(_, recorded)| {
+                recorded.direction == MessageDirection::Inbound && recorded.message.id == Some(id)
+            });
+
+        if let Some((response_index, response)) = response {
+            replayed[response_index] = true;
+
+            let response = Message {
+                id: message.id,
+                ..response.message.clone()
+            };
+            sink.send(response.to_bytes()?.into()).await?;
+        }
+    }
+
+    sink.close().await?;
+
+    Ok(())
+}
+
+/// Finds the first matching message from us which hasn't been replayed yet.
+/// If every match was already replayed, the request is being repeated and the first one is used.
+fn find_request(
+    messages: &[RecordedMessage],
+    replayed: &[bool],
+    message: &Message,
+) -> Option<usize> {
+    let mut matches = messages
+        .iter()
+        .enumerate()
+        .filter(|(_, recorded)| {
+            recorded.direction == MessageDirection::Outbound
+                && recorded.message.msg_type == message.msg_type
+                && recorded.message.data == message.data
+        })
+        .map(|(index, _)| index)
+        .peekable();
+
+    let first = *matches.peek()?;
+
+    Some(matches.find(|&index| !replayed[index]).unwrap_or(first))
+}
+
+fn encode_entry(direction: MessageDirection, elapsed: Duration, message: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(13 + message.len());
+    bytes.push(match direction {
+        MessageDirection::Outbound => 0,
+        MessageDirection::Inbound => 1,
+    });
+    bytes.extend_from_slice(
+        &u64::try_from(elapsed.as_micros())
+            .unwrap_or(u64::MAX)
+            .to_be_bytes(),
+    );
+    bytes.extend_from_slice(
+        &u32::try_from(message.len())
+            .unwrap_or(u32::MAX)
+            .to_be_bytes(),
+    );
+    bytes.extend_from_slice(message);
+    bytes
+}
+
+fn take<const N: usize>(bytes: &mut &[u8]) -> Result<[u8; N], ClientError> {
+    let (taken, rest) = bytes
+        .split_first_chunk::<N>()
+        .ok_or(ClientError::InvalidRecording)?;
+    *bytes = rest;
+    Ok(*taken)
+}
+
+/// Writes the messages of a peer connection to a file while a recording is in progress.
+#[derive(Debug, Default)]
+pub(crate) struct SessionRecorder(Mutex<Option<SessionWriter>>);
+
+/// The file is written on its own thread, so that recording a message never blocks the connection.
+#[derive(Debug)]
+struct SessionWriter {
+    start: Instant,
+    sender: std_mpsc::Sender<Vec<u8>>,
+    thread: JoinHandle<std::io::Result<()>>,
+}
+
+impl SessionRecorder {
+    pub(crate) fn start(
+        &self,
+        path: &Path,
+        handshake: Option<&Handshake>,
+    ) -> Result<(), ClientError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(RECORDING_MAGIC)?;
+
+        if let Some(handshake) = handshake {
+            let message = Message {
+                msg_type: ProtocolMessageTypes::Handshake,
+                id: None,
+                data: handshake.to_bytes()?.into(),
+            };
+            file.write_all(&encode_entry(
+                MessageDirection::Inbound,
+                Duration::ZERO,
+                &message.to_bytes()?,
+            ))?;
+        }
+
+        file.flush()?;
+
+        let (sender, receiver) = std_mpsc::channel();
+        let thread = std::thread::spawn(move || write_entries(file, &receiver));
+
+        *self.lock() = Some(SessionWriter {
+            start: Instant::now(),
+            sender,
+            thread,
+        });
+
+        Ok(())
+    }
+
+    /// Stops the recording, and waits for the messages recorded so far to be written.
+    pub(crate) fn stop(&self) -> Result<(), ClientError> {
+        let Some(writer) = self.lock().take() else {
+            return Ok(());
+        };
+
+        drop(writer.sender);
+
+        writer
+            .thread
+            .join()
+            .expect("recording writer thread panicked")?;
+
+        Ok(())
+    }
+
+    pub(crate) fn record(&self, direction: MessageDirection, message: &[u8]) {
+        let mut guard = self.lock();
+
+        let Some(writer) = guard.as_mut() else {
+            return;
+        };
+
+        let entry = encode_entry(direction, writer.start.elapsed(), message);
+
+        // The writer thread only exits early if it failed, in which case it has already logged why.
+        if writer.sender.send(entry).is_err() {
+            *guard = None;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<SessionWriter>> {
+        self.0.lock().expect("recorder lock poisoned")
+    }
+}
+
+/// Writes entries until the recording is stopped. The file is flushed whenever there are no more
+/// entries waiting, so that the recording is intact even if the process crashes.
+fn write_entries(
+    mut file: BufWriter<File>,
+    receiver: &std_mpsc::Receiver<Vec<u8>>,
+) -> std::io::Result<()> {
+    let result = (|| {
+        while let Ok(entry) = receiver.recv() {
+            file.write_all(&entry)?;
+
+            while let Ok(entry) = receiver.try_recv() {
+                file.write_all(&entry)?;
+            }
+
+            file.flush()?;
+        }
+
+        Ok(())
+    })();
+
+    if let Err(error) = &result {
+        warn!("Failed to write to the recording, so it was stopped: {error}");
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use chia_protocol::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn test_recording_roundtrip() -> Result<(), ClientError> {
+        let recording = Recording {
+            messages: vec![
+                RecordedMessage {
+                    direction: MessageDirection::Outbound,
+                    elapsed: Duration::from_millis(5),
+                    message: Message {
+                        msg_type: ProtocolMessageTypes::RequestChildren,
+                        id: Some(1),
+                        data: Bytes::new(vec![1; 32]),
+                    },
+                },
+                RecordedMessage {
+                    direction: MessageDirection::Inbound,
+                    elapsed: Duration::from_millis(20),
+                    message: Message {
+                        msg_type: ProtocolMessageTypes::RespondChildren,
+                        id: Some(1),
+                        data: Bytes::new(vec![0; 4]),
+                    },
+                },
+            ],
+        };
+
+        let bytes = recording.to_bytes()?;
+        assert_eq!(Recording::from_bytes(&bytes)?, recording);
+
+        assert!(Recording::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Recording::from_bytes(b"CHIAREC2").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_recorder() -> Result<(), ClientError> {
+        let path =
+            std::env::temp_dir().join(format!("chia-session-recorder-{}.bin", std::process::id()));
+
+        let messages: Vec<Message> = (0..100)
+            .map(|id| Message {
+                msg_type: ProtocolMessageTypes::RequestChildren,
+                id: Some(id),
+                data: Bytes::new(vec![1; 32]),
+            })
+            .collect();
+
+        let recorder = SessionRecorder::default();
+        recorder.record(MessageDirection::Outbound, &messages[0].to_bytes()?);
+
+        recorder.start(&path, None)?;
+        for message in &messages {
+            recorder.record(MessageDirection::Outbound, &message.to_bytes()?);
+        }
+        recorder.stop()?;
+
+        // Only the messages while recording are written, and all of them are written by the time
+        // the recording is stopped.
+        recorder.record(MessageDirection::Outbound, &messages[0].to_bytes()?);
+
+        let recording = Recording::load(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(
+            recording
+                .messages
+                .into_iter()
+                .map(|recorded| recorded.message)
+                .collect::<Vec<_>>(),
+            messages
+        );
+
+        Ok(())
+    }
+}
@@ -1,7 +1,13 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+};
 
 use chia_protocol::Message;
-use tokio::sync::{oneshot, Mutex, OwnedSemaphorePermit, Semaphore};
+use tokio::sync::{oneshot, OwnedSemaphorePermit, Semaphore};
+
+/// How many expired request ids are reserved at once, before the oldest are reused.
+const MAX_EXPIRED: usize = 1 << 15;
 
 #[derive(Debug)]
 pub(crate) struct Request {
@@ -15,16 +21,41 @@ impl Request {
     }
 }
 
+#[derive(Debug, Default)]
+struct RequestMapState {
+    items: HashMap<u16, Request>,
+    // Ids of requests which were abandoned before a response arrived.
+    // They are reserved until the late response is received, so that it can't be mistaken for
+    // a response to a different request. If it never arrives, the id is eventually reused.
+    expired: HashSet<u16>,
+    // The expired ids, oldest first.
+    expired_order: VecDeque<u16>,
+    // Ids are handed out in order, so that they aren't reused sooner than necessary.
+    next_id: u16,
+}
+
+impl RequestMapState {
+    fn is_free(&self, id: u16) -> bool {
+        !self.items.contains_key(&id) && !self.expired.contains(&id)
+    }
+
+    fn evict_oldest_expired(&mut self) -> Option<u16> {
+        let id = self.expired_order.pop_front()?;
+        self.expired.remove(&id);
+        Some(id)
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct RequestMap {
-    items: Mutex<HashMap<u16, Request>>,
+    state: Mutex<RequestMapState>,
     semaphore: Arc<Semaphore>,
 }
 
 impl RequestMap {
     pub(crate) fn new() -> Self {
         Self {
-            items: Mutex::new(HashMap::new()),
+            state: Mutex::new(RequestMapState::default()),
             semaphore: Arc::new(Semaphore::new(u16::MAX as usize)),
         }
     }
@@ -37,15 +68,21 @@ impl RequestMap {
             .await
             .expect("semaphore closed");
 
-        let mut items = self.items.lock().await;
-
-        items.retain(|_, v| !v.sender.is_closed());
+        let mut state = self.state.lock().expect("request map lock poisoned");
 
         let index = (0..=u16::MAX)
-            .find(|i| !items.contains_key(i))
-            .expect("exceeded expected number of requests");
+            .map(|offset| state.next_id.wrapping_add(offset))
+            .find(|&id| state.is_free(id));
 
-        items.insert(
+        // The semaphore leaves at least one id which isn't pending, so if they're all taken,
+        // the oldest expired one can be reused.
+        let index = index
+            .or_else(|| state.evict_oldest_expired())
+            .expect("no request ids available");
+
+        state.next_id = index.wrapping_add(1);
+
+        state.items.insert(
             index,
             Request {
                 sender,
@@ -55,7 +92,114 @@ impl RequestMap {
         index
     }
 
-    pub(crate) async fn remove(&self, id: u16) -> Option<Request> {
-        self.items.lock().await.remove(&id)
+    pub(crate) fn remove(&self, id: u16) -> Option<Request> {
+        self.state
+            .lock()
+            .expect("request map lock poisoned")
+            .items
+            .remove(&id)
+    }
+
+    /// Releases a pending request which is no longer being waited on.
+    pub(crate) fn expire(&self, id: u16) {
+        let mut state = self.state.lock().expect("request map lock poisoned");
+
+        if state.items.remove(&id).is_some() {
+            if state.expired.len() >= MAX_EXPIRED {
+                state.evict_oldest_expired();
+            }
+
+            state.expired.insert(id);
+            state.expired_order.push_back(id);
+        }
+    }
+
+    /// The number of pending and expired requests.
+    pub(crate) fn len(&self) -> (usize, usize) {
+        let state = self.state.lock().expect("request map lock poisoned");
+        (state.items.len(), state.expired.len())
+    }
+
+    /// Returns `true` if the id belonged to an expired request, and frees it up for reuse.
+    pub(crate) fn remove_expired(&self, id: u16) -> bool {
+        let mut state = self.state.lock().expect("request map lock poisoned");
+
+        if !state.expired.remove(&id) {
+            return false;
+        }
+
+        state.expired_order.retain(|&expired| expired != id);
+        true
+    }
+}
+
+/// Expires the request when dropped, unless it has completed.
+/// This handles both timeouts and the request future being cancelled.
+pub(crate) struct PendingRequest<'a> {
+    requests: &'a RequestMap,
+    id: u16,
+    completed: bool,
+}
+
+impl<'a> PendingRequest<'a> {
+    pub(crate) fn new(requests: &'a RequestMap, id: u16) -> Self {
+        Self {
+            requests,
+            id,
+            completed: false,
+        }
+    }
+
+    pub(crate) fn complete(mut self) {
+        self.completed = true;
+    }
+}
+
+impl Drop for PendingRequest<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.requests.expire(self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_expired_ids_are_reused() {
+        let requests = RequestMap::new();
+        let mut last_id = 0;
+
+        // Time out more requests than there are ids.
+        for _ in 0..=u16::MAX as usize * 2 {
+            let (sender, _receiver) = oneshot::channel();
+            last_id = requests.insert(sender).await;
+            drop(PendingRequest::new(&requests, last_id));
+        }
+
+        assert_eq!(requests.len(), (0, MAX_EXPIRED));
+
+        // The most recently expired ids are still reserved for late responses.
+        assert!(requests.remove_expired(last_id));
+        assert!(!requests.remove_expired(last_id));
+        assert_eq!(requests.len(), (0, MAX_EXPIRED - 1));
+    }
+
+    #[tokio::test]
+    async fn test_oldest_expired_id_is_evicted() {
+        let requests = RequestMap::new();
+        let state = RequestMapState {
+            expired: (0..=u16::MAX).collect(),
+            expired_order: (0..=u16::MAX).collect(),
+            ..Default::default()
+        };
+        *requests.state.lock().unwrap() = state;
+
+        let (sender, _receiver) = oneshot::channel();
+        assert_eq!(requests.insert(sender).await, 0);
+        assert!(!requests.remove_expired(0));
+        assert!(requests.remove_expired(1));
     }
 }
@@ -0,0 +1,600 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    pin::pin,
+    sync::Arc,
+    time::Duration,
+};
+
+use chia_protocol::{
+    Bytes32, ChiaProtocolMessage, CoinStateUpdate, Message, NewPeakWallet, NodeType,
+    ProtocolMessageTypes, RespondRemoveCoinSubscriptions, RespondRemovePuzzleSubscriptions,
+    RespondToCoinUpdates, RespondToPhUpdates,
+};
+use chia_traits::Streamable;
+use futures_util::future::{select, Either};
+use tokio::{
+    sync::{mpsc, Mutex},
+    task::JoinHandle,
+};
+use tokio_tungstenite::Connector;
+use tracing::{info, warn};
+
+use crate::{connect::connect_node, ClientError, Peer, PeerOptions, Proxy};
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectOptions {
+    pub initial_delay: Duration,
+    /// The delay doubles after each failed attempt, up to this maximum.
+    pub max_delay: Duration,
+    /// How many times to try reconnecting before giving up, or `None` to retry forever.
+    pub max_attempts: Option<u32>,
+    /// How many blocks below the last fork point to restore subscriptions from, so that coin states
+    /// which changed in a reorg while disconnected aren't missed.
+    pub reorg_margin: u32,
+}
+
+impl Default for ReconnectOptions {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            max_attempts: None,
+            reorg_margin: 32,
+        }
+    }
+}
+
+/// A peer connection which automatically reconnects when the websocket drops.
+///
+/// Subscriptions made through this handle are remembered, and registered again after reconnecting,
+/// starting [`ReconnectOptions::reorg_margin`] blocks below the last fork point that was received. The coin states returned when restoring
+/// subscriptions are forwarded to the receiver as [`RespondToPhUpdates`] and [`RespondToCoinUpdates`]
+/// messages, so that the consumer sees one continuous stream of updates.
+#[derive(Debug, Clone)]
+pub struct ResilientPeer(Arc<ResilientPeerInner>);
+
+#[derive(Debug)]
+struct ResilientPeerInner {
+    peer: Arc<Mutex<Peer>>,
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Debug, Default)]
+struct Subscriptions {
+    // The minimum height that each subscription was registered with.
+    puzzle_hashes: HashMap<Bytes32, u32>,
+    coin_ids: HashMap<Bytes32, u32>,
+}
+
+impl ResilientPeer {
+    pub async fn connect(
+        network_id: String,
+        connector: Connector,
+        socket_addr: SocketAddr,
+        options: PeerOptions,
+        reconnect_options: ReconnectOptions,
+    ) -> Result<(Self, mpsc::Receiver<Message>), ClientError> {
+        Self::start(
+            network_id,
+            connector,
+            socket_addr,
+            options,
+            reconnect_options,
+            None,
+        )
+        .await
+    }
+
+    /// Connects through a proxy, which is also used to reconnect.
+    pub async fn connect_with_proxy(
+        network_id: String,
+        connector: Connector,
+        socket_addr: SocketAddr,
+        options: PeerOptions,
+        reconnect_options: ReconnectOptions,
+        proxy: Proxy,
+    ) -> Result<(Self, mpsc::Receiver<Message>), ClientError> {
+        Self::start(
+            network_id,
+            connector,
+            socket_addr,
+            options,
+            reconnect_options,
+            Some(proxy),
+        )
+        .await
+    }
+
+    async fn start(
+        network_id: String,
+        connector: Connector,
+        socket_addr: SocketAddr,
+        options: PeerOptions,
+        reconnect_options: ReconnectOptions,
+        proxy: Option<Proxy>,
+    ) -> Result<(Self, mpsc::Receiver<Message>), ClientError> {
+        let (peer, receiver) = connect_node(
+            network_id.clone(),
+            connector.clone(),
+            socket_addr,
+            options,
+            NodeType::FullNode,
+            proxy.as_ref(),
+        )
+        .await?;
+
+        let (sender, consumer_receiver) = mpsc::channel(32);
+        let peer = Arc::new(Mutex::new(peer));
+        let subscriptions = Arc::new(Mutex::new(Subscriptions::default()));
+
+        let connection = Connection {
+            network_id,
+            connector,
+            socket_addr,
+            options,
+            reconnect_options,
+            proxy,
+            peer: peer.clone(),
+            subscriptions: subscriptions.clone(),
+            fork_height: std::sync::Mutex::new(None),
+            sender,
+        };
+
+        let task = tokio::spawn(connection.run(receiver));
+
+        Ok((
+            Self(Arc::new(ResilientPeerInner {
+                peer,
+                subscriptions,
+                task,
+            })),
+            consumer_receiver,
+        ))
+    }
+
+    /// The current connection. This may be disconnected if a reconnect is in progress.
+    pub async fn peer(&self) -> Peer {
+        self.0.peer.lock().await.clone()
+    }
+
+    pub async fn register_for_ph_updates(
+        &self,
+        puzzle_hashes: Vec<Bytes32>,
+        min_height: u32,
+    ) -> Result<RespondToPhUpdates, ClientError> {
+        let response = self
+            .peer()
+            .await
+            .register_for_ph_updates(puzzle_hashes.clone(), min_height)
+            .await?;
+
+        let mut subscriptions = self.0.subscriptions.lock().await;
+        for puzzle_hash in puzzle_hashes {
+            subscriptions.puzzle_hashes.insert(puzzle_hash, min_height);
+        }
+
+        Ok(response)
+    }
+
+    pub async fn register_for_coin_updates(
+        &self,
+        coin_ids: Vec<Bytes32>,
+        min_height: u32,
+    ) -> Result<RespondToCoinUpdates, ClientError> {
+        let response = self
+            .peer()
+            .await
+            .register_for_coin_updates(coin_ids.clone(), min_height)
+            .await?;
+
+        let mut subscriptions = self.0.subscriptions.lock().await;
+        for coin_id in coin_ids {
+            subscriptions.coin_ids.insert(coin_id, min_height);
+        }
+
+        Ok(response)
+    }
+
+    pub async fn remove_puzzle_subscriptions(
+        &self,
+        puzzle_hashes: Option<Vec<Bytes32>>,
+    ) -> Result<RespondRemovePuzzleSubscriptions, ClientError> {
+        {
+            let mut subscriptions = self.0.subscriptions.lock().await;
+            if let Some(puzzle_hashes) = &puzzle_hashes {
+                for puzzle_hash in puzzle_hashes {
+                    subscriptions.puzzle_hashes.remove(puzzle_hash);
+                }
+            } else {
+                subscriptions.puzzle_hashes.clear();
+            }
+        }
+
+        self.peer()
+            .await
+            .remove_puzzle_subscriptions(puzzle_hashes)
+            .await
+    }
+
+    pub async fn remove_coin_subscriptions(
+        &self,
+        coin_ids: Option<Vec<Bytes32>>,
+    ) -> Result<RespondRemoveCoinSubscriptions, ClientError> {
+        {
+            let mut subscriptions = self.0.subscriptions.lock().await;
+            if let Some(coin_ids) = &coin_ids {
+                for coin_id in coin_ids {
+                    subscriptions.coin_ids.remove(coin_id);
+                }
+            } else {
+                subscriptions.coin_ids.clear();
+            }
+        }
+
+        self.peer().await.remove_coin_subscriptions(coin_ids).await
+    }
+
+    pub async fn puzzle_subscriptions(&self) -> Vec<Bytes32> {
+        let subscriptions = self.0.subscriptions.lock().await;
+        subscriptions.puzzle_hashes.keys().copied().collect()
+    }
+
+    pub async fn coin_subscriptions(&self) -> Vec<Bytes32> {
+        let subscriptions = self.0.subscriptions.lock().await;
+        subscriptions.coin_ids.keys().copied().collect()
+    }
+}
+
+impl Drop for ResilientPeerInner {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct Connection {
+    network_id: String,
+    connector: Connector,
+    socket_addr: SocketAddr,
+    options: PeerOptions,
+    reconnect_options: ReconnectOptions,
+    proxy: Option<Proxy>,
+    peer: Arc<Mutex<Peer>>,
+    subscriptions: Arc<Mutex<Subscriptions>>,
+    // The fork point of the last peak, below which the coin states that were received are final.
+    // This is updated without awaiting, so that forwarding messages stays cancel safe.
+    fork_height: std::sync::Mutex<Option<u32>>,
+    sender: mpsc::Sender<Message>,
+}
+
+impl Connection {
+    async fn run(self, mut receiver: mpsc::Receiver<Message>) {
+        loop {
+            if !self.forward_messages(&mut receiver).await {
+                return;
+            }
+
+            warn!("Connection to {} was lost", self.socket_addr);
+
+            let Some((peer, new_receiver)) = self.reconnect().await else {
+                return;
+            };
+
+            receiver = new_receiver;
+            *self.peer.lock().await = peer.clone();
+
+            // The new connection's messages must be forwarded while restoring, otherwise the peer's
+            // message channel can fill up before the responses to the restore requests are received.
+            let restore = pin!(self.restore_subscriptions(&peer));
+            let forward = pin!(self.forward_messages(&mut receiver));
+
+            match select(restore, forward).await {
+                Either::Left((Ok(()), _)) | Either::Right((true, _)) => {}
+                Either::Left((Err(error), _)) => {
+                    warn!(
+                        "Failed to restore subscriptions on {}: {error}",
+                        self.socket_addr
+                    );
+                    peer.close().await.ok();
+                }
+                Either::Right((false, _)) => return,
+            }
+        }
+    }
+
+    /// Forwards messages to the consumer until the connection is lost, and returns `false` if the
+    /// consumer has been dropped. Messages aren't lost if this is cancelled.
+    async fn forward_messages(&self, receiver: &mut mpsc::Receiver<Message>) -> bool {
+        loop {
+            let Ok(permit) = self.sender.reserve().await else {
+                return false;
+            };
+
+            let Some(message) = receiver.recv().await else {
+                return true;
+            };
+
+            self.track_fork_height(&message);
+            permit.send(message);
+        }
+    }
+
+    fn track_fork_height(&self, message: &Message) {
+        let fork_height = match message.msg_type {
+            ProtocolMessageTypes::NewPeakWallet => NewPeakWallet::from_bytes(&message.data)
+                .ok()
+                .map(|new_peak| new_peak.fork_point_with_previous_peak),
+            ProtocolMessageTypes::CoinStateUpdate => CoinStateUpdate::from_bytes(&message.data)
+                .ok()
+                .map(|update| update.fork_height),
+            _ => None,
+        };
+
+        if let Some(fork_height) = fork_height {
+            *self.fork_height.lock().expect("fork height lock poisoned") = Some(fork_height);
+        }
+    }
+
+    async fn reconnect(&self) -> Option<(Peer, mpsc::Receiver<Message>)> {
+        let mut delay = self.reconnect_options.initial_delay;
+        let mut attempts = 0;
+
+        while !self.sender.is_closed() {
+            if self
+                .reconnect_options
+                .max_attempts
+                .is_some_and(|max_attempts| attempts >= max_attempts)
+            {
+                warn!(
+                    "Giving up on reconnecting to {} after {attempts} attempts",
+                    self.socket_addr
+                );
+                return None;
+            }
+
+            tokio::time::sleep(delay).await;
+            attempts += 1;
+
+            match connect_node(
+                self.network_id.clone(),
+                self.connector.clone(),
+                self.socket_addr,
+                self.options,
+                NodeType::FullNode,
+                self.proxy.as_ref(),
+            )
+            .await
+            {
+                Ok(result) => {
+                    info!("Reconnected to {}", self.socket_addr);
+                    return Some(result);
+                }
+                Err(error) => {
+                    warn!("Failed to reconnect to {}: {error}", self.socket_addr);
+                    delay = (delay * 2).min(self.reconnect_options.max_delay);
+                }
+            }
+        }
+
+        None
+    }
+
+    async fn restore_subscriptions(&self, peer: &Peer) -> Result<(), ClientError> {
+        let restore_height = self
+            .fork_height
+            .lock()
+            .expect("fork height lock poisoned")
+            .unwrap_or(0)
+            .saturating_sub(self.reconnect_options.reorg_margin);
+
+        let (puzzle_hashes, coin_ids) = {
+            let subscriptions = self.subscriptions.lock().await;
+            (
+                group_by_min_height(&subscriptions.puzzle_hashes, restore_height),
+                group_by_min_height(&subscriptions.coin_ids, restore_height),
+            )
+        };
+
+        for (min_height, puzzle_hashes) in puzzle_hashes {
+            let response = peer
+                .register_for_ph_updates(puzzle_hashes, min_height)
+                .await?;
+            self.forward(response).await?;
+        }
+
+        for (min_height, coin_ids) in coin_ids {
+            let response = peer.register_for_coin_updates(coin_ids, min_height).await?;
+            self.forward(response).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn forward<T>(&self, body: T) -> Result<(), ClientError>
+    where
+        T: Streamable + ChiaProtocolMessage,
+    {
+        self.sender
+            .send(Message {
+                msg_type: T::msg_type(),
+                id: None,
+                data: body.to_bytes()?.into(),
+            })
+            .await
+            .ok();
+        Ok(())
+    }
+}
+
+fn group_by_min_height(
+    items: &HashMap<Bytes32, u32>,
+    restore_height: u32,
+) -> BTreeMap<u32, Vec<Bytes32>> {
+    let mut groups = BTreeMap::<u32, Vec<Bytes32>>::new();
+    for (&item, &min_height) in items {
+        groups
+            .entry(min_height.max(restore_height))
+            .or_default()
+            .push(item);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use chia_protocol::RegisterForPhUpdates;
+
+    use crate::{
+        test_server::{test_connector, TestServer, NETWORK_ID},
+        to_message, InboundPeer, RequestHandler,
+    };
+
+    use super::*;
+
+    const FORK_HEIGHT: u32 = 90;
+    const UNANSWERED: Bytes32 = Bytes32::new([9; 32]);
+
+    /// Records puzzle hash subscriptions, and floods the peer with new peaks before responding.
+    #[derive(Default)]
+    struct SubscriptionHandler {
+        registrations: std::sync::Mutex<Vec<(Vec<Bytes32>, u32)>>,
+        flood: AtomicU32,
+    }
+
+    fn new_peak(height: u32) -> NewPeakWallet {
+        NewPeakWallet::new(Bytes32::default(), height, 0, FORK_HEIGHT)
+    }
+
+    impl RequestHandler for Arc<SubscriptionHandler> {
+        async fn handle(
+            &self,
+            peer: &InboundPeer,
+            message: Message,
+        ) -> Result<Option<Message>, ClientError> {
+            if message.msg_type != ProtocolMessageTypes::RegisterForPhUpdates {
+                return Ok(None);
+            }
+
+            let request = RegisterForPhUpdates::from_bytes(&message.data)?;
+
+            if request.puzzle_hashes.contains(&UNANSWERED) {
+                return Ok(None);
+            }
+
+            self.registrations
+                .lock()
+                .unwrap()
+                .push((request.puzzle_hashes.clone(), request.min_height));
+
+            for height in 0..self.flood.load(Ordering::SeqCst) {
+                peer.send(new_peak(FORK_HEIGHT + 1 + height)).await?;
+            }
+
+            Ok(Some(to_message(&RespondToPhUpdates::new(
+                request.puzzle_hashes,
+                request.min_height,
+                Vec::new(),
+            ))?))
+        }
+
+        async fn connected(&self, peer: &InboundPeer) {
+            peer.send(new_peak(FORK_HEIGHT + 10)).await.ok();
+        }
+    }
+
+    async fn connect(
+        server: &TestServer<Arc<SubscriptionHandler>>,
+        max_attempts: Option<u32>,
+    ) -> Result<(ResilientPeer, mpsc::Receiver<Message>), ClientError> {
+        ResilientPeer::connect(
+            NETWORK_ID.to_string(),
+            test_connector()?,
+            server.addr,
+            PeerOptions {
+                request_timeout: Duration::from_millis(500),
+                ..Default::default()
+            },
+            ReconnectOptions {
+                initial_delay: Duration::from_millis(10),
+                max_attempts,
+                reorg_margin: 5,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_restores_subscriptions() -> Result<(), ClientError> {
+        let handler = Arc::new(SubscriptionHandler::default());
+        let server = TestServer::start("127.0.0.1:0", handler.clone()).await?;
+        let (peer, mut receiver) = connect(&server, None).await?;
+
+        let message = receiver.recv().await.expect("missing new peak");
+        assert_eq!(message.msg_type, ProtocolMessageTypes::NewPeakWallet);
+
+        let puzzle_hash = Bytes32::new([1; 32]);
+        peer.register_for_ph_updates(vec![puzzle_hash], 0).await?;
+
+        // Subscriptions which fail aren't restored.
+        assert!(matches!(
+            peer.register_for_ph_updates(vec![UNANSWERED], 0).await,
+            Err(ClientError::Timeout(..))
+        ));
+        assert_eq!(peer.puzzle_subscriptions().await, vec![puzzle_hash]);
+
+        // More messages are sent while restoring than fit in the peer's message channel.
+        handler.flood.store(100, Ordering::SeqCst);
+        for inbound in server.server.peers() {
+            inbound.close();
+        }
+
+        let mut new_peaks = 0;
+        let response = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let message = receiver.recv().await.expect("consumer was closed");
+                match message.msg_type {
+                    ProtocolMessageTypes::NewPeakWallet => new_peaks += 1,
+                    ProtocolMessageTypes::RespondToPhUpdates => {
+                        return RespondToPhUpdates::from_bytes(&message.data);
+                    }
+                    _ => {}
+                }
+            }
+        })
+        .await
+        .expect("timed out waiting for the subscriptions to be restored")?;
+
+        assert_eq!(response.puzzle_hashes, vec![puzzle_hash]);
+        assert!(new_peaks >= 1);
+
+        // The subscription is restored from below the fork point of the last peak.
+        let registrations = handler.registrations.lock().unwrap().clone();
+        assert_eq!(
+            registrations,
+            vec![(vec![puzzle_hash], 0), (vec![puzzle_hash], FORK_HEIGHT - 5)]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_gives_up() -> Result<(), ClientError> {
+        let handler = Arc::new(SubscriptionHandler::default());
+        let server = TestServer::start("127.0.0.1:0", handler).await?;
+        let (_peer, mut receiver) = connect(&server, Some(2)).await?;
+
+        server.stop();
+
+        let closed = tokio::time::timeout(Duration::from_secs(10), async {
+            while receiver.recv().await.is_some() {}
+        })
+        .await;
+        assert!(closed.is_ok());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,740 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::SocketAddr,
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use chia_protocol::{ChiaProtocolMessage, Handshake, Message, NodeType, ProtocolMessageTypes};
+use chia_traits::Streamable;
+use futures_util::{
+    future::{select, Either},
+    SinkExt, Stream, StreamExt,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    sync::{mpsc, Notify, OwnedSemaphorePermit, Semaphore},
+};
+use tracing::{debug, info_span, warn, Instrument};
+
+use crate::{
+    Capability, ClientError, RateLimiter, PROTOCOL_VERSION, V2_RATE_LIMITS, WALLET_CAPABILITIES,
+};
+
+/// Handles the messages received by a [`Server`] from its inbound connections.
+pub trait RequestHandler: Send + Sync + 'static {
+    /// Handles a message from a connected peer. If the message is a request, the returned message is
+    /// sent back as the response. Returning an error closes the connection.
+    fn handle(
+        &self,
+        peer: &InboundPeer,
+        message: Message,
+    ) -> impl Future<Output = Result<Option<Message>, ClientError>> + Send;
+
+    /// Called once the handshake has completed, before any messages are handled.
+    fn connected(&self, _peer: &InboundPeer) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    fn disconnected(&self, _peer: &InboundPeer) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    /// Peers which send a handshake for a different network are disconnected.
+    pub network_id: String,
+    /// The node type that the server identifies as in its handshake.
+    pub node_type: NodeType,
+    /// Peers which send a handshake with any other node type are disconnected.
+    pub accepted_node_types: Vec<NodeType>,
+    /// The port advertised in the handshake, which other peers can connect to.
+    pub server_port: u16,
+    pub capabilities: Vec<Capability>,
+    pub rate_limit_factor: f64,
+    pub inbound_rate_limit_factor: f64,
+    /// How long to wait for each of the TLS and websocket handshakes, and then for the peer to
+    /// send its handshake, after connecting.
+    pub handshake_timeout: Duration,
+    /// The maximum number of inbound connections, including ones which are still handshaking.
+    /// Connections beyond this are closed as soon as they're accepted.
+    pub max_connections: usize,
+    /// The maximum number of messages from each peer which are handled at the same time.
+    /// Once this is reached, no more messages are read from the peer until one finishes.
+    pub max_concurrent_requests: usize,
+}
+
+impl ServerOptions {
+    pub fn new(network_id: String, node_type: NodeType) -> Self {
+        Self {
+            network_id,
+            node_type,
+            accepted_node_types: vec![NodeType::Wallet],
+            server_port: 0,
+            capabilities: WALLET_CAPABILITIES.to_vec(),
+            rate_limit_factor: 0.6,
+            inbound_rate_limit_factor: 1.0,
+            handshake_timeout: Duration::from_secs(10),
+            max_connections: 100,
+            max_concurrent_requests: 16,
+        }
+    }
+}
+
+/// A connection accepted by a [`Server`], which can be used to send messages to the peer.
+#[derive(Debug, Clone)]
+pub struct InboundPeer(Arc<InboundPeerInner>);
+
+#[derive(Debug)]
+struct InboundPeerInner {
+    socket_addr: SocketAddr,
+    handshake: Handshake,
+    sender: mpsc::Sender<Message>,
+    closed: AtomicBool,
+    close: Notify,
+}
+
+impl InboundPeer {
+    pub fn socket_addr(&self) -> SocketAddr {
+        self.0.socket_addr
+    }
+
+    /// The handshake that the peer sent when connecting.
+    pub fn handshake(&self) -> &Handshake {
+        &self.0.handshake
+    }
+
+    /// Whether the connection is still open.
+    pub fn is_connected(&self) -> bool {
+        !self.0.closed.load(Ordering::SeqCst)
+    }
+
+    /// Closes the connection, without waiting for queued messages to be sent.
+    pub fn close(&self) {
+        self.0.closed.store(true, Ordering::SeqCst);
+        self.0.close.notify_one();
+    }
+
+    /// Sends a message to the peer, which is not a response to any request.
+    pub async fn send<T>(&self, body: T) -> Result<(), ClientError>
+    where
+        T: Streamable + ChiaProtocolMessage,
+    {
+        self.send_raw(to_message(&body)?).await
+    }
+
+    pub async fn send_raw(&self, message: Message) -> Result<(), ClientError> {
+        self.0
+            .sender
+            .send(message)
+            .await
+            .map_err(|_| ClientError::Io(std::io::ErrorKind::NotConnected.into()))
+    }
+}
+
+/// Encodes a protocol message, so that it can be returned from a [`RequestHandler`].
+pub fn to_message<T>(body: &T) -> Result<Message, ClientError>
+where
+    T: Streamable + ChiaProtocolMessage,
+{
+    Ok(Message {
+        msg_type: T::msg_type(),
+        id: None,
+        data: body.to_bytes()?.into(),
+    })
+}
+
+/// Accepts inbound wallet protocol connections, and routes their messages to a [`RequestHandler`].
+#[derive(Debug)]
+pub struct Server<H> {
+    inner: Arc<ServerInner<H>>,
+}
+
+impl<H> Clone for Server<H> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ServerInner<H> {
+    handler: H,
+    options: ServerOptions,
+    peers: Mutex<HashMap<SocketAddr, InboundPeer>>,
+    connections: Arc<Semaphore>,
+}
+
+impl<H> Server<H>
+where
+    H: RequestHandler,
+{
+    pub fn new(handler: H, options: ServerOptions) -> Self {
+        let connections = Arc::new(Semaphore::new(options.max_connections));
+
+        Self {
+            inner: Arc::new(ServerInner {
+                handler,
+                options,
+                peers: Mutex::new(HashMap::new()),
+                connections,
+            }),
+        }
+    }
+
+    pub fn handler(&self) -> &H {
+        &self.inner.handler
+    }
+
+    pub fn options(&self) -> &ServerOptions {
+        &self.inner.options
+    }
+
+    /// The peers which have completed the handshake and are still connected.
+    pub fn peers(&self) -> Vec<InboundPeer> {
+        self.lock_peers().values().cloned().collect()
+    }
+
+    /// Accepts plain websocket connections from the listener until it fails.
+    /// This can be used behind a proxy which terminates TLS.
+    pub async fn serve(&self, listener: TcpListener) -> Result<(), ClientError> {
+        loop {
+            let (stream, socket_addr) = listener.accept().await?;
+
+            let Some(permit) = self.connection_permit(socket_addr) else {
+                continue;
+            };
+
+            let server = self.clone();
+
+            tokio::spawn(async move {
+                if let Err(error) = server.accept(stream, socket_addr).await {
+                    debug!("Inbound connection from {socket_addr} failed: {error}");
+                }
+
+                drop(permit);
+            });
+        }
+    }
+
+    /// Accepts TLS websocket connections from the listener until it fails, using the certificate.
+    #[cfg(feature = "native-tls")]
+    pub async fn serve_tls(
+        &self,
+        listener: TcpListener,
+        cert: &chia_ssl::ChiaCertificate,
+    ) -> Result<(), ClientError> {
+        let identity =
+            native_tls::Identity::from_pkcs8(cert.cert_pem.as_bytes(), cert.key_pem.as_bytes())?;
+        let acceptor = tokio_native_tls::TlsAcceptor::from(native_tls::TlsAcceptor::new(identity)?);
+
+        loop {
+            let (stream, socket_addr) = listener.accept().await?;
+
+            let Some(permit) = self.connection_permit(socket_addr) else {
+                continue;
+            };
+
+            let server = self.clone();
+            let acceptor = acceptor.clone();
+
+            tokio::spawn(async move {
+                let tls = tokio::time::timeout(
+                    server.inner.options.handshake_timeout,
+                    acceptor.accept(stream),
+                )
+                .await;
+
+                let result = match tls {
+                    Ok(Ok(stream)) => server.accept(stream, socket_addr).await,
+                    Ok(Err(error)) => Err(error.into()),
+                    Err(_) => Err(ClientError::MissingHandshake),
+                };
+
+                if let Err(error) = result {
+                    debug!("Inbound connection from {socket_addr} failed: {error}");
+                }
+
+                drop(permit);
+            });
+        }
+    }
+
+    /// Reserves a slot for a new connection, or returns `None` if there are too many already.
+    fn connection_permit(&self, socket_addr: SocketAddr) -> Option<OwnedSemaphorePermit> {
+        let permit = self.inner.connections.clone().try_acquire_owned().ok();
+
+        if permit.is_none() {
+            debug!("Rejecting inbound connection from {socket_addr}, since there are too many");
+        }
+
+        permit
+    }
+
+    /// Performs the websocket and protocol handshakes on the stream, and handles its messages
+    /// until the connection is closed. The stream can be wrapped in TLS by the caller.
+    pub async fn accept<S>(&self, stream: S, socket_addr: SocketAddr) -> Result<(), ClientError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let span = info_span!("inbound_peer", addr = %socket_addr);
+        self.run_connection(stream, socket_addr)
+            .instrument(span)
+            .await
+    }
+
+    async fn run_connection<S>(&self, stream: S, socket_addr: SocketAddr) -> Result<(), ClientError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let options = &self.inner.options;
+
+        let ws = tokio::time::timeout(
+            options.handshake_timeout,
+            tokio_tungstenite::accept_async(stream),
+        )
+        .await
+        .map_err(|_| ClientError::MissingHandshake)??;
+        let (mut sink, mut stream) = ws.split();
+
+        let handshake =
+            tokio::time::timeout(options.handshake_timeout, read_handshake(&mut stream))
+                .await
+                .map_err(|_| ClientError::MissingHandshake)??;
+
+        if handshake.network_id != options.network_id {
+            return Err(ClientError::WrongNetwork(
+                options.network_id.clone(),
+                handshake.network_id,
+            ));
+        }
+
+        if !options.accepted_node_types.contains(&handshake.node_type) {
+            return Err(ClientError::UnexpectedNodeType(handshake.node_type));
+        }
+
+        let response = to_message(&Handshake {
+            network_id: options.network_id.clone(),
+            protocol_version: PROTOCOL_VERSION.to_string(),
+            software_version: "0.0.0".to_string(),
+            server_port: options.server_port,
+            node_type: options.node_type,
+            capabilities: options
+                .capabilities
+                .iter()
+                .map(|&capability| (capability as u16, "1".to_string()))
+                .collect(),
+        })?;
+
+        sink.send(response.to_bytes()?.into()).await?;
+
+        let (sender, mut receiver) = mpsc::channel::<Message>(32);
+
+        let peer = InboundPeer(Arc::new(InboundPeerInner {
+            socket_addr,
+            handshake,
+            sender,
+            closed: AtomicBool::new(false),
+            close: Notify::new(),
+        }));
+
+        let mut outbound_rate_limiter =
+            RateLimiter::new(false, 60, options.rate_limit_factor, V2_RATE_LIMITS.clone());
+
+        let outbound_handle = tokio::spawn(
+            async move {
+                while let Some(message) = receiver.recv().await {
+                    while !outbound_rate_limiter.handle_message(&message) {
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+
+                    let bytes = match message.to_bytes() {
+                        Ok(bytes) => bytes,
+                        Err(error) => {
+                            warn!("Failed to encode {:?} message: {error}", message.msg_type);
+                            continue;
+                        }
+                    };
+
+                    if let Err(error) = sink.send(bytes.into()).await {
+                        debug!("Failed to send message: {error}");
+                        break;
+                    }
+                }
+
+                sink.close().await.ok();
+            }
+            .in_current_span(),
+        );
+
+        self.lock_peers().insert(socket_addr, peer.clone());
+        self.inner.handler.connected(&peer).await;
+
+        let result = self.handle_inbound_messages(&peer, &mut stream).await;
+
+        peer.close();
+        self.lock_peers().remove(&socket_addr);
+        self.inner.handler.disconnected(&peer).await;
+        outbound_handle.abort();
+
+        result
+    }
+
+    async fn handle_inbound_messages<S>(
+        &self,
+        peer: &InboundPeer,
+        stream: &mut S,
+    ) -> Result<(), ClientError>
+    where
+        S: Stream<Item = Result<tungstenite::Message, tungstenite::Error>> + Unpin,
+    {
+        use tungstenite::Message::{Binary, Close, Frame, Ping, Pong, Text};
+
+        let mut rate_limiter = RateLimiter::new(
+            true,
+            60,
+            self.inner.options.inbound_rate_limit_factor,
+            V2_RATE_LIMITS.clone(),
+        );
+
+        let semaphore = Arc::new(Semaphore::new(self.inner.options.max_concurrent_requests));
+
+        loop {
+            let closed = pin!(peer.0.close.notified());
+
+            let message = match select(stream.next(), closed).await {
+                Either::Left((Some(message), _)) => message?,
+                Either::Left((None, _)) | Either::Right(..) => break,
+            };
+
+            match message {
+                Frame(..) => unreachable!(),
+                Close(..) => break,
+                Ping(..) | Pong(..) => {}
+                Text(text) => {
+                    warn!("Received unexpected text message: {text}");
+                }
+                Binary(binary) => {
+                    let message = Message::from_bytes(&binary)?;
+
+                    if !rate_limiter.handle_message(&message) {
+                        warn!(
+                            "Received {:?} message which exceeded the inbound rate limit",
+                            message.msg_type
+                        );
+                        return Err(ClientError::RateLimitExceeded(message.msg_type));
+                    }
+
+                    // Requests are handled concurrently, so that a slow request doesn't block the rest.
+                    let closed = pin!(peer.0.close.notified());
+
+                    let permit = match select(pin!(semaphore.clone().acquire_owned()), closed).await
+                    {
+                        Either::Left((permit, _)) => {
+                            permit.expect("request semaphore is never closed")
+                        }
+                        Either::Right(..) => break,
+                    };
+
+                    let server = self.clone();
+                    let peer = peer.clone();
+
+                    tokio::spawn(
+                        async move {
+                            let id = message.id;
+                            let msg_type = message.msg_type;
+
+                            match server.inner.handler.handle(&peer, message).await {
+                                Ok(Some(mut response)) => {
+                                    response.id = id;
+                                    peer.send_raw(response).await.ok();
+                                }
+                                Ok(None) => {}
+                                Err(error) => {
+                                    warn!("Failed to handle {msg_type:?} message: {error}");
+                                    server.disconnect(peer.socket_addr());
+                                }
+                            }
+
+                            drop(permit);
+                        }
+                        .in_current_span(),
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Closes the connection to the peer, if it's connected.
+    pub fn disconnect(&self, socket_addr: SocketAddr) -> bool {
+        let Some(peer) = self.lock_peers().remove(&socket_addr) else {
+            return false;
+        };
+        peer.close();
+        true
+    }
+
+    fn lock_peers(&self) -> std::sync::MutexGuard<'_, HashMap<SocketAddr, InboundPeer>> {
+        self.inner.peers.lock().expect("peers lock poisoned")
+    }
+}
+
+async fn read_handshake<S>(stream: &mut S) -> Result<Handshake, ClientError>
+where
+    S: Stream<Item = Result<tungstenite::Message, tungstenite::Error>> + Unpin,
+{
+    while let Some(message) = stream.next().await {
+        let tungstenite::Message::Binary(binary) = message? else {
+            continue;
+        };
+
+        let message = Message::from_bytes(&binary)?;
+
+        if message.msg_type != ProtocolMessageTypes::Handshake {
+            return Err(ClientError::InvalidResponse(
+                vec![ProtocolMessageTypes::Handshake],
+                message.msg_type,
+            ));
+        }
+
+        return Ok(Handshake::from_bytes(&message.data)?);
+    }
+
+    Err(ClientError::MissingHandshake)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use chia_protocol::{RespondPeers, TimestampedPeerInfo};
+    use futures_util::future::try_join_all;
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::MaybeTlsStream;
+
+    use crate::{Peer, PeerOptions};
+
+    use super::*;
+
+    struct PeersHandler;
+
+    impl RequestHandler for PeersHandler {
+        async fn handle(
+            &self,
+            _peer: &InboundPeer,
+            message: Message,
+        ) -> Result<Option<Message>, ClientError> {
+            if message.msg_type != ProtocolMessageTypes::RequestPeers {
+                return Ok(None);
+            }
+
+            Ok(Some(to_message(&RespondPeers::new(vec![
+                TimestampedPeerInfo::new("127.0.0.1".to_string(), 8444, 0),
+            ]))?))
+        }
+    }
+
+    /// Counts how many requests are being handled at once, and responds to them after a delay.
+    #[derive(Default)]
+    struct SlowHandler {
+        active: AtomicUsize,
+        max_active: AtomicUsize,
+    }
+
+    impl RequestHandler for SlowHandler {
+        async fn handle(
+            &self,
+            _peer: &InboundPeer,
+            _message: Message,
+        ) -> Result<Option<Message>, ClientError> {
+            let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_active.fetch_max(active, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            self.active.fetch_sub(1, Ordering::SeqCst);
+
+            Ok(Some(to_message(&RespondPeers::new(Vec::new()))?))
+        }
+    }
+
+    async fn start_server() -> Result<(Server<PeersHandler>, SocketAddr), ClientError> {
+        start_server_with(
+            PeersHandler,
+            ServerOptions::new("testnet".to_string(), NodeType::FullNode),
+        )
+        .await
+    }
+
+    async fn start_server_with<H>(
+        handler: H,
+        options: ServerOptions,
+    ) -> Result<(Server<H>, SocketAddr), ClientError>
+    where
+        H: RequestHandler,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = Server::new(handler, options);
+
+        let serving = server.clone();
+        tokio::spawn(async move { serving.serve(listener).await });
+
+        Ok((server, addr))
+    }
+
+    async fn connect(
+        addr: SocketAddr,
+        network_id: &str,
+        node_type: NodeType,
+    ) -> Result<(Peer, mpsc::Receiver<Message>), ClientError> {
+        let stream = TcpStream::connect(addr).await?;
+        let (ws, _) =
+            tokio_tungstenite::client_async(format!("ws://{addr}"), MaybeTlsStream::Plain(stream))
+                .await?;
+
+        let (peer, receiver) = Peer::from_websocket(
+            ws,
+            PeerOptions {
+                trusted: true,
+                ..Default::default()
+            },
+        )?;
+
+        peer.send(Handshake {
+            network_id: network_id.to_string(),
+            protocol_version: PROTOCOL_VERSION.to_string(),
+            software_version: "0.0.0".to_string(),
+            server_port: 0,
+            node_type,
+            capabilities: Vec::new(),
+        })
+        .await?;
+
+        Ok((peer, receiver))
+    }
+
+    #[tokio::test]
+    async fn test_server_handles_requests() -> Result<(), ClientError> {
+        let (server, addr) = start_server().await?;
+        let (peer, mut receiver) = connect(addr, "testnet", NodeType::Wallet).await?;
+
+        let message = receiver.recv().await.ok_or(ClientError::MissingHandshake)?;
+        assert_eq!(message.msg_type, ProtocolMessageTypes::Handshake);
+        let handshake = Handshake::from_bytes(&message.data)?;
+        assert_eq!(handshake.node_type, NodeType::FullNode);
+        assert_eq!(handshake.network_id, "testnet");
+
+        let response = peer.request_peers().await?;
+        assert_eq!(response.peer_list.len(), 1);
+
+        let peers = server.peers();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].handshake().node_type, NodeType::Wallet);
+
+        assert!(server.disconnect(peers[0].socket_addr()));
+        assert!(!peers[0].is_connected());
+        while receiver.recv().await.is_some() {}
+        assert!(server.peers().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_server_rejects_wrong_network() -> Result<(), ClientError> {
+        let (server, addr) = start_server().await?;
+        let (_peer, mut receiver) = connect(addr, "mainnet", NodeType::Wallet).await?;
+
+        assert!(receiver.recv().await.is_none());
+        assert!(server.peers().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_server_rejects_wrong_node_type() -> Result<(), ClientError> {
+        let (server, addr) = start_server().await?;
+        let (_peer, mut receiver) = connect(addr, "testnet", NodeType::FullNode).await?;
+
+        assert!(receiver.recv().await.is_none());
+        assert!(server.peers().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_server_limits_concurrent_requests() -> Result<(), ClientError> {
+        let mut options = ServerOptions::new("testnet".to_string(), NodeType::FullNode);
+        options.max_concurrent_requests = 2;
+
+        let (server, addr) = start_server_with(SlowHandler::default(), options).await?;
+        let (peer, _receiver) = connect(addr, "testnet", NodeType::Wallet).await?;
+
+        try_join_all((0..5).map(|_| peer.request_peers())).await?;
+
+        assert_eq!(server.handler().max_active.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    /// Waits for the server to close the connection, without sending anything.
+    async fn wait_for_close(mut stream: TcpStream) -> Result<(), ClientError> {
+        use tokio::io::AsyncReadExt;
+
+        let mut buffer = [0; 1024];
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while stream.read(&mut buffer).await? > 0 {}
+            Ok::<_, ClientError>(())
+        })
+        .await
+        .expect("connection wasn't closed")
+    }
+
+    #[tokio::test]
+    async fn test_server_times_out_websocket_handshake() -> Result<(), ClientError> {
+        let mut options = ServerOptions::new("testnet".to_string(), NodeType::FullNode);
+        options.handshake_timeout = Duration::from_millis(100);
+
+        let (_server, addr) = start_server_with(PeersHandler, options).await?;
+        wait_for_close(TcpStream::connect(addr).await?).await
+    }
+
+    #[cfg(feature = "native-tls")]
+    #[tokio::test]
+    async fn test_server_times_out_tls_handshake() -> Result<(), ClientError> {
+        let mut options = ServerOptions::new("testnet".to_string(), NodeType::FullNode);
+        options.handshake_timeout = Duration::from_millis(100);
+
+        let server = crate::test_server::TestServer::start_with_options(
+            "127.0.0.1:0",
+            PeersHandler,
+            options,
+        )
+        .await?;
+        wait_for_close(TcpStream::connect(server.addr).await?).await
+    }
+
+    #[tokio::test]
+    async fn test_server_limits_connections() -> Result<(), ClientError> {
+        let mut options = ServerOptions::new("testnet".to_string(), NodeType::FullNode);
+        options.max_connections = 1;
+
+        let (server, addr) = start_server_with(PeersHandler, options).await?;
+        let (_peer, mut receiver) = connect(addr, "testnet", NodeType::Wallet).await?;
+        receiver.recv().await.ok_or(ClientError::MissingHandshake)?;
+
+        // The second connection is closed right away, while the first one is still open.
+        wait_for_close(TcpStream::connect(addr).await?).await?;
+        assert_eq!(server.peers().len(), 1);
+
+        Ok(())
+    }
+}
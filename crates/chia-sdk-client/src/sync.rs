@@ -0,0 +1,369 @@
+use std::{collections::HashSet, time::Duration};
+
+use chia_protocol::{
+    Bytes32, CoinState, CoinStateFilters, CoinStateUpdate, Message, ProtocolMessageTypes,
+    RejectStateReason,
+};
+use chia_traits::Streamable;
+use tracing::{debug, warn};
+
+use crate::{ClientError, Peer};
+
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    /// The maximum number of puzzle hashes to request in a single batch.
+    pub batch_size: usize,
+    pub filters: CoinStateFilters,
+    /// How many times a batch is restarted after being rejected due to a reorg.
+    pub max_retries: usize,
+    pub retry_delay: Duration,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 10_000,
+            filters: CoinStateFilters::new(true, true, true, 0),
+            max_retries: 5,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncEvent {
+    /// Coin states which were created or updated, as of the given peak.
+    CoinStates {
+        height: u32,
+        header_hash: Bytes32,
+        coin_states: Vec<CoinState>,
+    },
+    /// The chain was reorged back to the fork height.
+    /// Any coin states that were created or spent after this height need to be rolled back.
+    Reorg { fork_height: u32 },
+    /// The puzzle hashes weren't synced, since the peer's subscription limit has been reached.
+    /// They aren't tracked, and can be added again later or synced with a different peer.
+    SubscriptionLimitExceeded { puzzle_hashes: Vec<Bytes32> },
+}
+
+/// Keeps a set of puzzle hashes in sync with a peer.
+///
+/// Newly added puzzle hashes are synced from genesis in batches with [`Peer::request_puzzle_state`],
+/// and subscribed to once they are caught up to the peak. After that, live updates are received
+/// as [`CoinStateUpdate`] messages, which can be passed into [`WalletSync::handle_message`].
+#[derive(Debug)]
+pub struct WalletSync {
+    peer: Peer,
+    genesis_challenge: Bytes32,
+    options: SyncOptions,
+    puzzle_hashes: HashSet<Bytes32>,
+    peak: Option<(u32, Bytes32)>,
+}
+
+impl WalletSync {
+    pub fn new(peer: Peer, genesis_challenge: Bytes32, options: SyncOptions) -> Self {
+        Self {
+            peer,
+            genesis_challenge,
+            options,
+            puzzle_hashes: HashSet::new(),
+            peak: None,
+        }
+    }
+
+    pub fn peer(&self) -> &Peer {
+        &self.peer
+    }
+
+    pub fn puzzle_hashes(&self) -> &HashSet<Bytes32> {
+        &self.puzzle_hashes
+    }
+
+    /// The height and header hash that the wallet has been synced to.
+    pub fn peak(&self) -> Option<(u32, Bytes32)> {
+        self.peak
+    }
+
+    /// Syncs the coin states of the puzzle hashes which aren't already being tracked,
+    /// and subscribes to updates for them.
+    ///
+    /// If a batch exceeds the peer's subscription limit, it's split in half and retried, so that
+    /// as many puzzle hashes as possible are subscribed to. The rest are returned in a
+    /// [`SyncEvent::SubscriptionLimitExceeded`] event.
+    pub async fn add_puzzle_hashes(
+        &mut self,
+        puzzle_hashes: Vec<Bytes32>,
+    ) -> Result<Vec<SyncEvent>, ClientError> {
+        let puzzle_hashes: Vec<Bytes32> = puzzle_hashes
+            .into_iter()
+            .filter(|puzzle_hash| !self.puzzle_hashes.contains(puzzle_hash))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+
+        let mut events = Vec::new();
+        let mut rejected = Vec::new();
+
+        // The batches are synced in order, which is why they're popped from the end.
+        let mut batches: Vec<&[Bytes32]> = puzzle_hashes
+            .chunks(self.options.batch_size.max(1))
+            .rev()
+            .collect();
+
+        while let Some(batch) = batches.pop() {
+            let (height, header_hash, coin_states) = match self.sync_batch(batch).await {
+                Ok(result) => result,
+                Err(ClientError::PuzzleStateRejected(
+                    RejectStateReason::ExceededSubscriptionLimit,
+                )) => {
+                    if batch.len() == 1 {
+                        rejected.extend_from_slice(batch);
+                        continue;
+                    }
+
+                    debug!(
+                        "Batch of {} puzzle hashes exceeded the subscription limit, splitting it",
+                        batch.len()
+                    );
+
+                    let (first, second) = batch.split_at(batch.len() / 2);
+                    batches.push(second);
+                    batches.push(first);
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+
+            self.puzzle_hashes.extend(batch.iter().copied());
+
+            if self
+                .peak
+                .map_or(true, |(peak_height, _)| height > peak_height)
+            {
+                self.peak = Some((height, header_hash));
+            }
+
+            events.push(SyncEvent::CoinStates {
+                height,
+                header_hash,
+                coin_states,
+            });
+        }
+
+        if !rejected.is_empty() {
+            warn!(
+                "{} puzzle hashes weren't synced, since the subscription limit was reached",
+                rejected.len()
+            );
+            events.push(SyncEvent::SubscriptionLimitExceeded {
+                puzzle_hashes: rejected,
+            });
+        }
+
+        Ok(events)
+    }
+
+    /// Handles a message received from the peer, and returns the resulting events.
+    /// Messages other than [`CoinStateUpdate`] are ignored.
+    pub fn handle_message(&mut self, message: &Message) -> Result<Vec<SyncEvent>, ClientError> {
+        if message.msg_type != ProtocolMessageTypes::CoinStateUpdate {
+            return Ok(Vec::new());
+        }
+
+        let update = CoinStateUpdate::from_bytes(&message.data)?;
+        Ok(self.handle_update(update))
+    }
+
+    pub fn handle_update(&mut self, update: CoinStateUpdate) -> Vec<SyncEvent> {
+        let mut events = Vec::new();
+
+        if let Some((peak_height, _)) = self.peak {
+            if update.fork_height < peak_height && update.fork_height < update.height {
+                debug!(
+                    "Reorg detected from height {peak_height} back to {}",
+                    update.fork_height
+                );
+                events.push(SyncEvent::Reorg {
+                    fork_height: update.fork_height,
+                });
+            }
+        }
+
+        self.peak = Some((update.height, update.peak_hash));
+
+        events.push(SyncEvent::CoinStates {
+            height: update.height,
+            header_hash: update.peak_hash,
+            coin_states: update.items,
+        });
+
+        events
+    }
+
+    async fn sync_batch(
+        &self,
+        puzzle_hashes: &[Bytes32],
+    ) -> Result<(u32, Bytes32, Vec<CoinState>), ClientError> {
+        let mut retries = 0;
+        let mut coin_states = Vec::new();
+
+        // The height and header hash that each page was synced to, along with how many coin states
+        // had been received by then. After a reorg, syncing resumes from the last of these which
+        // is still part of the chain, rather than from genesis.
+        let mut checkpoints: Vec<(u32, Bytes32, usize)> = Vec::new();
+
+        loop {
+            let (previous_height, header_hash) = checkpoints.last().map_or(
+                (None, self.genesis_challenge),
+                |&(height, header_hash, _)| (Some(height), header_hash),
+            );
+
+            let response = self
+                .peer
+                .request_puzzle_state(
+                    puzzle_hashes.to_vec(),
+                    previous_height,
+                    header_hash,
+                    self.options.filters.clone(),
+                    true,
+                )
+                .await?;
+
+            let response = match response {
+                Ok(response) => response,
+                Err(rejection) if rejection.reason == RejectStateReason::Reorg => {
+                    if retries >= self.options.max_retries {
+                        return Err(rejection.into());
+                    }
+
+                    // The last checkpoint was reorged out, so fall back to the one before it.
+                    // If that one was reorged out as well, the next request will be rejected too.
+                    checkpoints.pop();
+                    coin_states.truncate(checkpoints.last().map_or(0, |&(_, _, len)| len));
+
+                    warn!(
+                        "Puzzle state request was rejected due to a reorg, resuming from height {}",
+                        checkpoints.last().map_or(0, |&(height, _, _)| height)
+                    );
+                    retries += 1;
+                    tokio::time::sleep(self.options.retry_delay).await;
+                    continue;
+                }
+                Err(rejection) => return Err(rejection.into()),
+            };
+
+            coin_states.extend(response.coin_states);
+
+            if response.is_finished {
+                return Ok((response.height, response.header_hash, coin_states));
+            }
+
+            checkpoints.push((response.height, response.header_hash, coin_states.len()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use chia_protocol::{Coin, RejectPuzzleState, RequestPuzzleState, RespondPuzzleState};
+
+    use crate::{test_server::TestServer, to_message, InboundPeer, RequestHandler};
+
+    use super::*;
+
+    fn header_hash(height: u8) -> Bytes32 {
+        Bytes32::new([height; 32])
+    }
+
+    fn coin_state(height: u32) -> CoinState {
+        let coin = Coin::new(Bytes32::default(), Bytes32::default(), height.into());
+        CoinState::new(coin, None, Some(height))
+    }
+
+    /// Serves one coin state per height, and rejects the request after height 2 due to a reorg.
+    #[derive(Default)]
+    struct ReorgHandler {
+        requests: Mutex<Vec<(Option<u32>, Bytes32)>>,
+    }
+
+    impl RequestHandler for ReorgHandler {
+        async fn handle(
+            &self,
+            _peer: &InboundPeer,
+            message: Message,
+        ) -> Result<Option<Message>, ClientError> {
+            let request = RequestPuzzleState::from_bytes(&message.data)?;
+
+            let mut requests = self.requests.lock().expect("requests lock poisoned");
+            requests.push((request.previous_height, request.header_hash));
+
+            let respond = |height: u8, is_finished: bool| {
+                to_message(&RespondPuzzleState::new(
+                    request.puzzle_hashes.clone(),
+                    height.into(),
+                    header_hash(height),
+                    is_finished,
+                    vec![coin_state(height.into())],
+                ))
+            };
+
+            let response = match requests.len() {
+                1 => respond(1, false)?,
+                2 => respond(2, false)?,
+                3 => to_message(&RejectPuzzleState::new(RejectStateReason::Reorg))?,
+                _ => respond(3, true)?,
+            };
+
+            Ok(Some(response))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_resumes_after_reorg() -> Result<(), ClientError> {
+        let server = TestServer::start_plain(ReorgHandler::default()).await?;
+        let (peer, _receiver) = server.connect_plain().await?;
+
+        let mut sync = WalletSync::new(
+            peer,
+            header_hash(0),
+            SyncOptions {
+                retry_delay: Duration::ZERO,
+                ..Default::default()
+            },
+        );
+
+        let events = sync.add_puzzle_hashes(vec![Bytes32::default()]).await?;
+
+        // The coin state at the reorged height is discarded, but the one before it is kept.
+        assert_eq!(
+            events,
+            vec![SyncEvent::CoinStates {
+                height: 3,
+                header_hash: header_hash(3),
+                coin_states: vec![coin_state(1), coin_state(3)],
+            }]
+        );
+
+        // Syncing resumed from the height before the rejected one, rather than from genesis.
+        let requests = server
+            .server
+            .handler()
+            .requests
+            .lock()
+            .expect("requests lock poisoned")
+            .clone();
+        assert_eq!(
+            requests,
+            vec![
+                (None, header_hash(0)),
+                (Some(1), header_hash(1)),
+                (Some(2), header_hash(2)),
+                (Some(1), header_hash(1)),
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+// Most of these helpers are only used by tests of the modules which require TLS.
+#![cfg_attr(not(feature = "native-tls"), allow(dead_code))]
+
+use std::net::{IpAddr, SocketAddr};
+
+use chia_protocol::{Message, NodeType, ProtocolMessageTypes, RespondPeers};
+use tokio::{
+    io::{copy_bidirectional, AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    task::JoinHandle,
+};
+use tokio_tungstenite::MaybeTlsStream;
+
+use crate::{
+    to_message, ClientError, InboundPeer, Peer, PeerOptions, RequestHandler, Server, ServerOptions,
+};
+
+#[cfg(feature = "native-tls")]
+use chia_ssl::ChiaCertificate;
+#[cfg(feature = "native-tls")]
+use once_cell::sync::Lazy;
+#[cfg(feature = "native-tls")]
+use tokio_tungstenite::Connector;
+
+#[cfg(feature = "native-tls")]
+use crate::{create_native_tls_connector, generate_ssl_cert};
+
+pub(crate) const NETWORK_ID: &str = "testnet11";
+
+// Generating a certificate is slow, so every server and client in the tests shares one.
+#[cfg(feature = "native-tls")]
+static CERT: Lazy<ChiaCertificate> =
+    Lazy::new(|| generate_ssl_cert().expect("failed to generate certificate"));
+
+/// A full node which the tests can connect to like a real peer.
+pub(crate) struct TestServer<H> {
+    pub(crate) server: Server<H>,
+    pub(crate) addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl<H> TestServer<H>
+where
+    H: RequestHandler,
+{
+    /// Starts the server on a loopback address. Each server needs its own IP address, since the
+    /// client only keeps one connection per IP address.
+    #[cfg(feature = "native-tls")]
+    pub(crate) async fn start(addr: &str, handler: H) -> Result<Self, ClientError> {
+        Self::start_with_options(
+            addr,
+            handler,
+            ServerOptions::new(NETWORK_ID.to_string(), NodeType::FullNode),
+        )
+        .await
+    }
+
+    #[cfg(feature = "native-tls")]
+    pub(crate) async fn start_with_options(
+        addr: &str,
+        handler: H,
+        options: ServerOptions,
+    ) -> Result<Self, ClientError> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let server = Server::new(handler, options);
+
+        let serving = server.clone();
+        let task = tokio::spawn(async move {
+            serving.serve_tls(listener, &CERT).await.ok();
+        });
+
+        Ok(Self { server, addr, task })
+    }
+
+    /// Starts the server without TLS, which can be connected to with [`TestServer::connect_plain`].
+    pub(crate) async fn start_plain(handler: H) -> Result<Self, ClientError> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = Server::new(
+            handler,
+            ServerOptions::new(NETWORK_ID.to_string(), NodeType::FullNode),
+        );
+
+        let serving = server.clone();
+        let task = tokio::spawn(async move {
+            serving.serve(listener).await.ok();
+        });
+
+        Ok(Self { server, addr, task })
+    }
+
+    /// Connects to a server started with [`TestServer::start_plain`], and performs the handshake.
+    pub(crate) async fn connect_plain(
+        &self,
+    ) -> Result<(Peer, mpsc::Receiver<Message>), ClientError> {
+        let stream = TcpStream::connect(self.addr).await?;
+        let (ws, _) = tokio_tungstenite::client_async(
+            format!("ws://{}", self.addr),
+            MaybeTlsStream::Plain(stream),
+        )
+        .await?;
+
+        let (peer, mut receiver) = Peer::from_websocket(
+            ws,
+            PeerOptions {
+                trusted: true,
+                ..Default::default()
+            },
+        )?;
+
+        peer.perform_handshake(&mut receiver, NETWORK_ID, NodeType::FullNode)
+            .await?;
+
+        Ok((peer, receiver))
+    }
+
+    /// Stops accepting connections, and closes the existing ones.
+    pub(crate) fn stop(&self) {
+        self.task.abort();
+
+        for peer in self.server.peers() {
+            peer.close();
+        }
+    }
+
+    /// Waits until a peer has completed the handshake.
+    pub(crate) async fn wait_for_peer(&self) -> InboundPeer {
+        loop {
+            if let Some(peer) = self.server.peers().pop() {
+                return peer;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }
+}
+
+impl<H> Drop for TestServer<H> {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Responds to requests for peers with an empty list, and ignores everything else.
+pub(crate) struct EmptyHandler;
+
+impl RequestHandler for EmptyHandler {
+    async fn handle(
+        &self,
+        _peer: &InboundPeer,
+        message: Message,
+    ) -> Result<Option<Message>, ClientError> {
+        if message.msg_type != ProtocolMessageTypes::RequestPeers {
+            return Ok(None);
+        }
+
+        Ok(Some(to_message(&RespondPeers::new(Vec::new()))?))
+    }
+}
+
+#[cfg(feature = "native-tls")]
+pub(crate) fn test_connector() -> Result<Connector, ClientError> {
+    create_native_tls_connector(&CERT)
+}
+
+/// A minimal SOCKS5 proxy, which accepts a single connection without authentication.
+/// Resolves to the host and port that it was asked to connect to, which can be a domain name.
+pub(crate) async fn socks5_proxy(
+) -> Result<(SocketAddr, JoinHandle<Result<String, ClientError>>), ClientError> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let handle = tokio::spawn(async move {
+        let (mut client, _) = listener.accept().await?;
+
+        let mut greeting = [0; 3];
+        client.read_exact(&mut greeting).await?;
+        client.write_all(&[5, 0]).await?;
+
+        let mut request = [0; 4];
+        client.read_exact(&mut request).await?;
+
+        let host = match request[3] {
+            1 => {
+                let mut octets = [0; 4];
+                client.read_exact(&mut octets).await?;
+                IpAddr::from(octets).to_string()
+            }
+            3 => {
+                let mut domain = vec![0; client.read_u8().await? as usize];
+                client.read_exact(&mut domain).await?;
+                String::from_utf8(domain)
+                    .map_err(|_| ClientError::Proxy("Invalid SOCKS domain".to_string()))?
+            }
+            4 => {
+                let mut octets = [0; 16];
+                client.read_exact(&mut octets).await?;
+                IpAddr::from(octets).to_string()
+            }
+            address_type => {
+                return Err(ClientError::Proxy(format!(
+                    "Unexpected SOCKS address type {address_type}"
+                )))
+            }
+        };
+
+        let port = client.read_u16().await?;
+
+        let mut server = TcpStream::connect((host.as_str(), port)).await?;
+        client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+
+        tokio::spawn(async move { copy_bidirectional(&mut client, &mut server).await });
+
+        Ok(format!("{host}:{port}"))
+    });
+
+    Ok((addr, handle))
+}
@@ -1,4 +1,4 @@
-use std::fs;
+use std::{fs, io::Write, path::Path};
 
 use chia_ssl::ChiaCertificate;
 
@@ -8,7 +8,12 @@ use tokio_tungstenite::Connector;
 use crate::ClientError;
 
 /// Loads an SSL certificate, or creates it if it doesn't exist already.
-pub fn load_ssl_cert(cert_path: &str, key_path: &str) -> Result<ChiaCertificate, ClientError> {
+pub fn load_ssl_cert(
+    cert_path: impl AsRef<Path>,
+    key_path: impl AsRef<Path>,
+) -> Result<ChiaCertificate, ClientError> {
+    let (cert_path, key_path) = (cert_path.as_ref(), key_path.as_ref());
+
     fs::read_to_string(cert_path)
         .and_then(|cert| {
             fs::read_to_string(key_path).map(|key| ChiaCertificate {
@@ -17,13 +22,60 @@ pub fn load_ssl_cert(cert_path: &str, key_path: &str) -> Result<ChiaCertificate,
             })
         })
         .or_else(|_| {
-            let cert = ChiaCertificate::generate()?;
-            fs::write(cert_path, &cert.cert_pem)?;
-            fs::write(key_path, &cert.key_pem)?;
+            let cert = generate_ssl_cert()?;
+            save_ssl_cert(&cert, cert_path, key_path)?;
             Ok(cert)
         })
 }
 
+/// Generates a new certificate signed by the Chia CA, which is only kept in memory.
+pub fn generate_ssl_cert() -> Result<ChiaCertificate, ClientError> {
+    Ok(ChiaCertificate::generate()?)
+}
+
+/// Writes a certificate to disk, creating any missing parent directories.
+/// On Unix, the private key is only readable by the current user.
+pub fn save_ssl_cert(
+    cert: &ChiaCertificate,
+    cert_path: impl AsRef<Path>,
+    key_path: impl AsRef<Path>,
+) -> Result<(), ClientError> {
+    let (cert_path, key_path) = (cert_path.as_ref(), key_path.as_ref());
+
+    for path in [cert_path, key_path] {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    fs::write(cert_path, &cert.cert_pem)?;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    // The key is never readable by other users, even while it's being written.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.mode(0o600);
+    }
+
+    let mut key_file = options.open(key_path)?;
+
+    // The mode only applies to new files, so an existing key file is restricted before writing.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        key_file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+
+    key_file.write_all(cert.key_pem.as_bytes())?;
+
+    Ok(())
+}
+
 /// Creates a native-tls connector from a certificate.
 #[cfg(feature = "native-tls")]
 pub fn create_native_tls_connector(cert: &ChiaCertificate) -> Result<Connector, ClientError> {
@@ -125,3 +177,48 @@ pub fn create_rustls_connector(cert: &ChiaCertificate) -> Result<Connector, Clie
 
     Ok(Connector::Rustls(Arc::new(config)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load_ssl_cert() -> Result<(), ClientError> {
+        let dir = std::env::temp_dir().join(format!("chia-sdk-client-tls-{}", std::process::id()));
+        let cert_path = dir.join("ssl").join("wallet.crt");
+        let key_path = dir.join("ssl").join("wallet.key");
+
+        let cert = generate_ssl_cert()?;
+        save_ssl_cert(&cert, &cert_path, &key_path)?;
+
+        let loaded = load_ssl_cert(&cert_path, &key_path)?;
+        assert_eq!(loaded.cert_pem, cert.cert_pem);
+        assert_eq!(loaded.key_pem, cert.key_pem);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = fs::metadata(&key_path)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        fs::remove_dir_all(dir)?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "native-tls")]
+    #[test]
+    fn test_generated_native_tls_connector() -> Result<(), ClientError> {
+        create_native_tls_connector(&generate_ssl_cert()?)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn test_generated_rustls_connector() -> Result<(), ClientError> {
+        create_rustls_connector(&generate_ssl_cert()?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use proc_macro::{Span, TokenStream};
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Expr, Fields, Ident, Type};
+
+struct LayerAttrs {
+    puzzle: Expr,
+    puzzle_hash: Expr,
+    args: Type,
+    solution: Type,
+}
+
+fn parse_attrs(input: &DeriveInput) -> syn::Result<LayerAttrs> {
+    let mut puzzle = None;
+    let mut puzzle_hash = None;
+    let mut args = None;
+    let mut solution = None;
+
+    for attr in &input.attrs {
+        if !attr.path().is_ident("layer") {
+            continue;
+        }
+
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("puzzle") {
+                puzzle = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("puzzle_hash") {
+                puzzle_hash = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("args") {
+                args = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("solution") {
+                solution = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("expected `puzzle`, `puzzle_hash`, `args` or `solution`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let missing = |name: &str| {
+        Error::new(
+            Span::call_site().into(),
+            format!("missing `{name}` in `#[layer(...)]` attribute"),
+        )
+    };
+
+    Ok(LayerAttrs {
+        puzzle: puzzle.ok_or_else(|| missing("puzzle"))?,
+        puzzle_hash: puzzle_hash.ok_or_else(|| missing("puzzle_hash"))?,
+        args: args.ok_or_else(|| missing("args"))?,
+        solution: solution.ok_or_else(|| missing("solution"))?,
+    })
+}
+
+fn field_names(input: &DeriveInput) -> syn::Result<Vec<Ident>> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "`Layer` cannot be derived for generic types",
+        ));
+    }
+
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "`Layer` can only be derived for structs",
+        ));
+    };
+
+    match &data.fields {
+        Fields::Named(fields) => Ok(fields
+            .named
+            .iter()
+            .filter_map(|field| field.ident.clone())
+            .collect()),
+        Fields::Unit => Ok(Vec::new()),
+        Fields::Unnamed(..) => Err(Error::new_spanned(
+            &input.ident,
+            "`Layer` can only be derived for structs with named fields",
+        )),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let LayerAttrs {
+        puzzle,
+        puzzle_hash,
+        args,
+        solution,
+    } = parse_attrs(input)?;
+
+    let fields = field_names(input)?;
+    let name = &input.ident;
+
+    let driver = quote!(::chia_sdk_driver);
+    let clvmr = quote!(#driver::__private::clvmr);
+    let clvm_traits = quote!(#driver::__private::clvm_traits);
+    let clvm_utils = quote!(#driver::__private::clvm_utils);
+    let result = quote!(::core::result::Result);
+    let option = quote!(::core::option::Option);
+
+    // The fields of the layer are curried into the puzzle by name, so they are cloned out of `self`.
+    let curried_args = quote! {
+        #args {
+            #( #fields: ::core::clone::Clone::clone(&self.#fields), )*
+        }
+    };
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl #driver::Layer for #name {
+            type Solution = #solution;
+
+            fn parse_puzzle(
+                allocator: &#clvmr::Allocator,
+                puzzle: #driver::Puzzle,
+            ) -> #result<#option<Self>, #driver::DriverError> {
+                let #option::Some(puzzle) = puzzle.as_curried() else {
+                    return #result::Ok(#option::None);
+                };
+
+                if puzzle.mod_hash != #puzzle_hash {
+                    return #result::Ok(#option::None);
+                }
+
+                let args = <#args as #clvm_traits::FromClvm<#clvmr::Allocator>>::from_clvm(
+                    allocator,
+                    puzzle.args,
+                )?;
+
+                #result::Ok(#option::Some(Self {
+                    #( #fields: args.#fields, )*
+                }))
+            }
+
+            fn parse_solution(
+                allocator: &#clvmr::Allocator,
+                solution: #clvmr::NodePtr,
+            ) -> #result<Self::Solution, #driver::DriverError> {
+                #result::Ok(
+                    <#solution as #clvm_traits::FromClvm<#clvmr::Allocator>>::from_clvm(
+                        allocator,
+                        solution,
+                    )?,
+                )
+            }
+
+            fn construct_puzzle(
+                &self,
+                ctx: &mut #driver::SpendContext,
+            ) -> #result<#clvmr::NodePtr, #driver::DriverError> {
+                let program = ctx.puzzle(#puzzle_hash, &#puzzle)?;
+                ctx.alloc(&#clvm_utils::CurriedProgram {
+                    program,
+                    args: #curried_args,
+                })
+            }
+
+            fn construct_solution(
+                &self,
+                ctx: &mut #driver::SpendContext,
+                solution: Self::Solution,
+            ) -> #result<#clvmr::NodePtr, #driver::DriverError> {
+                ctx.alloc(&solution)
+            }
+        }
+
+        #[automatically_derived]
+        impl #clvm_utils::ToTreeHash for #name {
+            fn tree_hash(&self) -> #clvm_utils::TreeHash {
+                #clvm_utils::ToTreeHash::tree_hash(&#clvm_utils::CurriedProgram {
+                    program: #puzzle_hash,
+                    args: #curried_args,
+                })
+            }
+        }
+    }
+    .into())
+}
+
+pub(crate) fn impl_layer(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(&input) {
+        Ok(tokens) => tokens,
+        Err(error) => error.to_compile_error().into(),
+    }
+}
@@ -1,10 +1,32 @@
 use proc_macro::TokenStream;
 
 mod impl_conditions;
+mod impl_layer;
 
 use impl_conditions::impl_conditions;
+use impl_layer::impl_layer;
 
 #[proc_macro]
 pub fn conditions(input: TokenStream) -> TokenStream {
     impl_conditions(input)
 }
+
+/// Implements `Layer` and `ToTreeHash` for a curried puzzle, given its reveal, hash, curried args and solution type.
+/// The fields of the struct must have the same names as those of the curried args.
+///
+/// ```ignore
+/// #[derive(Layer)]
+/// #[layer(
+///     puzzle = P2_ONE_OF_MANY_PUZZLE,
+///     puzzle_hash = P2_ONE_OF_MANY_PUZZLE_HASH,
+///     args = P2OneOfManyArgs,
+///     solution = P2OneOfManySolution<NodePtr, NodePtr>
+/// )]
+/// pub struct P2OneOfMany {
+///     pub merkle_root: Bytes32,
+/// }
+/// ```
+#[proc_macro_derive(Layer, attributes(layer))]
+pub fn layer(input: TokenStream) -> TokenStream {
+    impl_layer(input)
+}
@@ -0,0 +1,46 @@
+//! This crate doesn't depend on the `clvm` crates, so the derive must only refer to them through
+//! `chia_sdk_driver`, like it would in any other crate.
+
+use chia_bls::PublicKey;
+use chia_protocol::Program;
+use chia_puzzles::standard::{StandardArgs, StandardSolution, STANDARD_PUZZLE_HASH};
+use chia_sdk_driver::{Layer, Puzzle, SpendContext};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Layer)]
+#[layer(
+    puzzle = chia_puzzles::standard::STANDARD_PUZZLE,
+    puzzle_hash = STANDARD_PUZZLE_HASH,
+    args = StandardArgs,
+    solution = StandardSolution<Program, Program>
+)]
+struct CustomLayer {
+    synthetic_key: PublicKey,
+}
+
+#[test]
+fn test_derive_layer() -> anyhow::Result<()> {
+    let mut ctx = SpendContext::new();
+
+    let layer = CustomLayer {
+        synthetic_key: PublicKey::default(),
+    };
+
+    let puzzle = layer.construct_puzzle(&mut ctx)?;
+    assert_eq!(
+        ctx.tree_hash(puzzle),
+        StandardArgs::curry_tree_hash(PublicKey::default())
+    );
+
+    let parsed = CustomLayer::parse_puzzle(&ctx.allocator, Puzzle::parse(&ctx.allocator, puzzle))?;
+    assert_eq!(parsed, Some(layer));
+
+    let solution = StandardSolution {
+        original_public_key: None,
+        delegated_puzzle: Program::from(vec![0x80]),
+        solution: Program::from(vec![0x80]),
+    };
+    let ptr = layer.construct_solution(&mut ctx, solution.clone())?;
+    assert_eq!(CustomLayer::parse_solution(&ctx.allocator, ptr)?, solution);
+
+    Ok(())
+}
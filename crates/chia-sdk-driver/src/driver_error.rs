@@ -45,6 +45,12 @@ pub enum DriverError {
     #[error("expected even oracle fee, but it was odd")]
     OddOracleFee,
 
+    #[error("expected at least {required} signers, but got {signers}")]
+    NotEnoughSigners { required: usize, signers: usize },
+
+    #[error("signer is not a member of the multisig")]
+    UnknownSigner,
+
     #[error("custom driver error: {0}")]
     Custom(String),
 }
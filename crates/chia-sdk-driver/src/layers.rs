@@ -1,9 +1,12 @@
+mod augmented_condition_layer;
 mod cat_layer;
 mod did_layer;
 mod nft_ownership_layer;
 mod nft_state_layer;
+mod p2_curried_layer;
 mod p2_delegated_conditions_layer;
 mod p2_delegated_singleton_layer;
+mod p2_m_of_n_layer;
 mod p2_one_of_many;
 mod p2_singleton;
 mod royalty_transfer_layer;
@@ -11,12 +14,15 @@ mod settlement_layer;
 mod singleton_layer;
 mod standard_layer;
 
+pub use augmented_condition_layer::*;
 pub use cat_layer::*;
 pub use did_layer::*;
 pub use nft_ownership_layer::*;
 pub use nft_state_layer::*;
+pub use p2_curried_layer::*;
 pub use p2_delegated_conditions_layer::*;
 pub use p2_delegated_singleton_layer::*;
+pub use p2_m_of_n_layer::*;
 pub use p2_one_of_many::*;
 pub use p2_singleton::*;
 pub use royalty_transfer_layer::*;
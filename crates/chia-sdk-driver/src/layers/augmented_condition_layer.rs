@@ -0,0 +1,142 @@
+use chia_sdk_types::Condition;
+use clvm_traits::{FromClvm, ToClvm};
+use clvm_utils::{CurriedProgram, ToTreeHash, TreeHash, TreeHasher};
+use clvmr::{Allocator, NodePtr};
+use hex_literal::hex;
+
+use crate::{DriverError, Layer, Puzzle, SpendContext};
+
+/// The augmented condition [`Layer`] outputs a fixed condition in addition to those of its inner puzzle.
+/// This is typically used to add a timelock to a puzzle, such as in a [`Clawback`](crate::Clawback).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AugmentedConditionLayer<I> {
+    /// The condition which is always output when the puzzle is spent.
+    pub condition: Condition,
+    /// The inner puzzle layer, which produces the rest of the conditions.
+    pub inner_puzzle: I,
+}
+
+impl<I> AugmentedConditionLayer<I> {
+    pub fn new(condition: Condition, inner_puzzle: I) -> Self {
+        Self {
+            condition,
+            inner_puzzle,
+        }
+    }
+}
+
+impl<I> Layer for AugmentedConditionLayer<I>
+where
+    I: Layer,
+{
+    type Solution = AugmentedConditionSolution<I::Solution>;
+
+    fn parse_puzzle(allocator: &Allocator, puzzle: Puzzle) -> Result<Option<Self>, DriverError> {
+        let Some(puzzle) = puzzle.as_curried() else {
+            return Ok(None);
+        };
+
+        if puzzle.mod_hash != AUGMENTED_CONDITION_PUZZLE_HASH {
+            return Ok(None);
+        }
+
+        let args = AugmentedConditionArgs::<Condition, NodePtr>::from_clvm(allocator, puzzle.args)?;
+
+        let Some(inner_puzzle) =
+            I::parse_puzzle(allocator, Puzzle::parse(allocator, args.inner_puzzle))?
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(Self {
+            condition: args.condition,
+            inner_puzzle,
+        }))
+    }
+
+    fn parse_solution(
+        allocator: &Allocator,
+        solution: NodePtr,
+    ) -> Result<Self::Solution, DriverError> {
+        let solution = AugmentedConditionSolution::<NodePtr>::from_clvm(allocator, solution)?;
+        Ok(AugmentedConditionSolution {
+            inner_solution: I::parse_solution(allocator, solution.inner_solution)?,
+        })
+    }
+
+    fn construct_puzzle(&self, ctx: &mut SpendContext) -> Result<NodePtr, DriverError> {
+        let curried = CurriedProgram {
+            program: ctx.augmented_condition_puzzle()?,
+            args: AugmentedConditionArgs::new(
+                self.condition.clone(),
+                self.inner_puzzle.construct_puzzle(ctx)?,
+            ),
+        };
+        ctx.alloc(&curried)
+    }
+
+    fn construct_solution(
+        &self,
+        ctx: &mut SpendContext,
+        solution: Self::Solution,
+    ) -> Result<NodePtr, DriverError> {
+        let inner_solution = self
+            .inner_puzzle
+            .construct_solution(ctx, solution.inner_solution)?;
+        ctx.alloc(&AugmentedConditionSolution { inner_solution })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToClvm, FromClvm)]
+#[clvm(curry)]
+pub struct AugmentedConditionArgs<C, I> {
+    pub condition: C,
+    pub inner_puzzle: I,
+}
+
+impl<C, I> AugmentedConditionArgs<C, I> {
+    pub fn new(condition: C, inner_puzzle: I) -> Self {
+        Self {
+            condition,
+            inner_puzzle,
+        }
+    }
+}
+
+impl<C> AugmentedConditionArgs<C, TreeHash>
+where
+    C: ToClvm<TreeHasher>,
+{
+    pub fn curry_tree_hash(condition: C, inner_puzzle: TreeHash) -> TreeHash {
+        CurriedProgram {
+            program: AUGMENTED_CONDITION_PUZZLE_HASH,
+            args: AugmentedConditionArgs::new(condition, inner_puzzle),
+        }
+        .tree_hash()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToClvm, FromClvm)]
+#[clvm(list)]
+pub struct AugmentedConditionSolution<I> {
+    pub inner_solution: I,
+}
+
+pub const AUGMENTED_CONDITION_PUZZLE: [u8; 13] = hex!("ff04ff02ffff02ff05ff0b8080");
+
+pub const AUGMENTED_CONDITION_PUZZLE_HASH: TreeHash = TreeHash::new(hex!(
+    "d303eafa617bedf0bc05850dd014e10fbddf622187dc07891a2aacba9d8a93f6"
+));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::assert_puzzle_hash;
+
+    #[test]
+    fn test_puzzle_hash() -> anyhow::Result<()> {
+        assert_puzzle_hash!(AUGMENTED_CONDITION_PUZZLE => AUGMENTED_CONDITION_PUZZLE_HASH);
+        Ok(())
+    }
+}
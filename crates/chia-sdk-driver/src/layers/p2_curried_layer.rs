@@ -0,0 +1,81 @@
+use chia_protocol::Bytes32;
+use clvm_traits::{FromClvm, ToClvm};
+use clvm_utils::{CurriedProgram, ToTreeHash, TreeHash};
+use clvmr::NodePtr;
+use hex_literal::hex;
+
+use crate::Layer;
+
+/// The p2 curried [`Layer`] allows any puzzle with a given puzzle hash to be revealed and run in the solution.
+/// This makes it possible to commit to an inner puzzle without knowing anything other than its hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Layer)]
+#[layer(
+    puzzle = P2_CURRIED_PUZZLE,
+    puzzle_hash = P2_CURRIED_PUZZLE_HASH,
+    args = P2CurriedArgs,
+    solution = P2CurriedSolution<NodePtr, NodePtr>
+)]
+pub struct P2CurriedLayer {
+    /// The tree hash of the puzzle that must be revealed.
+    pub puzzle_hash: Bytes32,
+}
+
+impl P2CurriedLayer {
+    pub fn new(puzzle_hash: Bytes32) -> Self {
+        Self { puzzle_hash }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToClvm, FromClvm)]
+#[clvm(curry)]
+pub struct P2CurriedArgs {
+    pub puzzle_hash: Bytes32,
+}
+
+impl P2CurriedArgs {
+    pub fn new(puzzle_hash: Bytes32) -> Self {
+        Self { puzzle_hash }
+    }
+
+    pub fn curry_tree_hash(puzzle_hash: Bytes32) -> TreeHash {
+        CurriedProgram {
+            program: P2_CURRIED_PUZZLE_HASH,
+            args: P2CurriedArgs::new(puzzle_hash),
+        }
+        .tree_hash()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToClvm, FromClvm)]
+#[clvm(list)]
+pub struct P2CurriedSolution<P, S> {
+    pub puzzle: P,
+    pub solution: S,
+}
+
+pub const P2_CURRIED_PUZZLE: [u8; 143] = hex!(
+    "
+    ff02ffff01ff02ffff03ffff09ff05ffff02ff02ffff04ff02ffff04ff0bff80
+    80808080ffff01ff02ff0bff1780ffff01ff088080ff0180ffff04ffff01ff02
+    ffff03ffff07ff0580ffff01ff0bffff0102ffff02ff02ffff04ff02ffff04ff
+    09ff80808080ffff02ff02ffff04ff02ffff04ff0dff8080808080ffff01ff0b
+    ffff0101ff058080ff0180ff018080
+    "
+);
+
+pub const P2_CURRIED_PUZZLE_HASH: TreeHash = TreeHash::new(hex!(
+    "13e29a62b42cd2ef72a79e4bacdc59733ca6310d65af83d349360d36ec622363"
+));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::assert_puzzle_hash;
+
+    #[test]
+    fn test_puzzle_hash() -> anyhow::Result<()> {
+        assert_puzzle_hash!(P2_CURRIED_PUZZLE => P2_CURRIED_PUZZLE_HASH);
+        Ok(())
+    }
+}
@@ -0,0 +1,307 @@
+use chia_bls::PublicKey;
+use chia_protocol::Coin;
+use chia_sdk_types::Conditions;
+use clvm_traits::{clvm_quote, FromClvm, ToClvm};
+use clvm_utils::{CurriedProgram, ToTreeHash, TreeHash};
+use clvmr::NodePtr;
+use hex_literal::hex;
+
+use crate::{DriverError, Layer, Spend, SpendContext};
+
+/// The p2 m of n [`Layer`] requires a threshold of signatures from a fixed set of keys to spend the coin.
+///
+/// When it's spent, the selected members each sign the hash of the delegated puzzle with `AGG_SIG_ME`.
+/// This means that `RequiredSignature` in `chia-sdk-signer` lists exactly which members need to sign
+/// what, and their signatures can be aggregated in any order.
+///
+/// This is not the `p2_m_of_n_delegate_direct` puzzle from `chia-blockchain`, so it can only parse and spend
+/// coins that were locked with [`P2_M_OF_N_PUZZLE_HASH`], such as those created by this layer.
+#[derive(Debug, Clone, PartialEq, Eq, Layer)]
+#[layer(
+    puzzle = P2_M_OF_N_PUZZLE,
+    puzzle_hash = P2_M_OF_N_PUZZLE_HASH,
+    args = P2MOfNArgs,
+    solution = P2MOfNSolution<NodePtr, NodePtr>
+)]
+pub struct P2MOfNLayer {
+    /// The minimum number of members that must sign.
+    pub required: usize,
+    /// The public keys of every member, in the order they are curried into the puzzle.
+    pub public_keys: Vec<PublicKey>,
+}
+
+impl P2MOfNLayer {
+    pub fn new(required: usize, public_keys: Vec<PublicKey>) -> Self {
+        Self {
+            required,
+            public_keys,
+        }
+    }
+
+    /// Returns whether each member is one of the signers, in the order of [`P2MOfNLayer::public_keys`].
+    pub fn selectors(&self, signers: &[PublicKey]) -> Result<Vec<bool>, DriverError> {
+        if signers
+            .iter()
+            .any(|signer| !self.public_keys.contains(signer))
+        {
+            return Err(DriverError::UnknownSigner);
+        }
+
+        let selectors: Vec<bool> = self
+            .public_keys
+            .iter()
+            .map(|public_key| signers.contains(public_key))
+            .collect();
+
+        let selected = selectors.iter().filter(|&&selected| selected).count();
+
+        if selected < self.required {
+            return Err(DriverError::NotEnoughSigners {
+                required: self.required,
+                signers: selected,
+            });
+        }
+
+        Ok(selectors)
+    }
+
+    /// Returns the public keys of the members which signed a spend, given its selectors.
+    pub fn signers(&self, selectors: &[bool]) -> Vec<PublicKey> {
+        self.public_keys
+            .iter()
+            .zip(selectors)
+            .filter(|(_, &selected)| selected)
+            .map(|(public_key, _)| *public_key)
+            .collect()
+    }
+
+    /// Spends the coin with a delegated puzzle, which must be signed by each of the signers.
+    pub fn delegated_spend(
+        &self,
+        ctx: &mut SpendContext,
+        signers: &[PublicKey],
+        spend: Spend,
+    ) -> Result<Spend, DriverError> {
+        let selectors = self.selectors(signers)?;
+
+        self.construct_spend(
+            ctx,
+            P2MOfNSolution {
+                selectors,
+                delegated_puzzle: spend.puzzle,
+                delegated_solution: spend.solution,
+            },
+        )
+    }
+
+    /// Spends the coin to output a list of conditions, which must be signed by each of the signers.
+    pub fn spend_with_conditions(
+        &self,
+        ctx: &mut SpendContext,
+        signers: &[PublicKey],
+        conditions: Conditions,
+    ) -> Result<Spend, DriverError> {
+        let delegated_puzzle = ctx.alloc(&clvm_quote!(conditions))?;
+        self.delegated_spend(ctx, signers, Spend::new(delegated_puzzle, NodePtr::NIL))
+    }
+
+    pub fn spend(
+        &self,
+        ctx: &mut SpendContext,
+        coin: Coin,
+        signers: &[PublicKey],
+        conditions: Conditions,
+    ) -> Result<(), DriverError> {
+        let spend = self.spend_with_conditions(ctx, signers, conditions)?;
+        ctx.spend(coin, spend)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ToClvm, FromClvm)]
+#[clvm(curry)]
+pub struct P2MOfNArgs {
+    pub required: usize,
+    pub public_keys: Vec<PublicKey>,
+}
+
+impl P2MOfNArgs {
+    pub fn new(required: usize, public_keys: Vec<PublicKey>) -> Self {
+        Self {
+            required,
+            public_keys,
+        }
+    }
+
+    pub fn curry_tree_hash(required: usize, public_keys: Vec<PublicKey>) -> TreeHash {
+        CurriedProgram {
+            program: P2_M_OF_N_PUZZLE_HASH,
+            args: P2MOfNArgs::new(required, public_keys),
+        }
+        .tree_hash()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ToClvm, FromClvm)]
+#[clvm(list)]
+pub struct P2MOfNSolution<P, S> {
+    /// Whether each member signed the delegated puzzle, in the order they are curried into the puzzle.
+    pub selectors: Vec<bool>,
+    pub delegated_puzzle: P,
+    pub delegated_solution: S,
+}
+
+/// The source for this puzzle can be found in `puzzles/p2_m_of_n.clsp`, and the serialized form in
+/// `puzzles/p2_m_of_n.clsp.hex`. It fails unless at least `required` members are selected.
+pub const P2_M_OF_N_PUZZLE: [u8; 349] = hex!(
+    "
+    ff02ffff01ff02ff02ffff04ff02ffff04ff17ffff04ff2fffff04ffff02ff05
+    ffff04ff05ffff04ff5fff80808080ffff04ff0bffff04ffff02ff5fff8200bf
+    80ff8080808080808080ffff04ffff01ff02ffff03ff0bffff01ff02ffff03ff
+    13ffff01ff04ffff04ffff0132ffff04ff09ffff04ff17ff80808080ffff02ff
+    02ffff04ff02ffff04ff0dffff04ff1bffff04ff17ffff04ffff11ff2fffff01
+    0180ffff04ff5fff808080808080808080ffff01ff02ff02ffff04ff02ffff04
+    ff0dffff04ff1bffff04ff17ffff04ff2fffff04ff5fff808080808080808080
+    ff0180ffff01ff02ffff03ffff15ff2fff8080ffff01ff0880ffff015f80ff01
+    8080ff0180ffff04ffff01ff02ffff03ffff07ff0580ffff01ff0bffff0102ff
+    ff02ff02ffff04ff02ffff04ff09ff80808080ffff02ff02ffff04ff02ffff04
+    ff0dff8080808080ffff01ff0bffff0101ff058080ff0180ff01808080
+    "
+);
+
+pub const P2_M_OF_N_PUZZLE_HASH: TreeHash = TreeHash::new(hex!(
+    "cbd2bd95987f336346167fb58acb391719fd4601703db860b7bd049fad3d4e39"
+));
+
+#[cfg(test)]
+mod tests {
+    use chia_bls::{SecretKey, Signature};
+    use chia_consensus::gen::validation_error::ErrorCode;
+    use chia_protocol::SpendBundle;
+    use chia_sdk_signer::{AggSigConstants, RequiredSignature};
+    use chia_sdk_test::{test_secret_keys, Simulator, SimulatorError};
+    use chia_sdk_types::TESTNET11_CONSTANTS;
+    use clvmr::Allocator;
+
+    use crate::{assert_puzzle_hash, Puzzle};
+
+    use super::*;
+
+    #[test]
+    fn test_puzzle_hash() -> anyhow::Result<()> {
+        assert_puzzle_hash!(P2_M_OF_N_PUZZLE => P2_M_OF_N_PUZZLE_HASH);
+        Ok(())
+    }
+
+    #[test]
+    fn test_puzzle_matches_source() -> anyhow::Result<()> {
+        let serialized: String = include_str!("../../puzzles/p2_m_of_n.clsp.hex")
+            .split_whitespace()
+            .collect();
+        assert_eq!(hex::decode(serialized)?, P2_M_OF_N_PUZZLE);
+        Ok(())
+    }
+
+    #[test]
+    fn test_m_of_n_spend() -> anyhow::Result<()> {
+        let mut sim = Simulator::new();
+        let ctx = &mut SpendContext::new();
+
+        let secret_keys = test_secret_keys(3)?;
+        let public_keys: Vec<PublicKey> = secret_keys.iter().map(SecretKey::public_key).collect();
+
+        let layer = P2MOfNLayer::new(2, public_keys.clone());
+        let puzzle_hash = layer.tree_hash().into();
+        assert_eq!(
+            puzzle_hash,
+            P2MOfNArgs::curry_tree_hash(2, public_keys.clone()).into()
+        );
+
+        let coin = sim.new_coin(puzzle_hash, 1);
+
+        // Any two of the members can sign, regardless of the order they're provided in.
+        let signers = [public_keys[2], public_keys[0]];
+        layer.spend(
+            ctx,
+            coin,
+            &signers,
+            Conditions::new().create_coin(puzzle_hash, 1, Vec::new()),
+        )?;
+        let coin_spends = ctx.take();
+
+        // Only the selected members need to sign.
+        let mut allocator = Allocator::new();
+        let required = RequiredSignature::from_coin_spends(
+            &mut allocator,
+            &coin_spends,
+            &AggSigConstants::new(TESTNET11_CONSTANTS.agg_sig_me_additional_data),
+        )?;
+        let mut required_keys: Vec<PublicKey> =
+            required.iter().map(RequiredSignature::public_key).collect();
+        required_keys.sort_by_key(PublicKey::to_bytes);
+        let mut expected_keys = signers.to_vec();
+        expected_keys.sort_by_key(PublicKey::to_bytes);
+        assert_eq!(required_keys, expected_keys);
+
+        // The coin can be parsed back into the layer, along with who signed it.
+        let puzzle = coin_spends[0].puzzle_reveal.to_clvm(&mut allocator)?;
+        let solution = coin_spends[0].solution.to_clvm(&mut allocator)?;
+        let parsed = P2MOfNLayer::parse_puzzle(&allocator, Puzzle::parse(&allocator, puzzle))?
+            .expect("not a multisig puzzle");
+        let parsed_solution = P2MOfNLayer::parse_solution(&allocator, solution)?;
+        assert_eq!(parsed, layer);
+        assert_eq!(
+            parsed.signers(&parsed_solution.selectors),
+            vec![public_keys[0], public_keys[2]]
+        );
+
+        sim.spend_coins(
+            coin_spends,
+            &[secret_keys[0].clone(), secret_keys[2].clone()],
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_m_of_n_not_enough_signers() -> anyhow::Result<()> {
+        let mut sim = Simulator::new();
+        let ctx = &mut SpendContext::new();
+
+        let secret_keys = test_secret_keys(3)?;
+        let public_keys: Vec<PublicKey> = secret_keys.iter().map(SecretKey::public_key).collect();
+
+        let layer = P2MOfNLayer::new(2, public_keys.clone());
+        let puzzle_hash = layer.tree_hash().into();
+        let coin = sim.new_coin(puzzle_hash, 1);
+
+        assert!(matches!(
+            layer.selectors(&[public_keys[1]]),
+            Err(DriverError::NotEnoughSigners {
+                required: 2,
+                signers: 1
+            })
+        ));
+        assert!(matches!(
+            layer.selectors(&[public_keys[0], PublicKey::default()]),
+            Err(DriverError::UnknownSigner)
+        ));
+
+        // The puzzle itself also enforces the threshold.
+        let spend = layer.construct_spend(
+            ctx,
+            P2MOfNSolution {
+                selectors: vec![false, true, false],
+                delegated_puzzle: NodePtr::NIL,
+                delegated_solution: NodePtr::NIL,
+            },
+        )?;
+        ctx.spend(coin, spend)?;
+
+        assert!(matches!(
+            sim.new_transaction(SpendBundle::new(ctx.take(), Signature::default())),
+            Err(SimulatorError::Validation(ErrorCode::GeneratorRuntimeError))
+        ));
+
+        Ok(())
+    }
+}
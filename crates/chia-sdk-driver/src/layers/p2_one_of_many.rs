@@ -1,75 +1,35 @@
 use chia_protocol::Bytes32;
 use clvm_traits::{FromClvm, ToClvm};
-use clvm_utils::{CurriedProgram, TreeHash};
-use clvmr::{Allocator, NodePtr};
+use clvm_utils::TreeHash;
+use clvmr::NodePtr;
 use hex_literal::hex;
 
-use crate::{DriverError, Layer, Puzzle, SpendContext};
+use crate::Layer;
 
 /// The p2 1 of n [`Layer`] allows for picking from several delegated puzzles at runtime without revealing up front.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Layer)]
+#[layer(
+    puzzle = P2_ONE_OF_MANY_PUZZLE,
+    puzzle_hash = P2_ONE_OF_MANY_PUZZLE_HASH,
+    args = P2OneOfManyArgs,
+    solution = P2OneOfManySolution<NodePtr, NodePtr>
+)]
 pub struct P2OneOfMany {
     /// The merkle root used to lookup the delegated puzzle as part of the solution.
     pub merkle_root: Bytes32,
 }
 
-impl Layer for P2OneOfMany {
-    type Solution = P2OneOfManySolution<NodePtr, NodePtr>;
-
-    fn parse_puzzle(allocator: &Allocator, puzzle: Puzzle) -> Result<Option<Self>, DriverError> {
-        let Some(puzzle) = puzzle.as_curried() else {
-            return Ok(None);
-        };
-
-        if puzzle.mod_hash != P2_ONE_OF_MANY_PUZZLE_HASH {
-            return Ok(None);
-        }
-
-        let args = P2OneOfManyArgs::from_clvm(allocator, puzzle.args)?;
-
-        Ok(Some(Self {
-            merkle_root: args.merkle_root,
-        }))
-    }
-
-    fn parse_solution(
-        allocator: &Allocator,
-        solution: NodePtr,
-    ) -> Result<Self::Solution, DriverError> {
-        Ok(P2OneOfManySolution::<NodePtr, NodePtr>::from_clvm(
-            allocator, solution,
-        )?)
-    }
-
-    fn construct_puzzle(&self, ctx: &mut SpendContext) -> Result<NodePtr, DriverError> {
-        let curried = CurriedProgram {
-            program: ctx.p2_one_of_many_puzzle()?,
-            args: P2OneOfManyArgs {
-                merkle_root: self.merkle_root,
-            },
-        };
-        ctx.alloc(&curried)
-    }
-
-    fn construct_solution(
-        &self,
-        ctx: &mut SpendContext,
-        solution: Self::Solution,
-    ) -> Result<NodePtr, DriverError> {
-        ctx.alloc(&solution)
-    }
-}
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ToClvm, FromClvm)]
 #[clvm(curry)]
 pub struct P2OneOfManyArgs {
     pub merkle_root: Bytes32,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, ToClvm, FromClvm)]
+#[derive(Debug, Clone, PartialEq, Eq, ToClvm, FromClvm)]
 #[clvm(list)]
 pub struct P2OneOfManySolution<P, S> {
-    pub merkle_proof: Bytes32,
+    /// The path and sibling hashes from [`MerkleTree::get_proof`](crate::MerkleTree::get_proof).
+    pub merkle_proof: (u32, Vec<Bytes32>),
     pub puzzle: P,
     pub solution: S,
 }
@@ -94,13 +54,75 @@ pub const P2_ONE_OF_MANY_PUZZLE_HASH: TreeHash = TreeHash::new(hex!(
 
 #[cfg(test)]
 mod tests {
+    use chia_protocol::Coin;
+    use chia_sdk_test::Simulator;
+    use chia_sdk_types::Conditions;
+    use clvm_traits::clvm_quote;
+    use clvm_utils::ToTreeHash;
+
     use super::*;
 
-    use crate::assert_puzzle_hash;
+    use crate::{assert_puzzle_hash, MerkleTree, SpendContext};
 
     #[test]
     fn test_puzzle_hash() -> anyhow::Result<()> {
         assert_puzzle_hash!(P2_ONE_OF_MANY_PUZZLE => P2_ONE_OF_MANY_PUZZLE_HASH);
         Ok(())
     }
+
+    #[test]
+    fn test_p2_one_of_many_spend() -> anyhow::Result<()> {
+        let mut sim = Simulator::new();
+        let ctx = &mut SpendContext::new();
+
+        // Each member puzzle creates a coin with a different puzzle hash, so it's clear which one ran.
+        let members = (0..3)
+            .map(|i| {
+                ctx.alloc(&clvm_quote!(Conditions::new().create_coin(
+                    Bytes32::new([i; 32]),
+                    1,
+                    Vec::new()
+                )))
+            })
+            .collect::<Result<Vec<NodePtr>, _>>()?;
+        let leaves: Vec<Bytes32> = members
+            .iter()
+            .map(|&member| ctx.tree_hash(member).into())
+            .collect();
+        let merkle_tree = MerkleTree::new(&leaves);
+
+        let layer = P2OneOfMany {
+            merkle_root: merkle_tree.root,
+        };
+        let coin = sim.new_coin(layer.tree_hash().into(), 1);
+
+        let spend = layer.construct_spend(
+            ctx,
+            P2OneOfManySolution {
+                merkle_proof: merkle_tree.get_proof(leaves[1]).expect("missing proof"),
+                puzzle: members[1],
+                solution: NodePtr::NIL,
+            },
+        )?;
+        ctx.spend(coin, spend)?;
+        sim.spend_coins(ctx.take(), &[])?;
+
+        let child = Coin::new(coin.coin_id(), Bytes32::new([1; 32]), 1);
+        assert!(sim.coin_state(child.coin_id()).is_some());
+
+        // A member can't be spent with the proof of a different member.
+        let coin = sim.new_coin(layer.tree_hash().into(), 1);
+        let spend = layer.construct_spend(
+            ctx,
+            P2OneOfManySolution {
+                merkle_proof: merkle_tree.get_proof(leaves[0]).expect("missing proof"),
+                puzzle: members[2],
+                solution: NodePtr::NIL,
+            },
+        )?;
+        ctx.spend(coin, spend)?;
+        assert!(sim.spend_coins(ctx.take(), &[]).is_err());
+
+        Ok(())
+    }
 }
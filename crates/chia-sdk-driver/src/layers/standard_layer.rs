@@ -1,12 +1,13 @@
 use chia_bls::PublicKey;
 use chia_protocol::Coin;
-use chia_puzzles::standard::{StandardArgs, StandardSolution, STANDARD_PUZZLE_HASH};
+use chia_puzzles::standard::{
+    StandardArgs, StandardSolution, STANDARD_PUZZLE, STANDARD_PUZZLE_HASH,
+};
 use chia_sdk_types::Conditions;
-use clvm_traits::{clvm_quote, FromClvm};
-use clvm_utils::{CurriedProgram, ToTreeHash, TreeHash};
-use clvmr::{Allocator, NodePtr};
+use clvm_traits::clvm_quote;
+use clvmr::NodePtr;
 
-use crate::{DriverError, Layer, Puzzle, Spend, SpendContext, SpendWithConditions};
+use crate::{DriverError, Layer, Spend, SpendContext, SpendWithConditions};
 
 /// This is the actual puzzle name for the [`StandardLayer`].
 pub type P2DelegatedOrHiddenLayer = StandardLayer;
@@ -19,7 +20,13 @@ pub type P2DelegatedOrHiddenLayer = StandardLayer;
 /// When spending the coin, you can reveal this hidden puzzle and provide the original key.
 /// This functionality is seldom used in Chia, and usually the "default hidden puzzle" is used instead.
 /// The default hidden puzzle is not spendable, so you can only spend XCH coins by signing with your key.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Layer)]
+#[layer(
+    puzzle = STANDARD_PUZZLE,
+    puzzle_hash = STANDARD_PUZZLE_HASH,
+    args = StandardArgs,
+    solution = StandardSolution<NodePtr, NodePtr>
+)]
 pub struct StandardLayer {
     pub synthetic_key: PublicKey,
 }
@@ -55,49 +62,6 @@ impl StandardLayer {
     }
 }
 
-impl Layer for StandardLayer {
-    type Solution = StandardSolution<NodePtr, NodePtr>;
-
-    fn construct_puzzle(&self, ctx: &mut SpendContext) -> Result<NodePtr, DriverError> {
-        let curried = CurriedProgram {
-            program: ctx.standard_puzzle()?,
-            args: StandardArgs::new(self.synthetic_key),
-        };
-        ctx.alloc(&curried)
-    }
-
-    fn construct_solution(
-        &self,
-        ctx: &mut SpendContext,
-        solution: Self::Solution,
-    ) -> Result<NodePtr, DriverError> {
-        ctx.alloc(&solution)
-    }
-
-    fn parse_puzzle(allocator: &Allocator, puzzle: Puzzle) -> Result<Option<Self>, DriverError> {
-        let Some(puzzle) = puzzle.as_curried() else {
-            return Ok(None);
-        };
-
-        if puzzle.mod_hash != STANDARD_PUZZLE_HASH {
-            return Ok(None);
-        }
-
-        let args = StandardArgs::from_clvm(allocator, puzzle.args)?;
-
-        Ok(Some(Self {
-            synthetic_key: args.synthetic_key,
-        }))
-    }
-
-    fn parse_solution(
-        allocator: &Allocator,
-        solution: NodePtr,
-    ) -> Result<Self::Solution, DriverError> {
-        Ok(StandardSolution::from_clvm(allocator, solution)?)
-    }
-}
-
 impl SpendWithConditions for StandardLayer {
     fn spend_with_conditions(
         &self,
@@ -116,12 +80,6 @@ impl SpendWithConditions for StandardLayer {
     }
 }
 
-impl ToTreeHash for StandardLayer {
-    fn tree_hash(&self) -> TreeHash {
-        StandardArgs::curry_tree_hash(self.synthetic_key)
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use chia_sdk_test::Simulator;
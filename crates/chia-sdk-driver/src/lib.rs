@@ -1,5 +1,8 @@
 #![doc = include_str!("../docs.md")]
 
+// Allows the code generated by `#[derive(Layer)]` to refer to this crate by name.
+extern crate self as chia_sdk_driver;
+
 mod driver_error;
 mod hashed_ptr;
 mod layer;
@@ -7,6 +10,7 @@ mod layers;
 mod merkle_tree;
 mod primitives;
 mod puzzle;
+mod recognizer;
 mod spend;
 mod spend_context;
 mod spend_with_conditions;
@@ -18,6 +22,15 @@ pub use layers::*;
 pub use merkle_tree::*;
 pub use primitives::*;
 pub use puzzle::*;
+pub use recognizer::*;
 pub use spend::*;
 pub use spend_context::*;
 pub use spend_with_conditions::*;
+
+// The crates used by the code generated by `#[derive(Layer)]`, so that it works without depending on them.
+#[doc(hidden)]
+pub mod __private {
+    pub use clvm_traits;
+    pub use clvm_utils;
+    pub use clvmr;
+}
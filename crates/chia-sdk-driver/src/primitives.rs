@@ -1,10 +1,12 @@
 mod cat;
+mod clawback;
 mod did;
 mod intermediate_launcher;
 mod launcher;
 mod nft;
 
 pub use cat::*;
+pub use clawback::*;
 pub use did::*;
 pub use intermediate_launcher::*;
 pub use launcher::*;
@@ -0,0 +1,436 @@
+use chia_protocol::{Bytes, Bytes32, Coin};
+use chia_puzzles::cat::CatArgs;
+use chia_sdk_types::{run_puzzle, AssertSecondsRelative, Condition};
+use clvm_traits::FromClvm;
+use clvm_utils::{ToTreeHash, TreeHash};
+use clvmr::{Allocator, NodePtr};
+
+use crate::{
+    AugmentedConditionArgs, AugmentedConditionLayer, AugmentedConditionSolution, CatLayer,
+    DriverError, Layer, MerkleTree, P2CurriedArgs, P2CurriedLayer, P2CurriedSolution, P2OneOfMany,
+    P2OneOfManySolution, Puzzle, Spend, SpendContext,
+};
+
+/// A reversible payment, which the receiver can claim once the timelock expires.
+/// Until it's claimed, the sender can claw it back at any time.
+///
+/// This is used as the inner puzzle of the coin, so it works for both XCH and CATs.
+/// The puzzle is a [`P2OneOfMany`] with a path in its merkle tree for the receiver and the sender,
+/// which is the same as the clawbacks created by the reference wallet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clawback {
+    /// The puzzle hash which can claw back the coin.
+    pub sender_puzzle_hash: Bytes32,
+    /// The puzzle hash which can claim the coin after the timelock expires.
+    pub receiver_puzzle_hash: Bytes32,
+    /// The number of seconds after the coin is created until it can be claimed.
+    pub seconds: u64,
+}
+
+impl Clawback {
+    pub fn new(sender_puzzle_hash: Bytes32, receiver_puzzle_hash: Bytes32, seconds: u64) -> Self {
+        Self {
+            sender_puzzle_hash,
+            receiver_puzzle_hash,
+            seconds,
+        }
+    }
+
+    /// The memos for the coin, which hint it to the receiver and allow it to be parsed with [`Clawback::from_memos`].
+    /// The second memo is the clawback metadata, serialized the same way as the reference wallet.
+    pub fn memos(&self) -> Vec<Bytes> {
+        vec![self.receiver_puzzle_hash.into(), self.metadata().into()]
+    }
+
+    /// Parses the clawback from the memos of the coin's `CREATE_COIN` condition.
+    /// Any memos after the hint and metadata are ignored, like they are by the reference wallet.
+    /// The result should be checked against the puzzle hash of the coin, since anyone can create these memos.
+    pub fn from_memos(memos: &[Bytes]) -> Option<Self> {
+        let [hint, metadata, ..] = memos else {
+            return None;
+        };
+
+        let (seconds, puzzle_hashes) = metadata.as_ref().split_first_chunk::<8>()?;
+        let (sender_puzzle_hash, receiver_puzzle_hash) = puzzle_hashes.split_first_chunk::<32>()?;
+
+        let clawback = Self {
+            sender_puzzle_hash: Bytes32::new(*sender_puzzle_hash),
+            receiver_puzzle_hash: receiver_puzzle_hash.try_into().ok()?,
+            seconds: u64::from_be_bytes(*seconds),
+        };
+
+        if hint.as_ref() != clawback.receiver_puzzle_hash.as_ref() {
+            return None;
+        }
+
+        Some(clawback)
+    }
+
+    /// The timelock, sender puzzle hash and receiver puzzle hash, in that order.
+    fn metadata(&self) -> Vec<u8> {
+        let mut metadata = Vec::with_capacity(72);
+        metadata.extend_from_slice(&self.seconds.to_be_bytes());
+        metadata.extend_from_slice(&self.sender_puzzle_hash);
+        metadata.extend_from_slice(&self.receiver_puzzle_hash);
+        metadata
+    }
+
+    /// Finds the clawback coins created by a parent coin spend, including CATs.
+    pub fn parse_children(
+        allocator: &mut Allocator,
+        parent_coin: Coin,
+        parent_puzzle: Puzzle,
+        parent_solution: NodePtr,
+    ) -> Result<Vec<(Coin, Self)>, DriverError> {
+        let asset_id = CatLayer::<Puzzle>::parse_puzzle(allocator, parent_puzzle)?
+            .map(|cat_layer| cat_layer.asset_id);
+
+        let output = run_puzzle(allocator, parent_puzzle.ptr(), parent_solution)?;
+        let conditions = Vec::<Condition>::from_clvm(allocator, output)?;
+
+        let mut children = Vec::new();
+
+        for create_coin in conditions
+            .into_iter()
+            .filter_map(Condition::into_create_coin)
+        {
+            let Some(clawback) = Self::from_memos(&create_coin.memos) else {
+                continue;
+            };
+
+            // The CAT layer wraps the puzzle hashes of the coins created by its inner puzzle.
+            let puzzle_hash = match asset_id {
+                Some(asset_id) => CatArgs::curry_tree_hash(asset_id, clawback.tree_hash()),
+                None => clawback.tree_hash(),
+            };
+
+            if create_coin.puzzle_hash != puzzle_hash.into() {
+                continue;
+            }
+
+            children.push((
+                Coin::new(
+                    parent_coin.coin_id(),
+                    create_coin.puzzle_hash,
+                    create_coin.amount,
+                ),
+                clawback,
+            ));
+        }
+
+        Ok(children)
+    }
+
+    pub fn sender_path_hash(&self) -> TreeHash {
+        P2CurriedArgs::curry_tree_hash(self.sender_puzzle_hash)
+    }
+
+    pub fn receiver_path_hash(&self) -> TreeHash {
+        AugmentedConditionArgs::curry_tree_hash(
+            AssertSecondsRelative::new(self.seconds),
+            P2CurriedArgs::curry_tree_hash(self.receiver_puzzle_hash),
+        )
+    }
+
+    pub fn merkle_tree(&self) -> MerkleTree {
+        MerkleTree::new(&[
+            self.receiver_path_hash().into(),
+            self.sender_path_hash().into(),
+        ])
+    }
+
+    /// Claws back the coin, given a spend of the sender's puzzle.
+    /// The result is the inner spend of the coin, so for CATs it still needs to be wrapped.
+    pub fn clawback_spend(
+        &self,
+        ctx: &mut SpendContext,
+        spend: Spend,
+    ) -> Result<Spend, DriverError> {
+        let spend = P2CurriedLayer::new(self.sender_puzzle_hash).construct_spend(
+            ctx,
+            P2CurriedSolution {
+                puzzle: spend.puzzle,
+                solution: spend.solution,
+            },
+        )?;
+
+        self.spend_path(ctx, self.sender_path_hash(), spend)
+    }
+
+    /// Claims the coin after the timelock expires, given a spend of the receiver's puzzle.
+    /// The result is the inner spend of the coin, so for CATs it still needs to be wrapped.
+    pub fn claim_spend(&self, ctx: &mut SpendContext, spend: Spend) -> Result<Spend, DriverError> {
+        let layer = AugmentedConditionLayer::new(
+            AssertSecondsRelative::new(self.seconds).into(),
+            P2CurriedLayer::new(self.receiver_puzzle_hash),
+        );
+
+        let spend = layer.construct_spend(
+            ctx,
+            AugmentedConditionSolution {
+                inner_solution: P2CurriedSolution {
+                    puzzle: spend.puzzle,
+                    solution: spend.solution,
+                },
+            },
+        )?;
+
+        self.spend_path(ctx, self.receiver_path_hash(), spend)
+    }
+
+    fn spend_path(
+        &self,
+        ctx: &mut SpendContext,
+        path_hash: TreeHash,
+        spend: Spend,
+    ) -> Result<Spend, DriverError> {
+        let merkle_tree = self.merkle_tree();
+
+        let merkle_proof = merkle_tree
+            .get_proof(path_hash.into())
+            .expect("path is missing from the merkle tree");
+
+        P2OneOfMany {
+            merkle_root: merkle_tree.root,
+        }
+        .construct_spend(
+            ctx,
+            P2OneOfManySolution {
+                merkle_proof,
+                puzzle: spend.puzzle,
+                solution: spend.solution,
+            },
+        )
+    }
+}
+
+impl ToTreeHash for Clawback {
+    fn tree_hash(&self) -> TreeHash {
+        P2OneOfMany {
+            merkle_root: self.merkle_tree().root,
+        }
+        .tree_hash()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chia_sdk_test::Simulator;
+    use chia_sdk_types::Conditions;
+    use clvm_traits::ToClvm;
+    use hex_literal::hex;
+    use rstest::rstest;
+
+    use crate::{Cat, CatSpend, SpendWithConditions, StandardLayer};
+
+    use super::*;
+
+    fn parse_children(
+        sim: &Simulator,
+        allocator: &mut Allocator,
+        parent_coin: Coin,
+    ) -> anyhow::Result<Vec<(Coin, Clawback)>> {
+        let puzzle = sim
+            .puzzle_reveal(parent_coin.coin_id())
+            .expect("missing puzzle")
+            .to_clvm(allocator)?;
+        let solution = sim
+            .solution(parent_coin.coin_id())
+            .expect("missing solution")
+            .to_clvm(allocator)?;
+        let puzzle = Puzzle::parse(allocator, puzzle);
+        Ok(Clawback::parse_children(
+            allocator,
+            parent_coin,
+            puzzle,
+            solution,
+        )?)
+    }
+
+    fn output_conditions(ctx: &mut SpendContext, spend: Spend) -> anyhow::Result<Vec<Condition>> {
+        let output = ctx.run(spend.puzzle, spend.solution)?;
+        Ok(ctx.extract::<Vec<Condition>>(output)?)
+    }
+
+    #[rstest]
+    #[case::clawback(false)]
+    #[case::claim(true)]
+    fn test_clawback_xch(#[case] claim: bool) -> anyhow::Result<()> {
+        let mut sim = Simulator::new();
+        let ctx = &mut SpendContext::new();
+
+        let (sender_sk, sender_pk, sender_puzzle_hash, coin) = sim.new_p2(1)?;
+        let (receiver_sk, receiver_pk, receiver_puzzle_hash, _) = sim.new_p2(0)?;
+        let sender = StandardLayer::new(sender_pk);
+        let receiver = StandardLayer::new(receiver_pk);
+
+        let clawback = Clawback::new(sender_puzzle_hash, receiver_puzzle_hash, 100);
+        let clawback_puzzle_hash = clawback.tree_hash().into();
+
+        sender.spend(
+            ctx,
+            coin,
+            Conditions::new().create_coin(clawback_puzzle_hash, 1, clawback.memos()),
+        )?;
+        sim.spend_coins(ctx.take(), &[sender_sk.clone()])?;
+
+        // The receiver can find the coin by its hint, and parse it from the parent spend.
+        let children = parse_children(&sim, &mut ctx.allocator, coin)?;
+        assert_eq!(children.len(), 1);
+
+        let (clawback_coin, parsed) = children[0];
+        assert_eq!(parsed, clawback);
+        assert_eq!(
+            sim.hinted_coins(receiver_puzzle_hash),
+            vec![clawback_coin.coin_id()]
+        );
+
+        // Only the receiver has to wait for the timelock.
+        let timelock = Condition::from(AssertSecondsRelative::new(100));
+
+        let (spend, keys, destination) = if claim {
+            let inner_spend = receiver.spend_with_conditions(
+                ctx,
+                Conditions::new().create_coin(receiver_puzzle_hash, 1, Vec::new()),
+            )?;
+            let spend = clawback.claim_spend(ctx, inner_spend)?;
+            assert!(output_conditions(ctx, spend)?.contains(&timelock));
+            (spend, vec![receiver_sk], receiver_puzzle_hash)
+        } else {
+            let inner_spend = sender.spend_with_conditions(
+                ctx,
+                Conditions::new().create_coin(sender_puzzle_hash, 1, Vec::new()),
+            )?;
+            let spend = clawback.clawback_spend(ctx, inner_spend)?;
+            assert!(!output_conditions(ctx, spend)?.contains(&timelock));
+            (spend, vec![sender_sk], sender_puzzle_hash)
+        };
+
+        ctx.spend(clawback_coin, spend)?;
+        sim.spend_coins(ctx.take(), &keys)?;
+
+        let child = Coin::new(clawback_coin.coin_id(), destination, 1);
+        assert!(sim.coin_state(child.coin_id()).is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(clippy::similar_names)]
+    fn test_clawback_cat() -> anyhow::Result<()> {
+        let mut sim = Simulator::new();
+        let ctx = &mut SpendContext::new();
+
+        let (sender_sk, sender_pk, sender_puzzle_hash, coin) = sim.new_p2(1)?;
+        let (receiver_sk, receiver_pk, receiver_puzzle_hash, _) = sim.new_p2(0)?;
+        let sender = StandardLayer::new(sender_pk);
+        let receiver = StandardLayer::new(receiver_pk);
+
+        let clawback = Clawback::new(sender_puzzle_hash, receiver_puzzle_hash, 100);
+        let clawback_puzzle_hash = clawback.tree_hash().into();
+
+        let (issue_cat, cat) = Cat::single_issuance_eve(
+            ctx,
+            coin.coin_id(),
+            1,
+            Conditions::new().create_coin(clawback_puzzle_hash, 1, clawback.memos()),
+        )?;
+        sender.spend(ctx, coin, issue_cat)?;
+        sim.spend_coins(ctx.take(), &[sender_sk])?;
+
+        let children = parse_children(&sim, &mut ctx.allocator, cat.coin)?;
+        assert_eq!(children.len(), 1);
+
+        let cat = cat.wrapped_child(clawback_puzzle_hash, 1);
+        assert_eq!(children[0], (cat.coin, clawback));
+
+        let inner_spend = receiver.spend_with_conditions(
+            ctx,
+            Conditions::new().create_coin(
+                receiver_puzzle_hash,
+                1,
+                vec![receiver_puzzle_hash.into()],
+            ),
+        )?;
+        let inner_spend = clawback.claim_spend(ctx, inner_spend)?;
+
+        Cat::spend_all(ctx, &[CatSpend::new(cat, inner_spend)])?;
+        sim.spend_coins(ctx.take(), &[receiver_sk])?;
+
+        let child = cat.wrapped_child(receiver_puzzle_hash, 1);
+        assert!(sim.coin_state(child.coin.coin_id()).is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clawback_memos() {
+        let clawback = Clawback::new(Bytes32::new([1; 32]), Bytes32::new([2; 32]), 100);
+        let memos = clawback.memos();
+        assert_eq!(Clawback::from_memos(&memos), Some(clawback));
+        assert_eq!(Clawback::from_memos(&memos[..1]), None);
+
+        let mut extra_memos = memos.clone();
+        extra_memos.push(Bytes::new(b"note".to_vec()));
+        assert_eq!(Clawback::from_memos(&extra_memos), Some(clawback));
+        assert_eq!(
+            Clawback::from_memos(&[Bytes32::new([3; 32]).into(), memos[1].clone()]),
+            None
+        );
+        assert_eq!(
+            Clawback::from_memos(&[memos[0].clone(), memos[1][..71].to_vec().into()]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_clawback_reference_values() {
+        let clawback = Clawback::new(
+            Bytes32::new(hex!(
+                "4e6bd6b8a3d6d3e8e4c1f3cbe3e6e0c0c3e1b6f1c3d6e2a4f6e8b0a2c4e6f8a0"
+            )),
+            Bytes32::new(hex!(
+                "0b7a3d5c5b0d2f6e1b2a8d9c7e6f5a4b3c2d1e0f9a8b7c6d5e4f3a2b1c0d9e8f"
+            )),
+            3600,
+        );
+
+        // The merkle tree only has the receiver's path and the sender's path.
+        let merkle_tree = clawback.merkle_tree();
+        assert_eq!(merkle_tree.proofs.len(), 2);
+        assert!(merkle_tree
+            .proofs
+            .contains_key(&clawback.receiver_path_hash().into()));
+        assert!(merkle_tree
+            .proofs
+            .contains_key(&clawback.sender_path_hash().into()));
+
+        assert_eq!(
+            clawback.memos(),
+            vec![
+                Bytes::new(
+                    hex!("0b7a3d5c5b0d2f6e1b2a8d9c7e6f5a4b3c2d1e0f9a8b7c6d5e4f3a2b1c0d9e8f")
+                        .to_vec()
+                ),
+                Bytes::new(
+                    hex!(
+                        "
+                    0000000000000e10
+                    4e6bd6b8a3d6d3e8e4c1f3cbe3e6e0c0c3e1b6f1c3d6e2a4f6e8b0a2c4e6f8a0
+                    0b7a3d5c5b0d2f6e1b2a8d9c7e6f5a4b3c2d1e0f9a8b7c6d5e4f3a2b1c0d9e8f
+                    "
+                    )
+                    .to_vec()
+                ),
+            ]
+        );
+
+        // This was calculated separately, with the reference wallet's curry and merkle tree hashing.
+        assert_eq!(
+            clawback.tree_hash(),
+            TreeHash::new(hex!(
+                "14e52c82020d8a529dc4623fa0c4316367e45d8985261fc765cb7585c4e1475a"
+            ))
+        );
+    }
+}
@@ -0,0 +1,410 @@
+use std::{any::Any, sync::Arc};
+
+use chia_protocol::{Coin, CoinSpend};
+use chia_sdk_types::{run_puzzle, Condition};
+use clvm_traits::{FromClvm, ToClvm};
+use clvmr::{Allocator, NodePtr};
+
+use crate::{
+    Cat, CatLayer, Did, DidInfo, DriverError, HashedPtr, Layer, Nft, NftInfo, P2Singleton, Puzzle,
+    SettlementLayer, StandardLayer,
+};
+
+#[cfg(feature = "chip-0035")]
+use crate::{DataStore, DataStoreMetadata, NftStateLayer, SingletonLayer};
+
+/// A puzzle which was recognized by a [`Recognizer`], along with the information parsed from its layers.
+#[derive(Debug, Clone)]
+pub enum RecognizedPuzzle {
+    /// The standard puzzle, which is used to hold XCH.
+    Standard(StandardLayer),
+    /// A CAT, along with its inner puzzle.
+    Cat(CatLayer<Puzzle>),
+    /// An NFT, along with its p2 puzzle.
+    Nft(NftInfo<HashedPtr>, Puzzle),
+    /// A DID, along with its p2 puzzle.
+    Did(DidInfo<HashedPtr>, Puzzle),
+    /// A singleton with the NFT state layer but no ownership layer, which is used by data stores.
+    #[cfg(feature = "chip-0035")]
+    DataStore(SingletonLayer<NftStateLayer<DataStoreMetadata, Puzzle>>),
+    Settlement(SettlementLayer),
+    P2Singleton(P2Singleton),
+    /// A layer which was registered with [`Recognizer::with_layer`].
+    Custom(CustomLayer),
+    Unknown(Puzzle),
+}
+
+/// A layer parsed by a parser which was registered with [`Recognizer::with_layer`].
+#[derive(Debug, Clone)]
+pub struct CustomLayer {
+    name: &'static str,
+    layer: Arc<dyn Any + Send + Sync>,
+}
+
+impl CustomLayer {
+    /// The type name of the layer.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn downcast_ref<L>(&self) -> Option<&L>
+    where
+        L: Any,
+    {
+        self.layer.downcast_ref()
+    }
+}
+
+/// An asset created by a coin spend, recognized by [`Recognizer::recognize_spend`].
+#[derive(Debug, Clone)]
+pub enum Asset {
+    /// A coin which isn't wrapped in a recognized asset layer, such as XCH.
+    Coin(Coin),
+    Cat(Cat),
+    Nft(Nft<HashedPtr>),
+    Did(Did<HashedPtr>),
+    /// A data store. Delegated puzzles which were carried over from the parent can't be known
+    /// from its spend alone, so they must be copied from the parent if needed.
+    #[cfg(feature = "chip-0035")]
+    DataStore(DataStore),
+}
+
+#[derive(Debug, Clone)]
+pub struct RecognizedSpend {
+    /// The puzzle of the coin that was spent.
+    pub puzzle: RecognizedPuzzle,
+    /// The assets created by the spend, parsed according to the puzzle.
+    pub children: Vec<Asset>,
+}
+
+type LayerParser = fn(&Allocator, Puzzle) -> Result<Option<CustomLayer>, DriverError>;
+
+/// Classifies puzzles and coin spends as one of the assets supported by this crate.
+/// Custom layers can be registered, and are tried in order after the built in ones.
+///
+/// Only the outermost layers are recognized. For example, the inner puzzle of a CAT can be
+/// recognized separately to find out whether it's the standard puzzle.
+#[derive(Debug, Default, Clone)]
+pub struct Recognizer {
+    parsers: Vec<LayerParser>,
+}
+
+impl Recognizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a layer, which is recognized as [`RecognizedPuzzle::Custom`].
+    #[must_use]
+    pub fn with_layer<L>(mut self) -> Self
+    where
+        L: Layer + Send + Sync + 'static,
+    {
+        self.parsers.push(parse_custom_layer::<L>);
+        self
+    }
+
+    pub fn recognize_puzzle(
+        &self,
+        allocator: &Allocator,
+        puzzle: Puzzle,
+    ) -> Result<RecognizedPuzzle, DriverError> {
+        if let Some(layer) = StandardLayer::parse_puzzle(allocator, puzzle)? {
+            return Ok(RecognizedPuzzle::Standard(layer));
+        }
+
+        if let Some(layer) = CatLayer::<Puzzle>::parse_puzzle(allocator, puzzle)? {
+            return Ok(RecognizedPuzzle::Cat(layer));
+        }
+
+        if let Some(layer) = SettlementLayer::parse_puzzle(allocator, puzzle)? {
+            return Ok(RecognizedPuzzle::Settlement(layer));
+        }
+
+        if let Some(layer) = P2Singleton::parse_puzzle(allocator, puzzle)? {
+            return Ok(RecognizedPuzzle::P2Singleton(layer));
+        }
+
+        if let Some((info, p2_puzzle)) = NftInfo::<HashedPtr>::parse(allocator, puzzle)? {
+            return Ok(RecognizedPuzzle::Nft(info, p2_puzzle));
+        }
+
+        if let Some((info, p2_puzzle)) = DidInfo::<HashedPtr>::parse(allocator, puzzle)? {
+            return Ok(RecognizedPuzzle::Did(info, p2_puzzle));
+        }
+
+        // This must come after NFTs, which also have the state layer.
+        #[cfg(feature = "chip-0035")]
+        if let Some(layers) =
+            SingletonLayer::<NftStateLayer<DataStoreMetadata, Puzzle>>::parse_puzzle(
+                allocator, puzzle,
+            )?
+        {
+            return Ok(RecognizedPuzzle::DataStore(layers));
+        }
+
+        for parser in &self.parsers {
+            if let Some(layer) = parser(allocator, puzzle)? {
+                return Ok(RecognizedPuzzle::Custom(layer));
+            }
+        }
+
+        Ok(RecognizedPuzzle::Unknown(puzzle))
+    }
+
+    /// Recognizes the puzzle of the coin spend, and parses the assets that it creates.
+    /// Assets can only be parsed for the puzzles which have a primitive in this crate, so the
+    /// children of any other puzzle are returned as [`Asset::Coin`].
+    pub fn recognize_spend(
+        &self,
+        allocator: &mut Allocator,
+        coin_spend: &CoinSpend,
+    ) -> Result<RecognizedSpend, DriverError> {
+        let parent_coin = coin_spend.coin;
+        let puzzle_ptr = coin_spend.puzzle_reveal.to_clvm(allocator)?;
+        let solution = coin_spend.solution.to_clvm(allocator)?;
+        let puzzle = Puzzle::parse(allocator, puzzle_ptr);
+
+        let recognized = self.recognize_puzzle(allocator, puzzle)?;
+
+        let children = match &recognized {
+            RecognizedPuzzle::Cat(..) => {
+                Cat::parse_children(allocator, parent_coin, puzzle, solution)?
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(Asset::Cat)
+                    .collect()
+            }
+            RecognizedPuzzle::Nft(..) => {
+                Nft::<HashedPtr>::parse_child(allocator, parent_coin, puzzle, solution)?
+                    .into_iter()
+                    .map(Asset::Nft)
+                    .collect()
+            }
+            RecognizedPuzzle::Did(..) => {
+                let Some(coin) = created_coins(allocator, parent_coin, puzzle_ptr, solution)?
+                    .into_iter()
+                    .find(|coin| coin.amount % 2 == 1)
+                else {
+                    return Err(DriverError::MissingChild);
+                };
+
+                Did::<HashedPtr>::parse_child(allocator, parent_coin, puzzle, solution, coin)?
+                    .into_iter()
+                    .map(Asset::Did)
+                    .collect()
+            }
+            #[cfg(feature = "chip-0035")]
+            RecognizedPuzzle::DataStore(..) => DataStore::from_spend(allocator, coin_spend, &[])?
+                .into_iter()
+                .map(Asset::DataStore)
+                .collect(),
+            _ => created_coins(allocator, parent_coin, puzzle_ptr, solution)?
+                .into_iter()
+                .map(Asset::Coin)
+                .collect(),
+        };
+
+        Ok(RecognizedSpend {
+            puzzle: recognized,
+            children,
+        })
+    }
+}
+
+fn parse_custom_layer<L>(
+    allocator: &Allocator,
+    puzzle: Puzzle,
+) -> Result<Option<CustomLayer>, DriverError>
+where
+    L: Layer + Send + Sync + 'static,
+{
+    Ok(
+        L::parse_puzzle(allocator, puzzle)?.map(|layer| CustomLayer {
+            name: std::any::type_name::<L>(),
+            layer: Arc::new(layer),
+        }),
+    )
+}
+
+fn created_coins(
+    allocator: &mut Allocator,
+    parent_coin: Coin,
+    puzzle: NodePtr,
+    solution: NodePtr,
+) -> Result<Vec<Coin>, DriverError> {
+    let output = run_puzzle(allocator, puzzle, solution)?;
+    let conditions = Vec::<Condition>::from_clvm(allocator, output)?;
+
+    Ok(conditions
+        .into_iter()
+        .filter_map(Condition::into_create_coin)
+        .map(|create_coin| {
+            Coin::new(
+                parent_coin.coin_id(),
+                create_coin.puzzle_hash,
+                create_coin.amount,
+            )
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use chia_protocol::Bytes32;
+    use chia_puzzles::nft::NftMetadata;
+    use chia_sdk_test::Simulator;
+    use chia_sdk_types::Conditions;
+
+    use crate::{
+        CatSpend, DidOwner, IntermediateLauncher, Launcher, NftMint, P2DelegatedConditionsLayer,
+        SpendContext, SpendWithConditions,
+    };
+
+    use super::*;
+
+    fn find_spend(coin_spends: &[CoinSpend], coin_id: Bytes32) -> &CoinSpend {
+        coin_spends
+            .iter()
+            .find(|coin_spend| coin_spend.coin.coin_id() == coin_id)
+            .expect("missing coin spend")
+    }
+
+    #[test]
+    fn test_recognize_cat_spend() -> anyhow::Result<()> {
+        let mut sim = Simulator::new();
+        let ctx = &mut SpendContext::new();
+        let (sk, pk, puzzle_hash, coin) = sim.new_p2(1)?;
+        let p2 = StandardLayer::new(pk);
+
+        let (issue_cat, cat) = Cat::single_issuance_eve(
+            ctx,
+            coin.coin_id(),
+            1,
+            Conditions::new().create_coin(puzzle_hash, 1, vec![puzzle_hash.into()]),
+        )?;
+        p2.spend(ctx, coin, issue_cat)?;
+
+        let issuance = ctx.take();
+        sim.spend_coins(issuance.clone(), &[sk.clone()])?;
+
+        let mut allocator = Allocator::new();
+        let recognizer = Recognizer::new();
+
+        let spend =
+            recognizer.recognize_spend(&mut allocator, find_spend(&issuance, coin.coin_id()))?;
+        assert!(matches!(spend.puzzle, RecognizedPuzzle::Standard(layer) if layer == p2));
+        assert!(matches!(spend.children[..], [Asset::Coin(child)] if child == cat.coin));
+
+        let cat = cat.wrapped_child(puzzle_hash, 1);
+        let inner_spend = p2.spend_with_conditions(
+            ctx,
+            Conditions::new().create_coin(puzzle_hash, 1, vec![puzzle_hash.into()]),
+        )?;
+        Cat::spend_all(ctx, &[CatSpend::new(cat, inner_spend)])?;
+
+        let coin_spends = ctx.take();
+        sim.spend_coins(coin_spends.clone(), &[sk])?;
+
+        let spend = recognizer.recognize_spend(&mut allocator, &coin_spends[0])?;
+
+        let RecognizedPuzzle::Cat(layer) = spend.puzzle else {
+            panic!("expected a CAT, but found {:?}", spend.puzzle);
+        };
+        assert_eq!(layer.asset_id, cat.asset_id);
+        assert!(matches!(
+            recognizer.recognize_puzzle(&allocator, layer.inner_puzzle)?,
+            RecognizedPuzzle::Standard(..)
+        ));
+
+        let expected = cat.wrapped_child(puzzle_hash, 1);
+        assert!(matches!(spend.children[..], [Asset::Cat(child)] if child == expected));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recognize_nft_and_did_spends() -> anyhow::Result<()> {
+        let mut sim = Simulator::new();
+        let ctx = &mut SpendContext::new();
+        let (sk, pk, puzzle_hash, coin) = sim.new_p2(2)?;
+        let p2 = StandardLayer::new(pk);
+
+        let (create_did, did) = Launcher::new(coin.coin_id(), 1).create_simple_did(ctx, &p2)?;
+        p2.spend(ctx, coin, create_did)?;
+
+        let (mint_nft, nft) = IntermediateLauncher::new(did.coin.coin_id(), 0, 1)
+            .create(ctx)?
+            .mint_nft(
+                ctx,
+                NftMint::new(
+                    NftMetadata::default(),
+                    puzzle_hash,
+                    300,
+                    Some(DidOwner::from_did_info(&did.info)),
+                ),
+            )?;
+        let new_did = did.update(ctx, &p2, mint_nft)?;
+        let nft_coin = nft.coin;
+        let new_nft = nft.transfer(ctx, &p2, puzzle_hash, Conditions::new())?;
+
+        let coin_spends = ctx.take();
+        sim.spend_coins(coin_spends.clone(), &[sk])?;
+
+        let mut allocator = Allocator::new();
+        let recognizer = Recognizer::new();
+
+        let spend = recognizer
+            .recognize_spend(&mut allocator, find_spend(&coin_spends, nft_coin.coin_id()))?;
+        assert!(
+            matches!(spend.puzzle, RecognizedPuzzle::Nft(info, _) if info.launcher_id == new_nft.info.launcher_id)
+        );
+        let [Asset::Nft(child)] = &spend.children[..] else {
+            panic!("expected an NFT, but found {:?}", spend.children);
+        };
+        assert_eq!(child.coin, new_nft.coin);
+        assert_eq!(child.info.p2_puzzle_hash, puzzle_hash);
+
+        let spend = recognizer
+            .recognize_spend(&mut allocator, find_spend(&coin_spends, did.coin.coin_id()))?;
+        assert!(matches!(spend.puzzle, RecognizedPuzzle::Did(..)));
+        let [Asset::Did(child)] = &spend.children[..] else {
+            panic!("expected a DID, but found {:?}", spend.children);
+        };
+        assert_eq!(child.coin, new_did.coin);
+        assert_eq!(child.info.launcher_id, did.info.launcher_id);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recognize_custom_layer() -> anyhow::Result<()> {
+        let mut sim = Simulator::new();
+        let ctx = &mut SpendContext::new();
+        let (_sk, pk, _puzzle_hash, _coin) = sim.new_p2(1)?;
+
+        let layer = P2DelegatedConditionsLayer { public_key: pk };
+        let puzzle = layer.construct_puzzle(ctx)?;
+        let puzzle = Puzzle::parse(&ctx.allocator, puzzle);
+
+        assert!(matches!(
+            Recognizer::new().recognize_puzzle(&ctx.allocator, puzzle)?,
+            RecognizedPuzzle::Unknown(..)
+        ));
+
+        let recognizer = Recognizer::new().with_layer::<P2DelegatedConditionsLayer>();
+
+        let RecognizedPuzzle::Custom(custom) =
+            recognizer.recognize_puzzle(&ctx.allocator, puzzle)?
+        else {
+            panic!("expected a custom layer");
+        };
+        assert_eq!(
+            custom.downcast_ref::<P2DelegatedConditionsLayer>(),
+            Some(&layer)
+        );
+        assert!(custom.name().ends_with("P2DelegatedConditionsLayer"));
+
+        Ok(())
+    }
+}
@@ -27,9 +27,11 @@ use clvm_utils::{tree_hash, TreeHash};
 use clvmr::{serde::node_from_bytes, Allocator, NodePtr};
 
 use crate::{
-    DriverError, Spend, P2_DELEGATED_CONDITIONS_PUZZLE, P2_DELEGATED_CONDITIONS_PUZZLE_HASH,
-    P2_DELEGATED_SINGLETON_PUZZLE, P2_DELEGATED_SINGLETON_PUZZLE_HASH, P2_ONE_OF_MANY_PUZZLE,
-    P2_ONE_OF_MANY_PUZZLE_HASH, P2_SINGLETON_PUZZLE, P2_SINGLETON_PUZZLE_HASH,
+    DriverError, Spend, AUGMENTED_CONDITION_PUZZLE, AUGMENTED_CONDITION_PUZZLE_HASH,
+    P2_CURRIED_PUZZLE, P2_CURRIED_PUZZLE_HASH, P2_DELEGATED_CONDITIONS_PUZZLE,
+    P2_DELEGATED_CONDITIONS_PUZZLE_HASH, P2_DELEGATED_SINGLETON_PUZZLE,
+    P2_DELEGATED_SINGLETON_PUZZLE_HASH, P2_ONE_OF_MANY_PUZZLE, P2_ONE_OF_MANY_PUZZLE_HASH,
+    P2_SINGLETON_PUZZLE, P2_SINGLETON_PUZZLE_HASH,
 };
 
 /// A wrapper around [`Allocator`] that caches puzzles and keeps track of a list of [`CoinSpend`].
@@ -209,6 +211,16 @@ impl SpendContext {
         )
     }
 
+    /// Allocate the p2 curried puzzle and return its pointer.
+    pub fn p2_curried_puzzle(&mut self) -> Result<NodePtr, DriverError> {
+        self.puzzle(P2_CURRIED_PUZZLE_HASH, &P2_CURRIED_PUZZLE)
+    }
+
+    /// Allocate the augmented condition puzzle and return its pointer.
+    pub fn augmented_condition_puzzle(&mut self) -> Result<NodePtr, DriverError> {
+        self.puzzle(AUGMENTED_CONDITION_PUZZLE_HASH, &AUGMENTED_CONDITION_PUZZLE)
+    }
+
     /// Preload a puzzle into the cache.
     pub fn preload(&mut self, puzzle_hash: TreeHash, ptr: NodePtr) {
         self.puzzles.insert(puzzle_hash, ptr);
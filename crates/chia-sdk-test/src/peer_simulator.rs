@@ -88,6 +88,11 @@ impl PeerSimulator {
         &self.config
     }
 
+    /// The address that the simulator is listening for websocket connections on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
     pub async fn connect_raw(&self) -> Result<(Peer, mpsc::Receiver<Message>), PeerSimulatorError> {
         tracing::info!("connecting new peer to simulator");
         let (ws, _) = connect_async(format!("ws://{}", self.addr)).await?;
@@ -95,6 +100,8 @@ impl PeerSimulator {
             ws,
             PeerOptions {
                 rate_limit_factor: 0.6,
+                trusted: true,
+                ..Default::default()
             },
         )?)
     }
@@ -151,6 +158,10 @@ impl PeerSimulator {
     pub async fn peak_hash(&self) -> Bytes32 {
         self.simulator.lock().await.header_hash()
     }
+
+    pub async fn reorg(&self, fork_height: u32) {
+        self.simulator.lock().await.reorg(fork_height);
+    }
 }
 
 impl Drop for PeerSimulator {
@@ -161,12 +172,25 @@ impl Drop for PeerSimulator {
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use chia_bls::{DerivableKey, PublicKey, Signature};
     use chia_protocol::{
-        Bytes, CoinSpend, CoinStateFilters, CoinStateUpdate, RespondCoinState, RespondPuzzleState,
-        SpendBundle,
+        Bytes, CoinSpend, CoinStateFilters, CoinStateUpdate, ProtocolMessageTypes,
+        RequestPuzzleState, RespondCoinState, RespondPuzzleState, SpendBundle,
+    };
+    use chia_sdk_client::{
+        spend_bundle_cost, BroadcastOptions, ClientError, MessageDirection, PeerEvent,
+        PeerEventRouter, Proxy, Recording, SyncEvent, SyncOptions, TransactionAckStatus,
+        TransactionBroadcaster, TransactionStatus, WalletSync,
     };
     use chia_sdk_types::{AggSigMe, CreateCoin, Remark};
+    use chia_traits::Streamable;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::{
+        io::{copy_bidirectional, AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
 
     use crate::{coin_state_updates, test_secret_key, test_transaction, to_program, to_puzzle};
 
@@ -402,6 +426,24 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_request_timeout() -> anyhow::Result<()> {
+        let sim = PeerSimulator::new().await?;
+        let peer = sim
+            .connect()
+            .await?
+            .with_request_timeout(Duration::from_millis(100));
+
+        // The simulator doesn't respond to peer requests.
+        let error = peer.request_peers().await.unwrap_err();
+        assert!(matches!(
+            error,
+            ClientError::Timeout(ProtocolMessageTypes::RequestPeers)
+        ));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_request_children_unknown() -> anyhow::Result<()> {
         let sim = PeerSimulator::new().await?;
@@ -894,4 +936,781 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_wallet_sync() -> anyhow::Result<()> {
+        let sim = PeerSimulator::with_config(SimulatorConfig {
+            puzzle_state_batch_size: 2,
+            ..Default::default()
+        })
+        .await?;
+        let (peer, mut receiver) = sim.connect_split().await?;
+
+        let (puzzle_hash, puzzle_reveal) = to_puzzle(1)?;
+        let (other_puzzle_hash, _) = to_puzzle(2)?;
+
+        let mut coin = sim.mint_coin(puzzle_hash, 10).await;
+        sim.mint_coin(other_puzzle_hash, 1).await;
+
+        let spend = |coin: Coin| -> anyhow::Result<(Coin, SpendBundle)> {
+            let child = Coin::new(coin.coin_id(), puzzle_hash, coin.amount - 1);
+            let spend_bundle = SpendBundle::new(
+                vec![CoinSpend::new(
+                    coin,
+                    puzzle_reveal.clone(),
+                    to_program([CreateCoin::new(puzzle_hash, child.amount, Vec::new())])?,
+                )],
+                Signature::default(),
+            );
+            Ok((child, spend_bundle))
+        };
+
+        // Spread the coin states out over multiple heights, so that they're paginated.
+        for _ in 0..5 {
+            let (child, spend_bundle) = spend(coin)?;
+            let ack = peer.send_transaction(spend_bundle).await?;
+            assert_eq!(ack.status, 1);
+            coin = child;
+        }
+
+        let mut sync = WalletSync::new(
+            peer.clone(),
+            sim.config().constants.genesis_challenge,
+            SyncOptions {
+                batch_size: 1,
+                ..Default::default()
+            },
+        );
+
+        let events = sync
+            .add_puzzle_hashes(vec![puzzle_hash, other_puzzle_hash, puzzle_hash])
+            .await?;
+        assert_eq!(events.len(), 2);
+
+        let header_hash = sim.header_hash(5).await;
+        let coin_count: usize = events
+            .iter()
+            .map(|event| match event {
+                SyncEvent::CoinStates {
+                    height,
+                    header_hash: event_header_hash,
+                    coin_states,
+                } => {
+                    assert_eq!(*height, 5);
+                    assert_eq!(*event_header_hash, header_hash);
+                    coin_states.len()
+                }
+                event => panic!("unexpected event {event:?}"),
+            })
+            .sum();
+        assert_eq!(coin_count, 7);
+        assert_eq!(sync.peak(), Some((5, header_hash)));
+
+        // Puzzle hashes that are already synced are skipped.
+        assert!(sync.add_puzzle_hashes(vec![puzzle_hash]).await?.is_empty());
+
+        let (child, spend_bundle) = spend(coin)?;
+        let ack = peer.send_transaction(spend_bundle).await?;
+        assert_eq!(ack.status, 1);
+
+        let mut events = Vec::new();
+        while let Ok(message) = receiver.try_recv() {
+            events.extend(sync.handle_message(&message)?);
+        }
+
+        let [SyncEvent::CoinStates {
+            height,
+            coin_states,
+            ..
+        }] = events.as_slice()
+        else {
+            panic!("expected a single coin state update");
+        };
+        assert_eq!(*height, 6);
+        assert_eq!(coin_states.len(), 2);
+        assert!(coin_states.contains(&CoinState::new(coin, Some(5), Some(4))));
+        assert!(coin_states.contains(&CoinState::new(child, None, Some(5))));
+        assert_eq!(sync.peak(), Some((6, sim.peak_hash().await)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wallet_sync_subscription_limit() -> anyhow::Result<()> {
+        let sim = PeerSimulator::with_config(SimulatorConfig {
+            max_subscriptions: 3,
+            ..Default::default()
+        })
+        .await?;
+        let peer = sim.connect().await?;
+
+        let mut puzzle_hashes = Vec::new();
+
+        for i in 1..=5 {
+            let (puzzle_hash, _) = to_puzzle(i)?;
+            sim.mint_coin(puzzle_hash, 1).await;
+            puzzle_hashes.push(puzzle_hash);
+        }
+
+        let mut sync = WalletSync::new(
+            peer,
+            sim.config().constants.genesis_challenge,
+            SyncOptions::default(),
+        );
+        let events = sync.add_puzzle_hashes(puzzle_hashes).await?;
+
+        // The batch is split until as many puzzle hashes as possible have been subscribed to.
+        let coin_count: usize = events
+            .iter()
+            .map(|event| match event {
+                SyncEvent::CoinStates { coin_states, .. } => coin_states.len(),
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(coin_count, 3);
+        assert_eq!(sync.puzzle_hashes().len(), 3);
+
+        let Some(SyncEvent::SubscriptionLimitExceeded {
+            puzzle_hashes: rejected,
+        }) = events.last()
+        else {
+            panic!("expected the remaining puzzle hashes to be rejected");
+        };
+        assert_eq!(rejected.len(), 2);
+        assert!(rejected
+            .iter()
+            .all(|puzzle_hash| !sync.puzzle_hashes().contains(puzzle_hash)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wallet_sync_reorg() -> anyhow::Result<()> {
+        let sim = PeerSimulator::new().await?;
+        let peer = sim.connect().await?;
+
+        let mut sync = WalletSync::new(peer, Bytes32::default(), SyncOptions::default());
+        sync.handle_update(CoinStateUpdate::new(10, 10, Bytes32::default(), Vec::new()));
+
+        let events = sync.handle_update(CoinStateUpdate::new(9, 7, Bytes32::default(), Vec::new()));
+        assert_eq!(events[0], SyncEvent::Reorg { fork_height: 7 });
+        assert_eq!(sync.peak(), Some((9, Bytes32::default())));
+
+        Ok(())
+    }
+
+    #[derive(Debug, Default)]
+    struct RelayLog {
+        request_heights: Vec<Option<u32>>,
+        response_heights: Vec<u32>,
+    }
+
+    // Relays a single connection to the simulator, and reorgs out the block of the latest puzzle
+    // state response once the given number of responses have been sent back to the peer.
+    async fn reorg_relay(
+        sim: &PeerSimulator,
+        reorg_after: usize,
+    ) -> anyhow::Result<(SocketAddr, Arc<Mutex<RelayLog>>)> {
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let sim_addr = sim.addr();
+        let simulator = sim.simulator.clone();
+        let log = Arc::new(Mutex::new(RelayLog::default()));
+        let log_clone = log.clone();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut client = tokio_tungstenite::accept_async(stream).await?;
+            let (mut server, _) = connect_async(format!("ws://{sim_addr}")).await?;
+
+            loop {
+                tokio::select! {
+                    message = client.next() => {
+                        let Some(Ok(message)) = message else { break };
+
+                        if let WsMessage::Binary(bytes) = &message {
+                            let message = Message::from_bytes(bytes)?;
+                            if message.msg_type == ProtocolMessageTypes::RequestPuzzleState {
+                                let request = RequestPuzzleState::from_bytes(&message.data)?;
+                                log_clone.lock().await.request_heights.push(request.previous_height);
+                            }
+                        }
+
+                        server.send(message).await?;
+                    }
+                    message = server.next() => {
+                        let Some(Ok(message)) = message else { break };
+
+                        if let WsMessage::Binary(bytes) = &message {
+                            let message = Message::from_bytes(bytes)?;
+                            if message.msg_type == ProtocolMessageTypes::RespondPuzzleState {
+                                let response = RespondPuzzleState::from_bytes(&message.data)?;
+                                let mut log = log_clone.lock().await;
+                                log.response_heights.push(response.height);
+
+                                // The reorg happens before the peer can make its next request.
+                                if log.response_heights.len() == reorg_after {
+                                    let fork_height = log.response_heights[reorg_after - 2];
+                                    simulator.lock().await.reorg(fork_height);
+                                }
+                            }
+                        }
+
+                        client.send(message).await?;
+                    }
+                }
+            }
+
+            anyhow::Ok(())
+        });
+
+        Ok((addr, log))
+    }
+
+    #[tokio::test]
+    async fn test_wallet_sync_resumes_after_reorg() -> anyhow::Result<()> {
+        let sim = PeerSimulator::with_config(SimulatorConfig {
+            max_response_coins: 2,
+            puzzle_state_batch_size: 2,
+            ..Default::default()
+        })
+        .await?;
+        let peer = sim.connect().await?;
+
+        let (puzzle_hash, puzzle_reveal) = to_puzzle(1)?;
+        let mut coin = sim.mint_coin(puzzle_hash, 10).await;
+
+        // Spread the coin states out over multiple heights, so that they're paginated.
+        for _ in 0..5 {
+            let child = Coin::new(coin.coin_id(), puzzle_hash, coin.amount - 1);
+            let spend_bundle = SpendBundle::new(
+                vec![CoinSpend::new(
+                    coin,
+                    puzzle_reveal.clone(),
+                    to_program([CreateCoin::new(puzzle_hash, child.amount, Vec::new())])?,
+                )],
+                Signature::default(),
+            );
+            let ack = peer.send_transaction(spend_bundle).await?;
+            assert_eq!(ack.status, 1);
+            coin = child;
+        }
+
+        let (relay_addr, log) = reorg_relay(&sim, 2).await?;
+        let (ws, _) = connect_async(format!("ws://{relay_addr}")).await?;
+        let (peer, mut receiver) = Peer::from_websocket(
+            ws,
+            PeerOptions {
+                trusted: true,
+                ..Default::default()
+            },
+        )?;
+        tokio::spawn(async move { while receiver.recv().await.is_some() {} });
+
+        let mut sync = WalletSync::new(
+            peer,
+            sim.config().constants.genesis_challenge,
+            SyncOptions {
+                retry_delay: Duration::ZERO,
+                ..Default::default()
+            },
+        );
+
+        let events = sync.add_puzzle_hashes(vec![puzzle_hash]).await?;
+        let coin_count: usize = events
+            .iter()
+            .map(|event| match event {
+                SyncEvent::CoinStates { coin_states, .. } => coin_states.len(),
+                event => panic!("unexpected event {event:?}"),
+            })
+            .sum();
+        assert_eq!(coin_count, 6);
+        assert_eq!(sync.peak(), Some((5, sim.peak_hash().await)));
+
+        // The request from height 3 is rejected after the reorg, so the sync resumes from the
+        // checkpoint at height 1 rather than starting over.
+        let log = log.lock().await;
+        assert_eq!(log.response_heights, [1, 3, 3, 5]);
+        assert_eq!(
+            log.request_heights,
+            [None, Some(1), Some(3), Some(1), Some(3)]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_peer_event_router() -> anyhow::Result<()> {
+        let sim = PeerSimulator::new().await?;
+        let (peer, receiver) = sim.connect_split().await?;
+
+        let router = PeerEventRouter::spawn(receiver, 16);
+        let mut all_events = router.subscribe();
+        let mut coin_state_updates = router.subscribe_to([ProtocolMessageTypes::CoinStateUpdate]);
+
+        let (puzzle_hash, puzzle_reveal) = to_puzzle(1)?;
+        let coin = sim.mint_coin(puzzle_hash, 0).await;
+
+        peer.register_for_coin_updates(vec![coin.coin_id()], 0)
+            .await?;
+
+        let spend_bundle = SpendBundle::new(
+            vec![CoinSpend::new(coin, puzzle_reveal, to_program(())?)],
+            Signature::default(),
+        );
+
+        let ack = peer.send_transaction(spend_bundle).await?;
+        assert_eq!(ack.status, 1);
+
+        let Some(PeerEvent::NewPeakWallet(new_peak)) = all_events.recv().await else {
+            panic!("expected new peak event");
+        };
+        assert_eq!(new_peak.height, 1);
+
+        let Some(PeerEvent::CoinStateUpdate(update)) = all_events.recv().await else {
+            panic!("expected coin state update event");
+        };
+        assert_eq!(update.items, vec![CoinState::new(coin, Some(0), Some(0))]);
+
+        assert_eq!(
+            coin_state_updates.recv().await,
+            Some(PeerEvent::CoinStateUpdate(update))
+        );
+        assert_eq!(coin_state_updates.try_recv(), None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_peer_peak() -> anyhow::Result<()> {
+        let sim = PeerSimulator::new().await?;
+        let (peer, mut receiver) = sim.connect_split().await?;
+
+        let peak = peer.peak().expect("missing peak");
+        assert_eq!(peak.height, 0);
+
+        let (puzzle_hash, puzzle_reveal) = to_puzzle(1)?;
+        let coin = sim.mint_coin(puzzle_hash, 0).await;
+
+        let spend_bundle = SpendBundle::new(
+            vec![CoinSpend::new(coin, puzzle_reveal, to_program(())?)],
+            Signature::default(),
+        );
+
+        let ack = peer.send_transaction(spend_bundle).await?;
+        assert_eq!(ack.status, 1);
+
+        receiver
+            .recv()
+            .await
+            .expect("expected NewPeakWallet message");
+
+        let peak = peer.peak().expect("missing peak");
+        assert_eq!(peak.height, 1);
+        assert_eq!(peak.header_hash, sim.peak_hash().await);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_peer_metrics() -> anyhow::Result<()> {
+        let sim = PeerSimulator::new().await?;
+        let peer = sim.connect().await?;
+
+        for _ in 0..3 {
+            peer.request_children(Bytes32::default()).await?;
+        }
+
+        let metrics = peer.metrics();
+
+        let outbound = metrics.outbound[&ProtocolMessageTypes::RequestChildren];
+        assert_eq!(outbound.messages, 3);
+        assert!(outbound.bytes > 0);
+
+        let inbound = metrics.inbound[&ProtocolMessageTypes::RespondChildren];
+        assert_eq!(inbound.messages, 3);
+        assert!(metrics.total_inbound().messages >= 3);
+
+        let latency = metrics.latency[&ProtocolMessageTypes::RequestChildren];
+        assert_eq!(latency.count(), 3);
+        assert!(latency.quantile(0.5).is_some());
+
+        assert_eq!(metrics.pending_requests, 0);
+        assert!(metrics.timeouts.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() -> anyhow::Result<()> {
+        let sim = PeerSimulator::new().await?;
+        let (peer, mut receiver) = sim.connect_split().await?;
+
+        let (puzzle_hash, puzzle_reveal) = to_puzzle(1)?;
+        let coin = sim.mint_coin(puzzle_hash, 0).await;
+        let spend_bundle = SpendBundle::new(
+            vec![CoinSpend::new(coin, puzzle_reveal, to_program(())?)],
+            Signature::default(),
+        );
+
+        let path = std::env::temp_dir().join(format!("recording-{}.bin", std::process::id()));
+        peer.start_recording(&path)?;
+
+        let coin_states = peer
+            .register_for_ph_updates(vec![puzzle_hash], 0)
+            .await?
+            .coin_states;
+        let ack = peer.send_transaction(spend_bundle.clone()).await?;
+        let updates = coin_state_updates(&mut receiver);
+        assert_eq!(updates.len(), 1);
+
+        peer.stop_recording()?;
+        let recording = Recording::load(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(recording.messages[0].direction, MessageDirection::Outbound);
+        assert!(recording
+            .messages
+            .windows(2)
+            .all(|pair| pair[0].elapsed <= pair[1].elapsed));
+
+        let (replay, mut replay_receiver) = recording.replay(PeerOptions::default()).await?;
+
+        assert_eq!(
+            replay
+                .register_for_ph_updates(vec![puzzle_hash], 0)
+                .await?
+                .coin_states,
+            coin_states
+        );
+        assert_eq!(replay.send_transaction(spend_bundle).await?, ack);
+
+        // Messages which the peer sent on its own are delivered after the requests before them.
+        let update = loop {
+            let message = tokio::time::timeout(Duration::from_secs(5), replay_receiver.recv())
+                .await?
+                .expect("missing coin state update");
+
+            if message.msg_type == ProtocolMessageTypes::CoinStateUpdate {
+                break CoinStateUpdate::from_bytes(&message.data)?;
+            }
+        };
+        assert_eq!(update, updates[0]);
+
+        // Requests which aren't in the recording close the connection.
+        assert!(replay
+            .with_request_timeout(Duration::from_secs(1))
+            .request_children(Bytes32::default())
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fee_estimates() -> anyhow::Result<()> {
+        let sim = PeerSimulator::with_config(SimulatorConfig {
+            fee_rate: 5,
+            ..Default::default()
+        })
+        .await?;
+        let peer = sim.connect().await?;
+
+        let (puzzle_hash, puzzle_reveal) = to_puzzle(1)?;
+        let coin = sim.mint_coin(puzzle_hash, 0).await;
+
+        let spend_bundle = SpendBundle::new(
+            vec![CoinSpend::new(coin, puzzle_reveal, to_program(())?)],
+            Signature::default(),
+        );
+
+        let cost = spend_bundle_cost(&spend_bundle, &sim.config().constants, sim.height().await)?;
+        assert!(cost > 0);
+
+        let time_targets = [Duration::from_secs(60), Duration::from_secs(300)];
+        let suggestions = peer.suggest_fees(cost, &time_targets).await?;
+
+        assert_eq!(suggestions.len(), 2);
+
+        for (suggestion, time_target) in suggestions.into_iter().zip(time_targets) {
+            assert_eq!(suggestion.time_target, time_target);
+            assert_eq!(suggestion.mojos_per_clvm_cost, 5);
+            assert_eq!(suggestion.fee, cost * 5);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_confirmed() -> anyhow::Result<()> {
+        let sim = PeerSimulator::new().await?;
+        let peer = sim.connect().await?;
+
+        let (puzzle_hash, puzzle_reveal) = to_puzzle(1)?;
+        let coin = sim.mint_coin(puzzle_hash, 1).await;
+
+        let spend_bundle = SpendBundle::new(
+            vec![CoinSpend::new(
+                coin,
+                puzzle_reveal,
+                to_program([CreateCoin::new(puzzle_hash, 1, Vec::new())])?,
+            )],
+            Signature::default(),
+        );
+
+        let broadcaster = TransactionBroadcaster::new(
+            sim.config().constants.clone(),
+            BroadcastOptions::default(),
+        );
+        let pending = broadcaster
+            .broadcast(vec![peer.clone()], spend_bundle)
+            .await?;
+
+        assert_eq!(
+            pending.acks(),
+            [(peer.socket_addr(), TransactionAckStatus::Success)]
+        );
+        assert!(matches!(pending.await?, TransactionStatus::Confirmed(_)));
+        assert!(broadcaster.pending_transactions().is_empty());
+
+        // The coins are no longer subscribed to once the transaction is resolved.
+        let removed = peer.remove_coin_subscriptions(None).await?;
+        assert!(removed.coin_ids.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_failed() -> anyhow::Result<()> {
+        let sim = PeerSimulator::new().await?;
+        let peer = sim.connect().await?;
+        let public_key = test_secret_key()?.public_key();
+
+        let (puzzle_hash, puzzle_reveal) = to_puzzle(1)?;
+        let coin = sim.mint_coin(puzzle_hash, 0).await;
+
+        let spend_bundle = SpendBundle::new(
+            vec![CoinSpend::new(
+                coin,
+                puzzle_reveal,
+                to_program([AggSigMe::new(public_key, Bytes::default())])?,
+            )],
+            Signature::default(),
+        );
+
+        let broadcaster = TransactionBroadcaster::new(
+            sim.config().constants.clone(),
+            BroadcastOptions::default(),
+        );
+        let pending = broadcaster
+            .broadcast(vec![peer.clone()], spend_bundle.clone())
+            .await?;
+
+        assert!(matches!(
+            pending.acks(),
+            [(_, TransactionAckStatus::Invalid(_))]
+        ));
+        assert!(matches!(pending.await?, TransactionStatus::Failed(_)));
+        assert!(broadcaster.pending_transactions().is_empty());
+
+        let removed = peer.remove_coin_subscriptions(None).await?;
+        assert!(removed.coin_ids.is_empty());
+
+        assert!(matches!(
+            broadcaster.broadcast(Vec::new(), spend_bundle).await,
+            Err(ClientError::NoPeers)
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_conflicted() -> anyhow::Result<()> {
+        let sim = PeerSimulator::new().await?;
+        let peer = sim.connect().await?;
+
+        let (puzzle_hash, puzzle_reveal) = to_puzzle(1)?;
+        let coin = sim.mint_coin(puzzle_hash, 2).await;
+
+        let spend_bundle = SpendBundle::new(
+            vec![CoinSpend::new(
+                coin,
+                puzzle_reveal.clone(),
+                to_program([CreateCoin::new(puzzle_hash, 2, Vec::new())])?,
+            )],
+            Signature::default(),
+        );
+
+        let ack = peer.send_transaction(spend_bundle).await?;
+        assert_eq!(ack.status, 1);
+
+        // The same coin is spent again, but with a different output.
+        let spend_bundle = SpendBundle::new(
+            vec![CoinSpend::new(
+                coin,
+                puzzle_reveal,
+                to_program([CreateCoin::new(puzzle_hash, 1, Vec::new())])?,
+            )],
+            Signature::default(),
+        );
+
+        let broadcaster = TransactionBroadcaster::new(
+            sim.config().constants.clone(),
+            BroadcastOptions::default(),
+        );
+        let pending = broadcaster.broadcast(vec![peer], spend_bundle).await?;
+
+        assert_eq!(pending.await?, TransactionStatus::Conflicted);
+
+        Ok(())
+    }
+
+    // A minimal SOCKS5 proxy, which accepts a single connection without authentication.
+    async fn socks5_proxy() -> anyhow::Result<(SocketAddr, JoinHandle<anyhow::Result<SocketAddr>>)>
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+
+        let handle = tokio::spawn(async move {
+            let (mut client, _) = listener.accept().await?;
+
+            let mut greeting = [0; 3];
+            client.read_exact(&mut greeting).await?;
+            assert_eq!(greeting, [5, 1, 0]);
+            client.write_all(&[5, 0]).await?;
+
+            let mut request = [0; 10];
+            client.read_exact(&mut request).await?;
+            assert_eq!(request[..4], [5, 1, 0, 1]);
+
+            let ip = std::net::Ipv4Addr::new(request[4], request[5], request[6], request[7]);
+            let port = u16::from_be_bytes([request[8], request[9]]);
+            let target = SocketAddr::new(ip.into(), port);
+
+            let mut server = TcpStream::connect(target).await?;
+            client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+
+            tokio::spawn(async move { copy_bidirectional(&mut client, &mut server).await });
+
+            Ok(target)
+        });
+
+        Ok((addr, handle))
+    }
+
+    // A minimal HTTP proxy, which accepts a single `CONNECT` request with the expected credentials.
+    async fn http_proxy(
+        authorization: &'static str,
+    ) -> anyhow::Result<(SocketAddr, JoinHandle<anyhow::Result<String>>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+
+        let handle = tokio::spawn(async move {
+            let (mut client, _) = listener.accept().await?;
+
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(client.read_u8().await?);
+            }
+            let request = String::from_utf8(request)?;
+
+            let target = request
+                .split_whitespace()
+                .nth(1)
+                .expect("missing CONNECT target")
+                .to_string();
+
+            if !request.contains(&format!("Proxy-Authorization: Basic {authorization}\r\n")) {
+                client
+                    .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+                    .await?;
+                return Ok(target);
+            }
+
+            let mut server = TcpStream::connect(&target).await?;
+            client
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                .await?;
+
+            tokio::spawn(async move { copy_bidirectional(&mut client, &mut server).await });
+
+            Ok(target)
+        });
+
+        Ok((addr, handle))
+    }
+
+    async fn connect_through(proxy: &Proxy, sim: &PeerSimulator) -> anyhow::Result<Peer> {
+        let addr = sim.addr();
+        let stream = proxy.connect(&addr.ip().to_string(), addr.port()).await?;
+        let (ws, _) = tokio_tungstenite::client_async(
+            format!("ws://{addr}"),
+            tokio_tungstenite::MaybeTlsStream::Plain(stream),
+        )
+        .await?;
+
+        let (peer, mut receiver) = Peer::from_websocket(
+            ws,
+            PeerOptions {
+                trusted: true,
+                ..Default::default()
+            },
+        )?;
+
+        tokio::spawn(async move { while receiver.recv().await.is_some() {} });
+
+        Ok(peer)
+    }
+
+    #[tokio::test]
+    async fn test_socks5_proxy() -> anyhow::Result<()> {
+        let sim = PeerSimulator::new().await?;
+        let (proxy_addr, proxy_handle) = socks5_proxy().await?;
+
+        let proxy: Proxy = format!("socks5://{proxy_addr}").parse()?;
+        let peer = connect_through(&proxy, &sim).await?;
+
+        let children = peer.request_children(Bytes32::default()).await?;
+        assert!(children.coin_states.is_empty());
+
+        assert_eq!(proxy_handle.await??, sim.addr());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_http_proxy() -> anyhow::Result<()> {
+        let sim = PeerSimulator::new().await?;
+        let (proxy_addr, proxy_handle) = http_proxy("dXNlcjpwYXNz").await?;
+
+        let proxy = Proxy::http(proxy_addr.to_string()).with_credentials("user", "pass");
+        let peer = connect_through(&proxy, &sim).await?;
+
+        let children = peer.request_children(Bytes32::default()).await?;
+        assert!(children.coin_states.is_empty());
+
+        assert_eq!(proxy_handle.await??, sim.addr().to_string());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_http_proxy_rejected() -> anyhow::Result<()> {
+        let sim = PeerSimulator::new().await?;
+        let (proxy_addr, _proxy_handle) = http_proxy("dXNlcjpwYXNz").await?;
+
+        let proxy = Proxy::http(proxy_addr.to_string());
+
+        assert!(matches!(
+            connect_through(&proxy, &sim)
+                .await
+                .unwrap_err()
+                .downcast::<ClientError>()?,
+            ClientError::Proxy(..)
+        ));
+
+        Ok(())
+    }
 }
@@ -7,6 +7,8 @@ pub struct SimulatorConfig {
     pub max_subscriptions: usize,
     pub max_response_coins: usize,
     pub puzzle_state_batch_size: usize,
+    /// The fee rate, in mojos per CLVM cost, that is estimated for every time target.
+    pub fee_rate: u64,
 }
 
 impl Default for SimulatorConfig {
@@ -16,6 +18,7 @@ impl Default for SimulatorConfig {
             max_subscriptions: 200_000,
             max_response_coins: 100_000,
             puzzle_state_batch_size: 30_000,
+            fee_rate: 0,
         }
     }
 }
@@ -2,13 +2,15 @@ use std::{net::SocketAddr, sync::Arc};
 
 use chia_consensus::gen::validation_error::{ErrorCode, ValidationErr};
 use chia_protocol::{
-    Bytes, Bytes32, CoinState, CoinStateUpdate, Message, NewPeakWallet, ProtocolMessageTypes,
-    PuzzleSolutionResponse, RegisterForCoinUpdates, RegisterForPhUpdates, RejectCoinState,
-    RejectPuzzleSolution, RejectPuzzleState, RejectStateReason, RequestChildren, RequestCoinState,
+    Bytes, Bytes32, ChiaProtocolMessage, CoinState, CoinStateUpdate, FeeEstimate, FeeEstimateGroup,
+    FeeRate, Message, NewPeakWallet, ProtocolMessageTypes, PuzzleSolutionResponse,
+    RegisterForCoinUpdates, RegisterForPhUpdates, RejectCoinState, RejectPuzzleSolution,
+    RejectPuzzleState, RejectStateReason, RequestChildren, RequestCoinState, RequestFeeEstimates,
     RequestPuzzleSolution, RequestPuzzleState, RequestRemoveCoinSubscriptions,
-    RequestRemovePuzzleSubscriptions, RespondChildren, RespondCoinState, RespondPuzzleSolution,
-    RespondPuzzleState, RespondRemoveCoinSubscriptions, RespondRemovePuzzleSubscriptions,
-    RespondToCoinUpdates, RespondToPhUpdates, SendTransaction, SpendBundle, TransactionAck,
+    RequestRemovePuzzleSubscriptions, RespondChildren, RespondCoinState, RespondFeeEstimates,
+    RespondPuzzleSolution, RespondPuzzleState, RespondRemoveCoinSubscriptions,
+    RespondRemovePuzzleSubscriptions, RespondToCoinUpdates, RespondToPhUpdates, SendTransaction,
+    SpendBundle, TransactionAck,
 };
 use chia_traits::Streamable;
 use clvmr::NodePtr;
@@ -147,8 +149,7 @@ async fn handle_message(
         }
         ProtocolMessageTypes::RequestPuzzleSolution => {
             let request = RequestPuzzleSolution::from_bytes(&request.data)?;
-            let response = request_puzzle_solution(&request, &simulator)?;
-            (ProtocolMessageTypes::RespondPuzzleSolution, response)
+            request_puzzle_solution(&request, &simulator)?
         }
         ProtocolMessageTypes::RequestChildren => {
             let request = RequestChildren::from_bytes(&request.data)?;
@@ -158,14 +159,12 @@ async fn handle_message(
         ProtocolMessageTypes::RequestCoinState => {
             let request = RequestCoinState::from_bytes(&request.data)?;
             let subscriptions = subscriptions.lock().await;
-            let response = request_coin_state(addr, request, config, &simulator, subscriptions)?;
-            (ProtocolMessageTypes::RespondCoinState, response)
+            request_coin_state(addr, request, config, &simulator, subscriptions)?
         }
         ProtocolMessageTypes::RequestPuzzleState => {
             let request = RequestPuzzleState::from_bytes(&request.data)?;
             let subscriptions = subscriptions.lock().await;
-            let response = request_puzzle_state(addr, request, config, &simulator, subscriptions)?;
-            (ProtocolMessageTypes::RespondPuzzleState, response)
+            request_puzzle_state(addr, request, config, &simulator, subscriptions)?
         }
         ProtocolMessageTypes::RequestRemoveCoinSubscriptions => {
             let request = RequestRemoveCoinSubscriptions::from_bytes(&request.data)?;
@@ -185,6 +184,11 @@ async fn handle_message(
                 response,
             )
         }
+        ProtocolMessageTypes::RequestFeeEstimates => {
+            let request = RequestFeeEstimates::from_bytes(&request.data)?;
+            let response = request_fee_estimates(&request, config)?;
+            (ProtocolMessageTypes::RespondFeeEstimates, response)
+        }
         message_type => {
             return Err(PeerSimulatorError::UnsupportedMessage(message_type));
         }
@@ -389,16 +393,22 @@ fn register_for_ph_updates(
     .into())
 }
 
+// Requests which can be rejected respond with a different message type depending on the outcome.
+fn encode<T>(body: &T) -> Result<(ProtocolMessageTypes, Bytes), PeerSimulatorError>
+where
+    T: Streamable + ChiaProtocolMessage,
+{
+    Ok((T::msg_type(), body.to_bytes()?.into()))
+}
+
 fn request_puzzle_solution(
     request: &RequestPuzzleSolution,
     simulator: &MutexGuard<'_, Simulator>,
-) -> Result<Bytes, PeerSimulatorError> {
-    let reject = RejectPuzzleSolution {
+) -> Result<(ProtocolMessageTypes, Bytes), PeerSimulatorError> {
+    let reject = encode(&RejectPuzzleSolution {
         coin_name: request.coin_name,
         height: request.height,
-    }
-    .to_bytes()?
-    .into();
+    })?;
 
     let Some(coin_state) = simulator.coin_state(request.coin_name) else {
         return Ok(reject);
@@ -416,14 +426,12 @@ fn request_puzzle_solution(
         return Ok(reject);
     };
 
-    Ok(RespondPuzzleSolution::new(PuzzleSolutionResponse::new(
+    encode(&RespondPuzzleSolution::new(PuzzleSolutionResponse::new(
         request.coin_name,
         request.height,
         puzzle_reveal,
         solution,
-    ))
-    .to_bytes()?
-    .into())
+    )))
 }
 
 fn request_children(
@@ -435,23 +443,36 @@ fn request_children(
         .into())
 }
 
+fn request_fee_estimates(
+    request: &RequestFeeEstimates,
+    config: &SimulatorConfig,
+) -> Result<Bytes, PeerSimulatorError> {
+    let estimates = request
+        .time_targets
+        .iter()
+        .map(|&time_target| FeeEstimate::new(None, time_target, FeeRate::new(config.fee_rate)))
+        .collect();
+
+    Ok(
+        RespondFeeEstimates::new(FeeEstimateGroup::new(None, estimates))
+            .to_bytes()?
+            .into(),
+    )
+}
+
 fn request_coin_state(
     peer: SocketAddr,
     request: RequestCoinState,
     config: &SimulatorConfig,
     simulator: &MutexGuard<'_, Simulator>,
     mut subscriptions: MutexGuard<'_, Subscriptions>,
-) -> Result<Bytes, PeerSimulatorError> {
+) -> Result<(ProtocolMessageTypes, Bytes), PeerSimulatorError> {
     if let Some(previous_height) = request.previous_height {
         if Some(request.header_hash) != simulator.header_hash_of(previous_height) {
-            return Ok(RejectCoinState::new(RejectStateReason::Reorg)
-                .to_bytes()?
-                .into());
+            return encode(&RejectCoinState::new(RejectStateReason::Reorg));
         }
     } else if request.header_hash != config.constants.genesis_challenge {
-        return Ok(RejectCoinState::new(RejectStateReason::Reorg)
-            .to_bytes()?
-            .into());
+        return encode(&RejectCoinState::new(RejectStateReason::Reorg));
     }
 
     let coin_ids: IndexSet<Bytes32> = request.coin_ids.iter().copied().collect();
@@ -459,11 +480,9 @@ fn request_coin_state(
     let subscription_count = subscriptions.subscription_count(peer);
 
     if subscription_count + coin_ids.len() > config.max_subscriptions && request.subscribe {
-        return Ok(
-            RejectCoinState::new(RejectStateReason::ExceededSubscriptionLimit)
-                .to_bytes()?
-                .into(),
-        );
+        return encode(&RejectCoinState::new(
+            RejectStateReason::ExceededSubscriptionLimit,
+        ));
     }
 
     let coin_states: Vec<CoinState> = simulator
@@ -481,12 +500,10 @@ fn request_coin_state(
         subscriptions.add_coin_subscriptions(peer, coin_ids);
     }
 
-    Ok(RespondCoinState {
+    encode(&RespondCoinState {
         coin_ids: request.coin_ids,
         coin_states,
-    }
-    .to_bytes()?
-    .into())
+    })
 }
 
 fn request_puzzle_state(
@@ -495,17 +512,13 @@ fn request_puzzle_state(
     config: &SimulatorConfig,
     simulator: &MutexGuard<'_, Simulator>,
     mut subscriptions: MutexGuard<'_, Subscriptions>,
-) -> Result<Bytes, PeerSimulatorError> {
+) -> Result<(ProtocolMessageTypes, Bytes), PeerSimulatorError> {
     if let Some(previous_height) = request.previous_height {
         if Some(request.header_hash) != simulator.header_hash_of(previous_height) {
-            return Ok(RejectPuzzleState::new(RejectStateReason::Reorg)
-                .to_bytes()?
-                .into());
+            return encode(&RejectPuzzleState::new(RejectStateReason::Reorg));
         }
     } else if request.header_hash != config.constants.genesis_challenge {
-        return Ok(RejectPuzzleState::new(RejectStateReason::Reorg)
-            .to_bytes()?
-            .into());
+        return encode(&RejectPuzzleState::new(RejectStateReason::Reorg));
     }
 
     let puzzle_hashes: IndexSet<Bytes32> = request.puzzle_hashes.iter().copied().collect();
@@ -515,11 +528,9 @@ fn request_puzzle_state(
     if subscription_count + puzzle_hashes.len() > config.max_subscriptions
         && request.subscribe_when_finished
     {
-        return Ok(
-            RejectPuzzleState::new(RejectStateReason::ExceededSubscriptionLimit)
-                .to_bytes()?
-                .into(),
-        );
+        return encode(&RejectPuzzleState::new(
+            RejectStateReason::ExceededSubscriptionLimit,
+        ));
     }
 
     let puzzle_hashes: IndexSet<Bytes32> = request.puzzle_hashes.iter().copied().collect();
@@ -565,17 +576,17 @@ fn request_puzzle_state(
         subscriptions.add_puzzle_subscriptions(peer, puzzle_hashes);
     }
 
-    let height = next_height.unwrap_or(simulator.height());
+    // Like the full node, an unfinished response is as of the height before the next coin state,
+    // since the coin states at the next height haven't been included yet.
+    let height = next_height.map_or(simulator.height(), |height| height.saturating_sub(1));
 
-    Ok(RespondPuzzleState {
+    encode(&RespondPuzzleState {
         height,
         header_hash: simulator.header_hash_of(height).unwrap(),
         puzzle_hashes: request.puzzle_hashes,
         coin_states,
         is_finished: next_height.is_none(),
-    }
-    .to_bytes()?
-    .into())
+    })
 }
 
 fn request_remove_coin_subscriptions(
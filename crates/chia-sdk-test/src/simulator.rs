@@ -60,6 +60,16 @@ impl Simulator {
         self.header_hashes.get(height as usize).copied()
     }
 
+    /// Replaces the header hash of every block after the fork height, as if the chain had been
+    /// reorged onto blocks with the same contents.
+    pub fn reorg(&mut self, fork_height: u32) {
+        for header_hash in self.header_hashes.iter_mut().skip(fork_height as usize + 1) {
+            let mut bytes = [0; 32];
+            self.rng.fill(&mut bytes);
+            *header_hash = bytes.into();
+        }
+    }
+
     pub fn insert_coin(&mut self, coin: Coin) {
         let coin_state = CoinState::new(coin, None, Some(self.height));
         self.coin_states.insert(coin.coin_id(), coin_state);
@@ -1,9 +1,11 @@
 mod condition;
 mod conditions;
 mod constants;
+mod merkle_set;
 mod run_puzzle;
 
 pub use condition::*;
 pub use conditions::*;
 pub use constants::*;
+pub use merkle_set::*;
 pub use run_puzzle::*;
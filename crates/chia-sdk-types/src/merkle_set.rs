@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+
+use chia_consensus::merkle_set::compute_merkle_set_root;
+use chia_protocol::{Bytes32, Coin};
+use clvmr::sha2::Sha256;
+
+/// Hashes the coin ids which share a puzzle hash, as they are committed to in a block's additions root.
+pub fn hash_coin_ids(coin_ids: &[Bytes32]) -> Bytes32 {
+    let mut hasher = Sha256::new();
+
+    if let [coin_id] = coin_ids {
+        hasher.update(coin_id);
+    } else {
+        let mut coin_ids = coin_ids.to_vec();
+        coin_ids.sort_unstable_by(|a, b| b.cmp(a));
+
+        for coin_id in coin_ids {
+            hasher.update(coin_id);
+        }
+    }
+
+    hasher.finalize().into()
+}
+
+/// Computes the additions root of a block. Each puzzle hash is included in the Merkle set,
+/// along with the hash of the ids of the coins created with it.
+pub fn additions_root(additions: &[Coin]) -> Bytes32 {
+    let mut groups = BTreeMap::<Bytes32, Vec<Bytes32>>::new();
+
+    for coin in additions {
+        groups
+            .entry(coin.puzzle_hash)
+            .or_default()
+            .push(coin.coin_id());
+    }
+
+    let mut leafs: Vec<[u8; 32]> = groups
+        .into_iter()
+        .flat_map(|(puzzle_hash, coin_ids)| {
+            [puzzle_hash.to_bytes(), hash_coin_ids(&coin_ids).to_bytes()]
+        })
+        .collect();
+
+    compute_merkle_set_root(&mut leafs).into()
+}
+
+/// Computes the removals root of a block, which is the Merkle set of the spent coin ids.
+pub fn removals_root(coin_ids: &[Bytes32]) -> Bytes32 {
+    let mut leafs: Vec<[u8; 32]> = coin_ids.iter().map(|coin_id| coin_id.to_bytes()).collect();
+    compute_merkle_set_root(&mut leafs).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use chia_consensus::merkle_tree::{validate_merkle_proof, MerkleSet};
+
+    use super::*;
+
+    #[test]
+    fn test_hash_coin_ids() {
+        let a = Bytes32::new([1; 32]);
+        let b = Bytes32::new([2; 32]);
+
+        let mut hasher = Sha256::new();
+        hasher.update(a);
+        assert_eq!(hash_coin_ids(&[a]), Bytes32::new(hasher.finalize()));
+
+        // The order of the coin ids doesn't matter.
+        assert_eq!(hash_coin_ids(&[a, b]), hash_coin_ids(&[b, a]));
+    }
+
+    #[test]
+    fn test_additions_root() {
+        let a = Coin::new(Bytes32::new([1; 32]), Bytes32::new([3; 32]), 1);
+        let b = Coin::new(Bytes32::new([2; 32]), Bytes32::new([3; 32]), 2);
+
+        let mut leafs = [
+            a.puzzle_hash.to_bytes(),
+            hash_coin_ids(&[a.coin_id(), b.coin_id()]).to_bytes(),
+        ];
+        let set = MerkleSet::from_leafs(&mut leafs);
+        let root = additions_root(&[b, a]);
+
+        assert_eq!(root, Bytes32::new(set.get_root()));
+
+        let (included, proof) = set.generate_proof(&a.puzzle_hash.to_bytes()).unwrap();
+        assert!(included);
+        assert!(
+            validate_merkle_proof(&proof, &a.puzzle_hash.to_bytes(), &root.to_bytes()).unwrap()
+        );
+    }
+}
@@ -1,10 +1,10 @@
 use chia_bls::PublicKey;
-use chia_protocol::{Coin, CoinSpend};
-use chia_sdk_driver::{DriverError, Spend, SpendContext};
+use chia_protocol::Coin;
+use chia_sdk_driver::{DriverError, Layer, Spend, SpendContext, SpendWithConditions};
 use chia_sdk_test::{test_secret_key, Simulator};
 use chia_sdk_types::Conditions;
 use clvm_traits::{FromClvm, ToClvm};
-use clvm_utils::{CurriedProgram, ToTreeHash, TreeHash};
+use clvm_utils::{ToTreeHash, TreeHash};
 use clvmr::NodePtr;
 use hex_literal::hex;
 
@@ -32,20 +32,6 @@ pub struct CustomArgs {
     pub public_key: PublicKey,
 }
 
-impl CustomArgs {
-    pub fn new(public_key: PublicKey) -> Self {
-        Self { public_key }
-    }
-
-    pub fn curry_tree_hash(public_key: PublicKey) -> TreeHash {
-        CurriedProgram {
-            program: CUSTOM_P2_PUZZLE_HASH,
-            args: CustomArgs::new(public_key),
-        }
-        .tree_hash()
-    }
-}
-
 // And the solution is just a list of conditions.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ToClvm, FromClvm)]
 #[clvm(list)]
@@ -53,61 +39,29 @@ pub struct CustomSolution<T> {
     pub conditions: T,
 }
 
-// For convenience, we can add a way to allocate our puzzle on the `SpendContext`.
-pub trait CustomExt {
-    fn custom_puzzle(&mut self) -> Result<NodePtr, DriverError>;
-    fn spend_custom_coin(
-        &mut self,
-        coin: Coin,
-        public_key: PublicKey,
-        conditions: Conditions,
-    ) -> Result<(), DriverError>;
-}
-
-impl CustomExt for SpendContext {
-    fn custom_puzzle(&mut self) -> Result<NodePtr, DriverError> {
-        self.puzzle(CUSTOM_P2_PUZZLE_HASH, &CUSTOM_P2_PUZZLE)
-    }
-
-    fn spend_custom_coin(
-        &mut self,
-        coin: Coin,
-        public_key: PublicKey,
-        conditions: Conditions,
-    ) -> Result<(), DriverError> {
-        let spend = conditions.custom_spend(self, public_key)?;
-        let puzzle_reveal = self.serialize(&spend.puzzle)?;
-        let solution = self.serialize(&spend.solution)?;
-        self.insert(CoinSpend::new(coin, puzzle_reveal, solution));
-        Ok(())
-    }
-}
-
-// Let's extend the `Conditions` struct to generate spends for our new p2 puzzle.
-pub trait CustomSpend {
-    fn custom_spend(
-        self,
-        ctx: &mut SpendContext,
-        public_key: PublicKey,
-    ) -> Result<Spend, DriverError>;
+// Now we can derive a `Layer` for the puzzle, which knows how to construct and parse it.
+// The fields of the layer are curried into the puzzle, so they must match the `CustomArgs`.
+// This also implements `ToTreeHash`, to calculate the puzzle hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Layer)]
+#[layer(
+    puzzle = CUSTOM_P2_PUZZLE,
+    puzzle_hash = CUSTOM_P2_PUZZLE_HASH,
+    args = CustomArgs,
+    solution = CustomSolution<NodePtr>
+)]
+pub struct CustomLayer {
+    pub public_key: PublicKey,
 }
 
-impl CustomSpend for Conditions {
-    fn custom_spend(
-        self,
+// Finally, we can allow the layer to be spent with a list of conditions.
+impl SpendWithConditions for CustomLayer {
+    fn spend_with_conditions(
+        &self,
         ctx: &mut SpendContext,
-        public_key: PublicKey,
+        conditions: Conditions,
     ) -> Result<Spend, DriverError> {
-        let custom_puzzle = ctx.custom_puzzle()?;
-
-        let puzzle = ctx.alloc(&CurriedProgram {
-            program: custom_puzzle,
-            args: CustomArgs::new(public_key),
-        })?;
-
-        let solution = ctx.alloc(&CustomSolution { conditions: self })?;
-
-        Ok(Spend::new(puzzle, solution))
+        let conditions = ctx.alloc(&conditions)?;
+        self.construct_spend(ctx, CustomSolution { conditions })
     }
 }
 
@@ -118,7 +72,8 @@ fn main() -> anyhow::Result<()> {
     // Setup the key, puzzle hash, and mint a coin.
     let sk = test_secret_key()?;
     let pk = sk.public_key();
-    let puzzle_hash = CustomArgs::curry_tree_hash(pk).into();
+    let custom = CustomLayer { public_key: pk };
+    let puzzle_hash = custom.tree_hash().into();
     let coin = sim.new_coin(puzzle_hash, 1_000);
 
     println!("Minted custom test coin with coin id {}", coin.coin_id());
@@ -130,7 +85,8 @@ fn main() -> anyhow::Result<()> {
         .create_coin(puzzle_hash, 900, Vec::new())
         .reserve_fee(100);
 
-    ctx.spend_custom_coin(coin, pk, conditions)?;
+    let spend = custom.spend_with_conditions(ctx, conditions)?;
+    ctx.spend(coin, spend)?;
 
     let new_coin = Coin::new(coin.coin_id(), puzzle_hash, 900);
 